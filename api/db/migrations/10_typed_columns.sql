@@ -0,0 +1,45 @@
+-- every column keeps exactly one of text_value, numeric_value or date_value
+-- instead of a serialized ColumnValue json so values are indexed and compared natively
+ALTER TABLE columns ADD COLUMN IF NOT EXISTS text_value TEXT;
+ALTER TABLE columns ADD COLUMN IF NOT EXISTS numeric_value NUMERIC;
+ALTER TABLE columns ADD COLUMN IF NOT EXISTS date_value DATE;
+
+ALTER TABLE columns DISABLE TRIGGER columns_row_version_trigger;
+
+UPDATE columns SET
+  text_value = value->>'String',
+  numeric_value = (COALESCE(value->>'Decimal', value->>'Float'))::numeric,
+  date_value = (value->>'Date')::date;
+
+ALTER TABLE columns ENABLE TRIGGER columns_row_version_trigger;
+
+ALTER TABLE columns DROP COLUMN IF EXISTS value_normalized;
+ALTER TABLE columns DROP COLUMN IF EXISTS value;
+
+ALTER TABLE columns ADD CONSTRAINT columns_single_value_check
+  CHECK (num_nonnulls(text_value, numeric_value, date_value) = 1);
+
+-- to_char is only stable because of the locale, the fixed date pattern makes it safe here
+CREATE OR REPLACE FUNCTION column_value_text(t TEXT, n NUMERIC, d DATE) RETURNS TEXT AS $$
+  SELECT COALESCE(t, to_char(d, 'YYYY-MM-DD'), n::text)
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+
+ALTER TABLE columns ADD COLUMN value_normalized TEXT
+  GENERATED ALWAYS AS (
+    normalize_arabic(column_value_text(text_value, numeric_value, date_value))
+  ) STORED;
+
+CREATE INDEX IF NOT EXISTS columns_header_value_normalized_idx
+  ON columns(header_name, value_normalized);
+CREATE INDEX IF NOT EXISTS columns_header_numeric_value_idx
+  ON columns(header_name, numeric_value) WHERE numeric_value IS NOT NULL;
+CREATE INDEX IF NOT EXISTS columns_header_date_value_idx
+  ON columns(header_name, date_value) WHERE date_value IS NOT NULL;
+
+DO $$
+BEGIN
+  CREATE INDEX IF NOT EXISTS columns_value_normalized_trgm_idx
+    ON columns USING GIN (value_normalized gin_trgm_ops);
+EXCEPTION WHEN OTHERS THEN
+  NULL;
+END $$;
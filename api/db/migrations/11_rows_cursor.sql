@@ -0,0 +1,11 @@
+-- rows are paged by (inserted_at, id) instead of OFFSET over the day-precision
+-- insert_date so concurrent inserts can not shift or repeat a page
+ALTER TABLE rows ADD COLUMN IF NOT EXISTS inserted_at TIMESTAMP;
+
+UPDATE rows SET inserted_at = COALESCE(insert_date::timestamp, CURRENT_TIMESTAMP::timestamp)
+  WHERE inserted_at IS NULL;
+
+ALTER TABLE rows ALTER COLUMN inserted_at SET DEFAULT clock_timestamp();
+ALTER TABLE rows ALTER COLUMN inserted_at SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS rows_sheet_inserted_at_idx ON rows(sheet_id, inserted_at, id);
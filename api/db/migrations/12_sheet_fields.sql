@@ -0,0 +1,42 @@
+-- sheet level fields used to live in a row sharing the id of its sheet
+CREATE TABLE IF NOT EXISTS sheet_fields (
+  sheet_id UUID NOT NULL,
+  header_name VARCHAR(80) NOT NULL,
+  text_value TEXT,
+  numeric_value NUMERIC,
+  date_value DATE,
+  PRIMARY KEY (sheet_id, header_name),
+  FOREIGN KEY (sheet_id) REFERENCES sheets (id) ON DELETE CASCADE,
+  CHECK (num_nonnulls(text_value, numeric_value, date_value) = 1)
+);
+
+INSERT INTO sheet_fields(sheet_id,header_name,text_value,numeric_value,date_value)
+  SELECT r.sheet_id,c.header_name,c.text_value,c.numeric_value,c.date_value
+  FROM rows r JOIN columns c ON c.row_id = r.id
+  WHERE r.id = r.sheet_id
+  ON CONFLICT DO NOTHING;
+
+ALTER TABLE columns DISABLE TRIGGER columns_row_version_trigger;
+
+DELETE FROM columns c USING rows r WHERE c.row_id = r.id AND r.id = r.sheet_id;
+
+ALTER TABLE columns ENABLE TRIGGER columns_row_version_trigger;
+
+DELETE FROM rows WHERE id = sheet_id;
+
+-- fields are guarded by the sheet version like the sheet name
+CREATE OR REPLACE FUNCTION bump_sheet_fields_version() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    UPDATE sheets SET version = version + 1 WHERE id = OLD.sheet_id;
+    RETURN OLD;
+  END IF;
+  UPDATE sheets SET version = version + 1 WHERE id = NEW.sheet_id;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS sheet_fields_version_trigger ON sheet_fields;
+CREATE TRIGGER sheet_fields_version_trigger
+  AFTER INSERT OR UPDATE OR DELETE ON sheet_fields
+  FOR EACH ROW EXECUTE FUNCTION bump_sheet_fields_version();
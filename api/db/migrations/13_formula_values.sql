@@ -0,0 +1,5 @@
+-- a calculated column may now be any formula value such as ROUND(...), the
+-- stored bare operation trees get the Operation tag of the formula value
+UPDATE sheet_type_calculated_columns
+  SET operation = json_build_object('Operation', operation)
+  WHERE json_typeof(operation) = 'object' AND operation->'op' IS NOT NULL;
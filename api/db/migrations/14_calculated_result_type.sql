@@ -0,0 +1,4 @@
+-- calculated columns may produce text and dates, existing ones stay decimal
+ALTER TABLE sheet_type_calculated_columns
+  ADD COLUMN IF NOT EXISTS result_type VARCHAR(10) NOT NULL DEFAULT 'decimal'
+  CHECK (result_type IN ('decimal','string','date'));
@@ -0,0 +1,3 @@
+-- sheet types opt in to failing formulas that read a missing column
+ALTER TABLE sheet_types
+  ADD COLUMN IF NOT EXISTS strict_formulas BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS users (
+  id UUID PRIMARY KEY NOT NULL,
+  username VARCHAR(80) NOT NULL UNIQUE,
+  password VARCHAR(80) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+  token UUID PRIMARY KEY NOT NULL,
+  user_id UUID NOT NULL,
+  insert_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions(user_id);
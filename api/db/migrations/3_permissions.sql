@@ -0,0 +1,13 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE users SET is_admin = TRUE
+  WHERE id = (SELECT id FROM users ORDER BY username LIMIT 1)
+  AND NOT EXISTS (SELECT 1 FROM users WHERE is_admin);
+
+CREATE TABLE IF NOT EXISTS permissions (
+  user_id UUID NOT NULL,
+  sheet_type_name VARCHAR(80) NOT NULL,
+  role VARCHAR(10) NOT NULL CHECK (role IN ('viewer','editor','admin')),
+  PRIMARY KEY(user_id,sheet_type_name),
+  FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+  id UUID PRIMARY KEY NOT NULL,
+  sheet_id UUID NOT NULL,
+  row_id UUID,
+  header_name VARCHAR(80),
+  action VARCHAR(20) NOT NULL,
+  old_value JSON,
+  new_value JSON,
+  -- no foreign key to users, the append only trigger would reject the update
+  -- deleting a user runs, the username is copied so the history keeps the actor
+  user_id UUID,
+  username VARCHAR(80),
+  insert_time TIMESTAMP NOT NULL DEFAULT clock_timestamp()
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_sheet_id_idx ON audit_log(sheet_id,insert_time,id);
+CREATE INDEX IF NOT EXISTS audit_log_insert_time_idx ON audit_log(insert_time);
+
+CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_log is append only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS audit_log_append_only_trigger ON audit_log;
+CREATE TRIGGER audit_log_append_only_trigger
+  BEFORE UPDATE OR DELETE ON audit_log
+  FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
@@ -0,0 +1,3 @@
+ALTER TABLE sheets ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP;
+
+CREATE INDEX IF NOT EXISTS sheets_deleted_at_idx ON sheets(deleted_at);
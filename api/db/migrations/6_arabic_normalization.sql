@@ -0,0 +1,33 @@
+-- strips tashkeel (U+064B..U+0652, U+0670) and tatweel (U+0640),
+-- folds alef variants to ا, ة to ه, ى and ی to ي and arabic-indic digits to 0-9
+CREATE OR REPLACE FUNCTION normalize_arabic(input TEXT) RETURNS TEXT AS $$
+  SELECT lower(
+    translate(
+      regexp_replace(COALESCE(input, ''), '[ً-ْٰـ]', '', 'g'),
+      'أإآٱةىی٠١٢٣٤٥٦٧٨٩۰۱۲۳۴۵۶۷۸۹',
+      'ااااهيي01234567890123456789'
+    )
+  )
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+
+ALTER TABLE sheets ADD COLUMN IF NOT EXISTS sheet_name_normalized TEXT
+  GENERATED ALWAYS AS (normalize_arabic(sheet_name)) STORED;
+
+ALTER TABLE columns ADD COLUMN IF NOT EXISTS value_normalized TEXT
+  GENERATED ALWAYS AS (
+    normalize_arabic(COALESCE(value->>'String', value->>'Date', value->>'Float'))
+  ) STORED;
+
+CREATE INDEX IF NOT EXISTS columns_header_value_normalized_idx
+  ON columns(header_name, value_normalized);
+
+DO $$
+BEGIN
+  CREATE EXTENSION IF NOT EXISTS pg_trgm;
+  CREATE INDEX IF NOT EXISTS sheets_name_normalized_trgm_idx
+    ON sheets USING GIN (sheet_name_normalized gin_trgm_ops);
+  CREATE INDEX IF NOT EXISTS columns_value_normalized_trgm_idx
+    ON columns USING GIN (value_normalized gin_trgm_ops);
+EXCEPTION WHEN OTHERS THEN
+  CREATE INDEX IF NOT EXISTS sheets_name_normalized_idx ON sheets(sheet_name_normalized);
+END $$;
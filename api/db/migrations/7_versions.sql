@@ -0,0 +1,32 @@
+ALTER TABLE sheets ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE rows ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
+
+CREATE OR REPLACE FUNCTION bump_sheet_version() RETURNS trigger AS $$
+BEGIN
+  NEW.version := OLD.version + 1;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS sheets_version_trigger ON sheets;
+CREATE TRIGGER sheets_version_trigger
+  BEFORE UPDATE ON sheets
+  FOR EACH ROW
+  WHEN (OLD.sheet_name IS DISTINCT FROM NEW.sheet_name)
+  EXECUTE FUNCTION bump_sheet_version();
+
+CREATE OR REPLACE FUNCTION bump_row_version() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    UPDATE rows SET version = version + 1 WHERE id = OLD.row_id;
+    RETURN OLD;
+  END IF;
+  UPDATE rows SET version = version + 1 WHERE id = NEW.row_id;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS columns_row_version_trigger ON columns;
+CREATE TRIGGER columns_row_version_trigger
+  AFTER INSERT OR UPDATE OR DELETE ON columns
+  FOR EACH ROW EXECUTE FUNCTION bump_row_version();
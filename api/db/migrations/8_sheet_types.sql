@@ -0,0 +1,49 @@
+CREATE TABLE IF NOT EXISTS sheet_types (
+  name VARCHAR(80) PRIMARY KEY NOT NULL,
+  priorities VARCHAR(80)[] NOT NULL DEFAULT '{}',
+  import_main_entry TEXT[] NOT NULL DEFAULT '{}',
+  import_repeated_entry TEXT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS sheet_type_columns (
+  type_name VARCHAR(80) NOT NULL,
+  position INT NOT NULL,
+  header VARCHAR(80) NOT NULL,
+  value_type VARCHAR(10) NOT NULL CHECK (value_type IN ('string','float','date')),
+  is_completable BOOLEAN NOT NULL DEFAULT FALSE,
+  PRIMARY KEY(type_name,header),
+  FOREIGN KEY (type_name) REFERENCES sheet_types (name) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS sheet_type_calculated_columns (
+  type_name VARCHAR(80) NOT NULL,
+  position INT NOT NULL,
+  header VARCHAR(80) NOT NULL,
+  operation JSON NOT NULL,
+  PRIMARY KEY(type_name,header),
+  FOREIGN KEY (type_name) REFERENCES sheet_types (name) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS sheet_type_import_mappings (
+  type_name VARCHAR(80) NOT NULL,
+  section VARCHAR(10) NOT NULL CHECK (section IN ('unique','repeated','primary')),
+  header VARCHAR(80) NOT NULL,
+  path TEXT[] NOT NULL,
+  PRIMARY KEY(type_name,section,header),
+  FOREIGN KEY (type_name) REFERENCES sheet_types (name) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS sheet_type_row_identities (
+  type_name VARCHAR(80) PRIMARY KEY NOT NULL,
+  id_header VARCHAR(80) NOT NULL,
+  FOREIGN KEY (type_name) REFERENCES sheet_types (name) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS sheet_type_identity_ops (
+  type_name VARCHAR(80) NOT NULL,
+  header VARCHAR(80) NOT NULL,
+  op VARCHAR(10) NOT NULL CHECK (op IN ('sum','prod','max','min','nth')),
+  nth INT,
+  PRIMARY KEY(type_name,header),
+  FOREIGN KEY (type_name) REFERENCES sheet_type_row_identities (type_name) ON DELETE CASCADE
+);
@@ -0,0 +1,32 @@
+-- numbers were stored as {"Float": 1.5} json floats, they become exact
+-- decimals stored as text {"Decimal": "1.5"}, the audit log is append only
+-- so its old entries keep the Float tag which is still accepted when reading
+ALTER TABLE columns DROP COLUMN IF EXISTS value_normalized;
+
+ALTER TABLE columns DISABLE TRIGGER columns_row_version_trigger;
+
+UPDATE columns SET value = json_build_object('Decimal', (value->>'Float')::numeric::text)
+  WHERE value->>'Float' IS NOT NULL;
+
+ALTER TABLE columns ENABLE TRIGGER columns_row_version_trigger;
+
+ALTER TABLE columns ADD COLUMN value_normalized TEXT
+  GENERATED ALWAYS AS (
+    normalize_arabic(COALESCE(value->>'String', value->>'Date', value->>'Decimal'))
+  ) STORED;
+
+CREATE INDEX IF NOT EXISTS columns_header_value_normalized_idx
+  ON columns(header_name, value_normalized);
+
+DO $$
+BEGIN
+  CREATE INDEX IF NOT EXISTS columns_value_normalized_trgm_idx
+    ON columns USING GIN (value_normalized gin_trgm_ops);
+EXCEPTION WHEN OTHERS THEN
+  NULL;
+END $$;
+
+ALTER TABLE sheet_type_columns DROP CONSTRAINT IF EXISTS sheet_type_columns_value_type_check;
+UPDATE sheet_type_columns SET value_type = 'decimal' WHERE value_type = 'float';
+ALTER TABLE sheet_type_columns ADD CONSTRAINT sheet_type_columns_value_type_check
+  CHECK (value_type IN ('string','decimal','date'));
@@ -18,3 +18,18 @@ pub fn get_configs_server() -> (String, u16) {
 pub fn get_config_postgres_url() -> String {
     env::var("DATABASE_URL").expect("unvalid db url key")
 }
+
+pub fn get_config_admin() -> (String, String) {
+    let username = env::var("ADMIN_NAME").expect("invalid admin name key");
+    let password = env::var("ADMIN_PASSWORD")
+        .ok()
+        .filter(|password| !password.trim().is_empty())
+        .expect("ADMIN_PASSWORD must be set to create the first admin");
+    (username, password)
+}
+
+pub fn get_config_sheet_types_seed() -> Option<String> {
+    env::var("SHEET_TYPES_SEED")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+}
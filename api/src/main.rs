@@ -3,15 +3,23 @@ mod service;
 
 use service::*;
 
-use config::{get_config_postgres_url, get_configs_server, set_debug_configs};
+use client_models::Config;
+use config::{
+    get_config_admin, get_config_postgres_url, get_config_sheet_types_seed, get_configs_server,
+    set_debug_configs,
+};
 use dotenv::dotenv;
+use models::Credentials;
 
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
+use tokio::sync::broadcast;
 
-use sqlx::{postgres::PgPoolOptions, query, Pool, Postgres};
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use std::io::Cursor;
 
 pub struct AppState {
     pub db: Pool<Postgres>,
+    pub events: events::Events,
 }
 
 #[actix_web::main]
@@ -21,24 +29,24 @@ async fn main() -> std::io::Result<()> {
 
     let db_pool = connect_db_pool().await;
 
-    query!(
-        r#"
-          INSERT INTO rows(id,sheet_id) select s.id,s.id from sheets s 
-          	where s.id not in (select r.id from "rows" r 
-          		where r.id in (select id from sheets));"#,
-    )
-    .fetch_all(&db_pool)
-    .await
-    .expect("primary rows prepare failed");
+    let (events, _) = broadcast::channel(events::EVENTS_CAPACITY);
+
+    create_default_admin(&db_pool, &events).await;
+    seed_sheet_types(&db_pool, &events).await;
 
     HttpServer::new(move || {
         App::new()
             .app_data(Data::new(AppState {
                 db: db_pool.clone(),
+                events: events.clone(),
             }))
             .wrap(Logger::default())
-            .service(sheet::scope())
-            .service(column::scope())
+            .service(auth::scope())
+            .service(sheet::scope().wrap(auth::RequireAuth))
+            .service(column::scope().wrap(auth::RequireAuth))
+            .service(aggregate::scope().wrap(auth::RequireAuth))
+            .service(events::scope().wrap(auth::RequireAuth))
+            .service(sheet_type::scope().wrap(auth::RequireAuth))
     })
     .bind(get_configs_server())?
     .run()
@@ -60,3 +68,38 @@ async fn connect_db_pool() -> Pool<Postgres> {
 
     p
 }
+
+async fn create_default_admin(db_pool: &Pool<Postgres>, events: &events::Events) {
+    let state = AppState {
+        db: db_pool.clone(),
+        events: events.clone(),
+    };
+    let count = auth::users_count(&state)
+        .await
+        .expect("failed to count users");
+    if count != 0 {
+        return;
+    }
+    let (username, password) = get_config_admin();
+    auth::create_user(&state, Credentials { username, password }, true)
+        .await
+        .expect("failed to create default admin");
+}
+
+async fn seed_sheet_types(db_pool: &Pool<Postgres>, events: &events::Events) {
+    let Some(path) = get_config_sheet_types_seed() else {
+        return;
+    };
+    let state = AppState {
+        db: db_pool.clone(),
+        events: events.clone(),
+    };
+    let buf = std::fs::read(&path).expect("failed to read the sheet types seed");
+    let config = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(buf))
+        .expect("the sheet types seed is not cbor")
+        .deserialized::<Config>()
+        .expect("the sheet types seed is not a config");
+    sheet_type::seed_sheet_types(&state, config)
+        .await
+        .expect("failed to seed sheet types");
+}
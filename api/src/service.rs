@@ -1,13 +1,50 @@
 use std::io::Cursor;
 
 use actix_web::web;
-use serde::Deserialize;
+use models::ApiError;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+pub mod aggregate;
+pub mod audit;
+pub mod auth;
 pub mod column;
+pub mod events;
+pub mod schema;
 pub mod sheet;
+pub mod sheet_type;
+#[cfg(test)]
+pub mod testing;
 
-fn extract<'a, T: Deserialize<'a>>(params: web::Bytes) -> Result<T, Box<dyn std::error::Error>> {
-    let params = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(params))
-        .map(|body| body.deserialized::<T>())??;
+fn extract<'a, T: Deserialize<'a>>(params: web::Bytes) -> Result<T, ApiError> {
+    let params = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(params))?
+        .deserialized::<T>()
+        .map_err(|err| ApiError::BadRequest(err.to_string()))?;
     Ok(params)
 }
+
+fn compact<T: Serialize>(value: &T) -> Result<Vec<u8>, ApiError> {
+    let mut buf = vec![];
+    ciborium::ser::into_writer(value, Cursor::new(&mut buf))?;
+    Ok(buf)
+}
+
+fn encode_cursor<T: Serialize>(value: &T) -> Result<String, ApiError> {
+    let cursor = compact(value)?
+        .into_iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(cursor)
+}
+
+fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> Result<T, ApiError> {
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| {
+            cursor
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| ApiError::BadRequest(format!("cursor ({cursor})")))?;
+    extract(web::Bytes::from(bytes))
+}
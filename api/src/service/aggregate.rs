@@ -0,0 +1,192 @@
+use actix_web::{
+    post,
+    web::{self, Data, ReqData},
+    HttpResponse, Scope,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use sqlx::{Postgres, QueryBuilder};
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::{
+    auth::{check_role, Target, User},
+    service::{compact, extract},
+    AppState,
+};
+
+use models::{
+    AggregateGroup, AggregateGrouping, AggregateParams, ApiError, HeaderAggregate, Role, ToSerial,
+};
+
+pub fn scope() -> Scope {
+    web::scope("/aggregate").service(aggregate_columns)
+}
+
+#[post("/")]
+async fn aggregate_columns(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    params: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let params = extract::<AggregateParams>(params)?;
+    let target = Target::SheetType(&params.sheet_type_name);
+    check_role(&state, &user, target, Role::Viewer).await?;
+    let groups = aggregate_by_params(&state, params)
+        .await?
+        .into_iter()
+        .map(|group| group.to_serial())
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().body(compact(&groups)?))
+}
+
+type AggregateRecord = (
+    Option<Uuid>,
+    Option<String>,
+    Option<NaiveDate>,
+    String,
+    i64,
+    BigDecimal,
+    BigDecimal,
+    BigDecimal,
+    BigDecimal,
+);
+
+async fn aggregate_by_params(
+    state: &AppState,
+    params: AggregateParams,
+) -> Result<Vec<AggregateGroup<Uuid>>, ApiError> {
+    let AggregateParams {
+        sheet_type_name,
+        headers,
+        begin,
+        end,
+        sheets_ids,
+        group_by,
+    } = params;
+    if headers.is_empty() {
+        return Err(ApiError::BadRequest(String::from(
+            "no headers to aggregate",
+        )));
+    }
+
+    let group_columns = match group_by {
+        AggregateGrouping::None => "NULL::uuid,NULL::text,NULL::date",
+        AggregateGrouping::Sheet => "s.id,s.sheet_name::text,NULL::date",
+        AggregateGrouping::Month => "NULL::uuid,NULL::text,date_trunc('month',s.insert_date)::date",
+    };
+    let mut builder = QueryBuilder::<Postgres>::new("SELECT ");
+    builder.push(group_columns).push(
+        r#",c.header_name,count(*),
+        sum(c.numeric_value),
+        avg(c.numeric_value),
+        min(c.numeric_value),
+        max(c.numeric_value)
+        FROM columns c
+        JOIN rows r ON r.id = c.row_id
+        JOIN sheets s ON s.id = r.sheet_id
+        WHERE s.deleted_at IS NULL AND c.numeric_value IS NOT NULL AND s.type_name = "#,
+    );
+    builder.push_bind(sheet_type_name);
+    builder
+        .push(" AND c.header_name = ANY(")
+        .push_bind(headers)
+        .push(")");
+    if let Some(begin) = begin {
+        builder.push(" AND s.insert_date >= ").push_bind(begin);
+    }
+    if let Some(end) = end {
+        builder.push(" AND s.insert_date <= ").push_bind(end);
+    }
+    if !sheets_ids.is_empty() {
+        let sheets_ids = sheets_ids
+            .iter()
+            .map(|id| Uuid::from_str(id))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| ApiError::BadRequest(format!("sheet id ({err})")))?;
+        builder
+            .push(" AND s.id = ANY(")
+            .push_bind(sheets_ids)
+            .push(")");
+    }
+    builder.push(" GROUP BY 1,2,3,4 ORDER BY 3,2,1,4");
+    let records = builder
+        .build_query_as::<AggregateRecord>()
+        .fetch_all(&state.db)
+        .await?;
+
+    let mut groups = Vec::<AggregateGroup<Uuid>>::new();
+    for (sheet_id, sheet_name, month, header, count, sum, avg, min, max) in records {
+        let aggregate = HeaderAggregate {
+            header,
+            count,
+            sum,
+            avg,
+            min,
+            max,
+        };
+        match groups.last_mut() {
+            Some(group) if group.sheet_id == sheet_id && group.month == month => {
+                group.aggregates.push(aggregate)
+            }
+            _ => groups.push(AggregateGroup {
+                sheet_id,
+                sheet_name,
+                month,
+                aggregates: vec![aggregate],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, insert_row, insert_sheet, insert_sheet_type};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn aggregates_only_the_requested_sheets(pool: PgPool) {
+        let state = app_state(pool);
+        insert_sheet_type(&state, "type").await;
+        let first = insert_sheet(&state, "type").await;
+        let second = insert_sheet(&state, "type").await;
+        for value in [1, 2] {
+            insert_row(&state, &first, value).await;
+        }
+        insert_row(&state, &second, 10).await;
+
+        let params = AggregateParams {
+            sheet_type_name: "type".to_string(),
+            headers: vec!["value".to_string()],
+            begin: None,
+            end: None,
+            sheets_ids: vec![first.to_serial()],
+            group_by: AggregateGrouping::None,
+        };
+        // the params take the same path as a client request body
+        let params =
+            extract::<AggregateParams>(web::Bytes::from(compact(&params).unwrap())).unwrap();
+        let groups = aggregate_by_params(&state, params).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        let aggregate = &groups[0].aggregates[0];
+        assert_eq!(aggregate.count, 2);
+        assert_eq!(aggregate.sum, BigDecimal::from(3));
+        assert_eq!(aggregate.max, BigDecimal::from(2));
+
+        let params = AggregateParams {
+            sheet_type_name: "type".to_string(),
+            headers: vec!["value".to_string()],
+            begin: None,
+            end: None,
+            sheets_ids: vec![Arc::from("not an id")],
+            group_by: AggregateGrouping::None,
+        };
+        assert!(matches!(
+            aggregate_by_params(&state, params).await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+}
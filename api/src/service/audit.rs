@@ -0,0 +1,207 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, Transaction};
+use std::{str::FromStr, sync::Arc};
+use uuid::Uuid;
+
+use models::{ApiError, AuditAction, AuditEntry, HistoryPage, ToSerial};
+
+use crate::{service::encode_cursor, AppState};
+
+pub struct AuditRecord<'a> {
+    pub user_id: &'a Uuid,
+    pub sheet_id: &'a Uuid,
+    pub row_id: Option<&'a Uuid>,
+    pub header: Option<&'a str>,
+    pub action: AuditAction,
+    pub old_value: Option<serde_json::Value>,
+    pub new_value: Option<serde_json::Value>,
+}
+
+pub async fn record(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    record: AuditRecord<'_>,
+) -> Result<(), ApiError> {
+    let AuditRecord {
+        user_id,
+        sheet_id,
+        row_id,
+        header,
+        action,
+        old_value,
+        new_value,
+    } = record;
+    query!(
+        r#"
+	INSERT INTO audit_log(id,sheet_id,row_id,header_name,action,old_value,new_value,user_id,username)
+	VALUES($1,$2,$3,$4,$5,$6,$7,$8,(SELECT username FROM users WHERE id = $8))"#,
+        Uuid::new_v4(),
+        sheet_id,
+        row_id.copied(),
+        header,
+        action.as_str(),
+        old_value,
+        new_value,
+        user_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HistoryCursor {
+    insert_time: NaiveDateTime,
+    id: Arc<str>,
+}
+
+// the newest entries first, a page ends where the cursor of the next one starts
+pub async fn fetch_sheet_history(
+    state: &AppState,
+    sheet_id: &Uuid,
+    after: Option<HistoryCursor>,
+    limit: i64,
+) -> Result<HistoryPage<Uuid, Arc<str>>, ApiError> {
+    let (after_time, after_id) = match after {
+        Some(HistoryCursor { insert_time, id }) => {
+            let id = Uuid::from_str(&id)
+                .map_err(|err| ApiError::BadRequest(format!("cursor id ({err})")))?;
+            (Some(insert_time), Some(id))
+        }
+        None => (None, None),
+    };
+    let records = query!(
+        r#"
+        SELECT a.id,a.sheet_id,a.row_id,a.header_name,a.action,
+               a.old_value,a.new_value,a.insert_time,a.username
+        FROM audit_log a
+        WHERE a.sheet_id = $1
+          AND ($2::timestamp IS NULL OR (a.insert_time,a.id) < ($2,$3::uuid))
+        ORDER BY a.insert_time DESC,a.id DESC
+        LIMIT $4"#,
+        sheet_id,
+        after_time,
+        after_id,
+        limit,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let mut entries = Vec::new();
+    for record in records {
+        entries.push(AuditEntry {
+            id: record.id,
+            sheet_id: record.sheet_id,
+            row_id: record.row_id,
+            header: record.header_name.map(Arc::from),
+            action: record.action.parse().map_err(ApiError::Internal)?,
+            old_value: record.old_value.map(serde_json::from_value).transpose()?,
+            new_value: record.new_value.map(serde_json::from_value).transpose()?,
+            username: record.username.map(Arc::from),
+            time: record.insert_time,
+        });
+    }
+    let next = match entries.last() {
+        Some(entry) if entries.len() as i64 == limit => {
+            let cursor = HistoryCursor {
+                insert_time: entry.time,
+                id: entry.id.to_serial(),
+            };
+            Some(encode_cursor(&cursor)?)
+        }
+        _ => None,
+    };
+    Ok(HistoryPage { entries, next })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::decode_cursor,
+        testing::{admin_session, app_state, insert_sheet, insert_sheet_type},
+    };
+    use sqlx::PgPool;
+
+    async fn record_renames(state: &AppState, user_id: &Uuid, sheet_id: &Uuid, len: usize) {
+        let mut transaction = state.db.begin().await.unwrap();
+        for i in 0..len {
+            let audit = AuditRecord {
+                user_id,
+                sheet_id,
+                row_id: None,
+                header: None,
+                action: AuditAction::RenameSheet,
+                old_value: None,
+                new_value: Some(serde_json::json!({ "String": format!("name {i}") })),
+            };
+            record(&mut transaction, audit).await.unwrap();
+        }
+        transaction.commit().await.unwrap();
+    }
+
+    async fn user_id(state: &AppState, username: &str) -> Uuid {
+        query!("SELECT id FROM users WHERE username = $1", username)
+            .fetch_one(&state.db)
+            .await
+            .unwrap()
+            .id
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn history_pages_go_from_newest_to_oldest(pool: PgPool) {
+        let state = app_state(pool);
+        insert_sheet_type(&state, "type").await;
+        let sheet_id = insert_sheet(&state, "type").await;
+        admin_session(&state, "editor").await;
+        let editor = user_id(&state, "editor").await;
+        record_renames(&state, &editor, &sheet_id, 5).await;
+
+        let mut entries = Vec::new();
+        let mut after = None;
+        loop {
+            let page = fetch_sheet_history(&state, &sheet_id, after, 2)
+                .await
+                .unwrap();
+            assert!(page.entries.len() <= 2);
+            entries.extend(page.entries);
+            let Some(next) = page.next else {
+                break;
+            };
+            after = Some(decode_cursor::<HistoryCursor>(&next).unwrap());
+        }
+        let names = entries
+            .iter()
+            .map(|entry| entry.new_value.clone().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["name 4", "name 3", "name 2", "name 1", "name 0"].map(String::from)
+        );
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn deleting_a_user_keeps_its_history(pool: PgPool) {
+        let state = app_state(pool);
+        insert_sheet_type(&state, "type").await;
+        let sheet_id = insert_sheet(&state, "type").await;
+        admin_session(&state, "editor").await;
+        let editor = user_id(&state, "editor").await;
+        record_renames(&state, &editor, &sheet_id, 1).await;
+
+        query!("DELETE FROM sessions WHERE user_id = $1", editor)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        query!("DELETE FROM users WHERE id = $1", editor)
+            .execute(&state.db)
+            .await
+            .unwrap();
+
+        let page = fetch_sheet_history(&state, &sheet_id, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].username.as_deref(), Some("editor"));
+        assert!(page.next.is_none());
+    }
+}
@@ -0,0 +1,482 @@
+use crate::{
+    service::{compact, extract},
+    AppState,
+};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    get,
+    http::header,
+    post, put,
+    web::{self, Data, ReqData},
+    HttpMessage, HttpRequest, HttpResponse, ResponseError, Scope,
+};
+use sqlx::query;
+use std::{
+    collections::HashMap,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    str::FromStr,
+};
+use uuid::Uuid;
+
+use models::{ApiError, Credentials, Name, Permissions, Role, RoleGrant, ToSerial};
+
+pub fn scope() -> Scope {
+    web::scope("/auth")
+        .service(login)
+        .service(logout)
+        .service(
+            web::scope("/me")
+                .wrap(RequireAuth)
+                .service(me)
+                .service(my_permissions),
+        )
+        .service(
+            web::scope("/admin")
+                .wrap(RequireAuth)
+                .service(add_user)
+                .service(grant_role),
+        )
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    pub is_admin: bool,
+}
+
+/// sessions older than this are rejected and cleaned up on the next login
+const SESSION_MAX_AGE_HOURS: i32 = 24 * 7;
+
+pub enum Target<'a> {
+    SheetType(&'a str),
+    /// a live sheet, trashed sheets are reported as not found
+    Sheet(&'a Uuid),
+    /// a sheet in the trash, only reachable to restore or purge it
+    TrashedSheet(&'a Uuid),
+}
+
+#[post("/login")]
+async fn login(state: Data<AppState>, credentials: web::Bytes) -> Result<HttpResponse, ApiError> {
+    let credentials = extract::<Credentials>(credentials)?;
+    let Some(token) = create_session(&state, credentials).await? else {
+        return Err(ApiError::Unauthorized);
+    };
+    Ok(HttpResponse::Ok().body(compact(&token.to_serial())?))
+}
+
+#[post("/logout")]
+async fn logout(state: Data<AppState>, req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let Some(token) = bearer_token(&req) else {
+        return Err(ApiError::Unauthorized);
+    };
+    delete_session(&state, &token).await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+#[get("")]
+async fn me(user: ReqData<User>) -> Result<HttpResponse, ApiError> {
+    let User { id, username, .. } = user.into_inner();
+    let name = Name {
+        id,
+        the_name: username,
+    };
+    Ok(HttpResponse::Ok().body(compact(&name.to_serial())?))
+}
+
+#[get("/permissions")]
+async fn my_permissions(
+    state: Data<AppState>,
+    user: ReqData<User>,
+) -> Result<HttpResponse, ApiError> {
+    let permissions = fetch_permissions(&state, &user).await?;
+    Ok(HttpResponse::Ok().body(compact(&permissions)?))
+}
+
+#[post("/users")]
+async fn add_user(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    credentials: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&user)?;
+    let credentials = extract::<Credentials>(credentials)?;
+    let id = create_user(&state, credentials, false).await?;
+    Ok(HttpResponse::Ok().body(compact(&id.to_serial())?))
+}
+
+#[put("/permissions")]
+async fn grant_role(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    grant: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&user)?;
+    let grant = extract::<RoleGrant>(grant)?;
+    save_role_grant(&state, grant).await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+pub fn require_admin(user: &User) -> Result<(), ApiError> {
+    if user.is_admin {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(Role::Admin.as_str().to_string()))
+    }
+}
+
+pub async fn check_role(
+    state: &AppState,
+    user: &User,
+    target: Target<'_>,
+    role: Role,
+) -> Result<(), ApiError> {
+    let type_name = match target {
+        Target::SheetType(type_name) => {
+            if user.is_admin {
+                return Ok(());
+            }
+            type_name.to_string()
+        }
+        Target::Sheet(sheet_id) => fetch_sheet_type_name(state, sheet_id, false).await?,
+        Target::TrashedSheet(sheet_id) => fetch_sheet_type_name(state, sheet_id, true).await?,
+    };
+    if user.is_admin {
+        return Ok(());
+    }
+    match fetch_role(state, &user.id, &type_name).await? {
+        Some(user_role) if user_role >= role => Ok(()),
+        _ => Err(ApiError::Forbidden(format!(
+            "{} {}",
+            role.as_str(),
+            type_name
+        ))),
+    }
+}
+
+async fn fetch_sheet_type_name(
+    state: &AppState,
+    sheet_id: &Uuid,
+    trashed: bool,
+) -> Result<String, ApiError> {
+    let record = query!(
+        r#"
+        select type_name
+        from sheets WHERE id = $1 AND (deleted_at IS NOT NULL) = $2"#,
+        sheet_id,
+        trashed,
+    )
+    .fetch_optional(&state.db)
+    .await?;
+    record
+        .map(|x| x.type_name)
+        .ok_or_else(|| ApiError::NotFound(sheet_id.to_string()))
+}
+
+async fn fetch_role(
+    state: &AppState,
+    user_id: &Uuid,
+    sheet_type_name: &str,
+) -> Result<Option<Role>, ApiError> {
+    let record = query!(
+        r#"
+        select role
+        from permissions WHERE user_id = $1 AND sheet_type_name = $2"#,
+        user_id,
+        sheet_type_name,
+    )
+    .fetch_optional(&state.db)
+    .await?;
+    match record {
+        Some(record) => Ok(Some(
+            Role::from_str(&record.role).map_err(ApiError::Internal)?,
+        )),
+        None => Ok(None),
+    }
+}
+
+async fn fetch_permissions(state: &AppState, user: &User) -> Result<Permissions, ApiError> {
+    let records = query!(
+        r#"
+        select sheet_type_name,role
+        from permissions WHERE user_id = $1"#,
+        user.id,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let mut roles = HashMap::new();
+    for record in records {
+        let role = Role::from_str(&record.role).map_err(ApiError::Internal)?;
+        roles.insert(record.sheet_type_name, role);
+    }
+    Ok(Permissions {
+        is_admin: user.is_admin,
+        roles,
+    })
+}
+
+async fn save_role_grant(state: &AppState, grant: RoleGrant) -> Result<(), ApiError> {
+    let RoleGrant {
+        username,
+        sheet_type_name,
+        role,
+    } = grant;
+    let result = match role {
+        Some(role) => {
+            query!(
+                r#"
+        INSERT INTO permissions(user_id,sheet_type_name,role)
+        SELECT id,$2,$3 FROM users WHERE username = $1
+        ON CONFLICT (user_id,sheet_type_name) DO UPDATE SET role = $3"#,
+                username,
+                sheet_type_name,
+                role.as_str(),
+            )
+            .execute(&state.db)
+            .await?
+        }
+        None => {
+            query!(
+                r#"
+        DELETE FROM permissions
+        WHERE sheet_type_name = $2 AND user_id = (
+            SELECT id FROM users WHERE username = $1
+        )"#,
+                username,
+                sheet_type_name,
+            )
+            .execute(&state.db)
+            .await?
+        }
+    };
+    if role.is_some() && result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(username));
+    }
+    Ok(())
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<Uuid> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .and_then(|token| Uuid::from_str(token.trim()).ok())
+}
+
+async fn create_session(
+    state: &AppState,
+    credentials: Credentials,
+) -> Result<Option<Uuid>, ApiError> {
+    let Credentials { username, password } = credentials;
+    let Some(record) = query!(
+        r#"
+        select id,password
+        from users WHERE username = $1"#,
+        username,
+    )
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let verified = bcrypt::verify(password, &record.password)
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    if !verified {
+        return Ok(None);
+    }
+    delete_expired_sessions(state).await?;
+    let token = Uuid::new_v4();
+    query!(
+        r#"
+	INSERT INTO sessions(token,user_id)
+	VALUES($1,$2)"#,
+        token,
+        record.id,
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(Some(token))
+}
+
+async fn delete_session(state: &AppState, token: &Uuid) -> Result<(), ApiError> {
+    query!(
+        r#"
+        DELETE FROM sessions WHERE token = $1"#,
+        token,
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+async fn delete_expired_sessions(state: &AppState) -> Result<(), ApiError> {
+    query!(
+        r#"
+        DELETE FROM sessions
+        WHERE insert_date < CURRENT_TIMESTAMP - make_interval(hours => $1)"#,
+        SESSION_MAX_AGE_HOURS,
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+// a missing or expired session is not an error, only failing to look it up is
+async fn fetch_session_user(state: &AppState, token: &Uuid) -> Result<Option<User>, ApiError> {
+    let record = query!(
+        r#"
+        select u.id,u.username,u.is_admin
+        from sessions s JOIN users u ON u.id = s.user_id
+        WHERE s.token = $1
+        AND s.insert_date >= CURRENT_TIMESTAMP - make_interval(hours => $2)"#,
+        token,
+        SESSION_MAX_AGE_HOURS,
+    )
+    .fetch_optional(&state.db)
+    .await?;
+    Ok(record.map(|record| User {
+        id: record.id,
+        username: record.username,
+        is_admin: record.is_admin,
+    }))
+}
+
+pub async fn create_user(
+    state: &AppState,
+    credentials: Credentials,
+    is_admin: bool,
+) -> Result<Uuid, ApiError> {
+    let Credentials { username, password } = credentials;
+    let id = Uuid::new_v4();
+    let password = bcrypt::hash(password, bcrypt::DEFAULT_COST)
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    query!(
+        r#"
+	INSERT INTO users(id,username,password,is_admin)
+	VALUES($1,$2,$3,$4)"#,
+        id,
+        username,
+        password,
+        is_admin,
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(id)
+}
+
+pub async fn users_count(state: &AppState) -> Result<i64, ApiError> {
+    let record = query!(
+        r#"
+        select count(id) as len
+        from users"#,
+    )
+    .fetch_one(&state.db)
+    .await?;
+    Ok(record.len.unwrap_or_default())
+}
+
+pub struct RequireAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for RequireAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequireAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAuthMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequireAuthMiddleware<S> {
+    service: Rc<S>,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for RequireAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let token = bearer_token(req.request());
+            let state = req.app_data::<Data<AppState>>().cloned();
+            let user = match (token, state) {
+                (Some(token), Some(state)) => match fetch_session_user(&state, &token).await {
+                    Ok(user) => user,
+                    Err(err) => {
+                        let res = err.error_response();
+                        return Ok(req.into_response(res).map_into_right_body());
+                    }
+                },
+                _ => None,
+            };
+            let Some(user) = user else {
+                let res = ApiError::Unauthorized.error_response();
+                return Ok(req.into_response(res).map_into_right_body());
+            };
+            req.extensions_mut().insert(user);
+            service.call(req).await.map(|res| res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{admin_session, app_state};
+    use actix_web::{http::StatusCode, test, App};
+    use sqlx::PgPool;
+
+    async fn status_with_token(state: AppState, token: Uuid) -> StatusCode {
+        let app = test::init_service(
+            App::new().app_data(Data::new(state)).service(
+                web::resource("/")
+                    .wrap(RequireAuth)
+                    .to(|| async { HttpResponse::Ok().finish() }),
+            ),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {token}")))
+            .to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn only_missing_sessions_are_unauthorized(pool: PgPool) {
+        let state = app_state(pool.clone());
+        let token = admin_session(&state, "admin").await;
+        let status = status_with_token(app_state(pool.clone()), token).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let status = status_with_token(app_state(pool.clone()), Uuid::new_v4()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // a database failure keeps the user logged in
+        pool.close().await;
+        let status = status_with_token(app_state(pool), token).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -1,191 +1,424 @@
-use actix_web::{post, put, web, HttpResponse, Responder, Scope};
-use sqlx::{query, Transaction};
-
-use std::error::Error;
+use actix_web::{
+    post,
+    web::{self, ReqData},
+    HttpResponse, Scope,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use sqlx::{query, QueryBuilder, Transaction};
 use uuid::Uuid;
 
 use std::sync::Arc;
 
-use crate::{service::extract, AppState};
+use crate::{
+    audit::{self, AuditRecord},
+    auth::{check_role, Target, User},
+    service::{compact, extract},
+    sheet::fetch_columns_by_rows_ids,
+    AppState,
+};
 
-use models::{ColumnId, ColumnValue};
+use models::{
+    ApiError, AuditAction, ColumnId, ColumnSearchMode, ColumnSearchParams, ColumnValue, Role, Row,
+    RowMatch, SuggestionParams, ToSerial,
+};
 
-pub fn scope() -> Scope {
-    web::scope("/columns")
-        .service(delete_columns)
-        .service(update_columns)
-        .service(save_columns)
+pub struct StoredValue {
+    pub text_value: Option<String>,
+    pub numeric_value: Option<BigDecimal>,
+    pub date_value: Option<NaiveDate>,
 }
 
-#[post("/delete")]
-async fn delete_columns(state: web::Data<AppState>, ids: web::Bytes) -> impl Responder {
-    let ids = match extract::<Vec<ColumnId<Uuid, Arc<str>>>>(ids) {
-        Ok(ids) => ids,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
-
-    let mut transaction = match state.db.begin().await {
-        Ok(v) => v,
-        Err(err) => {
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
-    };
-
-    for id in ids {
-        if let Err(err) = delete_column_by_column_id(&mut transaction, id).await {
-            transaction.rollback().await.unwrap_or_default();
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
+impl From<ColumnValue<Arc<str>>> for StoredValue {
+    fn from(value: ColumnValue<Arc<str>>) -> Self {
+        let mut stored = Self {
+            text_value: None,
+            numeric_value: None,
+            date_value: None,
+        };
+        match value {
+            ColumnValue::String(v) => stored.text_value = Some(v.to_string()),
+            ColumnValue::Decimal(v) => stored.numeric_value = Some(v),
+            ColumnValue::Date(v) => stored.date_value = Some(v),
         }
+        stored
     }
-    if let Err(err) = transaction.commit().await {
-        return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-    };
-
-    HttpResponse::Ok().into()
 }
 
-#[put("/")]
-async fn update_columns(state: web::Data<AppState>, ids_and_values: web::Bytes) -> impl Responder {
-    let ids_and_values =
-        match extract::<Vec<(ColumnId<Uuid, Arc<str>>, ColumnValue<Arc<str>>)>>(ids_and_values) {
-            Ok(ids) => ids,
-            Err(err) => {
-                return HttpResponse::InternalServerError().body(err.to_string().into_bytes())
-            }
-        };
+impl TryFrom<StoredValue> for ColumnValue<Arc<str>> {
+    type Error = ApiError;
 
-    let mut transaction = match state.db.begin().await {
-        Ok(v) => v,
-        Err(err) => {
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
+    fn try_from(stored: StoredValue) -> Result<Self, Self::Error> {
+        match stored {
+            StoredValue {
+                text_value: Some(v),
+                ..
+            } => Ok(ColumnValue::String(Arc::from(v))),
+            StoredValue {
+                numeric_value: Some(v),
+                ..
+            } => Ok(ColumnValue::Decimal(v)),
+            StoredValue {
+                date_value: Some(v),
+                ..
+            } => Ok(ColumnValue::Date(v)),
+            _ => Err(ApiError::Internal(String::from("column without value"))),
         }
-    };
-
-    for ids_and_value in ids_and_values {
-        let (ids, value) = ids_and_value;
-        if let Err(err) = update_column_by_column_id(&mut transaction, ids, value).await {
-            transaction.rollback().await.unwrap_or_default();
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
-    }
-    if let Err(err) = transaction.commit().await {
-        return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
     }
-
-    HttpResponse::Ok().into()
 }
 
-#[post("/")]
-async fn save_columns(state: web::Data<AppState>, ids_and_values: web::Bytes) -> impl Responder {
-    let ids_and_values =
-        extract::<Vec<(ColumnId<Uuid, Arc<str>>, ColumnValue<Arc<str>>)>>(ids_and_values);
-
-    let ids_and_values = match ids_and_values {
-        Ok(ids) => ids,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
+impl StoredValue {
+    pub fn into_json(self) -> Result<serde_json::Value, ApiError> {
+        let value = ColumnValue::try_from(self)?;
+        Ok(serde_json::json!(value))
+    }
+}
 
-    let mut transaction = match state.db.begin().await {
-        Ok(v) => v,
-        Err(err) => {
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
-    };
+pub fn scope() -> Scope {
+    web::scope("/columns")
+        .service(search_columns)
+        .service(column_suggestions)
+}
 
-    for ids_and_value in ids_and_values {
-        let (
-            ColumnId {
-                sheet_id: _,
-                row_id,
-                header,
-            },
-            value,
-        ) = ids_and_value;
-        if let Err(err) = save_cloumn_value(&mut transaction, &row_id, header, value).await {
-            transaction.rollback().await.unwrap_or_default();
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
+#[post("/search")]
+async fn search_columns(
+    state: web::Data<AppState>,
+    user: ReqData<User>,
+    params: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let params = extract::<ColumnSearchParams>(params)?;
+    if let Some(type_name) = &params.sheet_type_name {
+        check_role(&state, &user, Target::SheetType(type_name), Role::Viewer).await?;
     }
-    if let Err(err) = transaction.commit().await {
-        return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-    };
-    HttpResponse::Ok().into()
+    let matches = search_rows_by_column(&state, &user, params)
+        .await?
+        .into_iter()
+        .map(|x| x.to_serial())
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().body(compact(&matches)?))
+}
+
+#[post("/suggestions")]
+async fn column_suggestions(
+    state: web::Data<AppState>,
+    user: ReqData<User>,
+    params: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let params = extract::<SuggestionParams>(params)?;
+    check_role(
+        &state,
+        &user,
+        Target::SheetType(&params.sheet_type_name),
+        Role::Viewer,
+    )
+    .await?;
+    let suggestions = fetch_suggestions(&state, params).await?;
+    Ok(HttpResponse::Ok().body(compact(&suggestions)?))
 }
 
 pub async fn delete_column_by_column_id(
     transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
     ids: ColumnId<Uuid, Arc<str>>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), ApiError> {
     let ColumnId {
         sheet_id,
         row_id,
         header,
     } = ids;
-    query!(
+    let deleted = query!(
         r#"
         DELETE FROM columns 
             WHERE header_name = $1 AND row_id = (
                 SELECT id FROM rows 
                     WHERE id = $2 AND sheet_id = $3
             )
+        RETURNING text_value,numeric_value,date_value
         "#,
         header.to_string(),
         row_id,
         sheet_id,
     )
-    .execute(transaction)
+    .fetch_all(&mut *transaction)
     .await?;
+    if deleted.is_empty() {
+        return Err(ApiError::Conflict(format!("{sheet_id}/{row_id}/{header}")));
+    }
+    for record in deleted {
+        let old_value = StoredValue {
+            text_value: record.text_value,
+            numeric_value: record.numeric_value,
+            date_value: record.date_value,
+        };
+        let entry = AuditRecord {
+            user_id,
+            sheet_id: &sheet_id,
+            row_id: Some(&row_id),
+            header: Some(&header),
+            action: AuditAction::DeleteColumn,
+            old_value: Some(old_value.into_json()?),
+            new_value: None,
+        };
+        audit::record(&mut *transaction, entry).await?;
+    }
     Ok(())
 }
 
 pub async fn update_column_by_column_id(
     transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
     ids: ColumnId<Uuid, Arc<str>>,
     value: ColumnValue<Arc<str>>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), ApiError> {
     let ColumnId {
         sheet_id,
         row_id,
         header,
     } = ids;
-    let value = serde_json::json!(value);
-    query!(
+    let new_value = serde_json::json!(value);
+    let StoredValue {
+        text_value,
+        numeric_value,
+        date_value,
+    } = StoredValue::from(value);
+    let updated = query!(
         r#"
-        UPDATE columns 
-            set value = $1
-            WHERE header_name = $2 AND row_id = (
-                SELECT id FROM rows 
-                    WHERE id = $3 AND sheet_id = $4
-            )
+        UPDATE columns c
+            set text_value = $1, numeric_value = $2, date_value = $3
+            FROM (
+                SELECT id, text_value, numeric_value, date_value FROM columns
+                    WHERE header_name = $4 AND row_id = (
+                        SELECT id FROM rows 
+                            WHERE id = $5 AND sheet_id = $6
+                    )
+                FOR UPDATE
+            ) old
+            WHERE c.id = old.id
+        RETURNING old.text_value AS old_text_value,
+                  old.numeric_value AS old_numeric_value,
+                  old.date_value AS old_date_value
         "#,
-        value,
+        text_value,
+        numeric_value,
+        date_value,
         header.to_string(),
         row_id,
         sheet_id,
     )
-    .execute(transaction)
+    .fetch_all(&mut *transaction)
     .await?;
+    if updated.is_empty() {
+        return Err(ApiError::Conflict(format!("{sheet_id}/{row_id}/{header}")));
+    }
+    for record in updated {
+        let old_value = StoredValue {
+            text_value: record.old_text_value,
+            numeric_value: record.old_numeric_value,
+            date_value: record.old_date_value,
+        };
+        let entry = AuditRecord {
+            user_id,
+            sheet_id: &sheet_id,
+            row_id: Some(&row_id),
+            header: Some(&header),
+            action: AuditAction::UpdateColumn,
+            old_value: Some(old_value.into_json()?),
+            new_value: Some(new_value.clone()),
+        };
+        audit::record(&mut *transaction, entry).await?;
+    }
     Ok(())
 }
 
 pub async fn save_cloumn_value(
     transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+    sheet_id: &Uuid,
     row_id: &Uuid,
     header_name: Arc<str>,
     value: ColumnValue<Arc<str>>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), ApiError> {
     let column_id = Uuid::new_v4();
-    let value = serde_json::json!(value);
-    query!(
+    let new_value = serde_json::json!(value);
+    let StoredValue {
+        text_value,
+        numeric_value,
+        date_value,
+    } = StoredValue::from(value);
+    let inserted = query!(
         r#"
-	INSERT INTO columns(id,row_id,header_name,value)
-	VALUES($1,$2,$3,$4)"#,
+	INSERT INTO columns(id,row_id,header_name,text_value,numeric_value,date_value)
+	SELECT $1,id,$3,$4,$5,$6 FROM rows WHERE id = $2 AND sheet_id = $7"#,
         column_id,
         row_id,
         header_name.to_string(),
-        value,
+        text_value,
+        numeric_value,
+        date_value,
+        sheet_id,
     )
-    .execute(transaction)
+    .execute(&mut *transaction)
     .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{sheet_id}/{row_id}")));
+    }
+    let entry = AuditRecord {
+        user_id,
+        sheet_id,
+        row_id: Some(row_id),
+        header: Some(&header_name),
+        action: AuditAction::AddColumn,
+        old_value: None,
+        new_value: Some(new_value),
+    };
+    audit::record(transaction, entry).await?;
     Ok(())
 }
+
+fn parse_number(value: &str) -> Option<BigDecimal> {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '٠'..='٩' => char::from_digit(c as u32 - '٠' as u32, 10),
+            '۰'..='۹' => char::from_digit(c as u32 - '۰' as u32, 10),
+            c => Some(c),
+        })
+        .collect::<Option<String>>()?
+        .parse()
+        .ok()
+}
+
+async fn search_rows_by_column(
+    state: &AppState,
+    user: &User,
+    params: ColumnSearchParams,
+) -> Result<Vec<RowMatch<Uuid, Arc<str>>>, ApiError> {
+    let ColumnSearchParams {
+        header_name,
+        mode,
+        sheet_type_name,
+        offset,
+        limit,
+    } = params;
+    if !(1..=100).contains(&limit) || offset < 0 {
+        return Err(ApiError::BadRequest(format!(
+            "offset ({offset}) limit ({limit})"
+        )));
+    }
+    let mut builder = QueryBuilder::new(
+        r#"
+        SELECT r.id,r.version,s.id,s.sheet_name,s.type_name
+        FROM columns c
+        JOIN rows r ON r.id = c.row_id
+        JOIN sheets s ON s.id = r.sheet_id
+        WHERE s.deleted_at IS NULL AND c.header_name = "#,
+    );
+    builder.push_bind(header_name);
+    if let Some(type_name) = sheet_type_name {
+        builder.push(" AND s.type_name = ").push_bind(type_name);
+    }
+    if !user.is_admin {
+        builder
+            .push(" AND s.type_name IN (SELECT sheet_type_name FROM permissions WHERE user_id = ")
+            .push_bind(user.id)
+            .push(")");
+    }
+    match mode {
+        ColumnSearchMode::Exact(value) => {
+            let number = parse_number(&value);
+            builder
+                .push(" AND (c.value_normalized = normalize_arabic(")
+                .push_bind(value)
+                .push(")");
+            if let Some(number) = number {
+                builder.push(" OR c.numeric_value = ").push_bind(number);
+            }
+            builder.push(")");
+        }
+        ColumnSearchMode::Contains(value) => {
+            builder
+                .push(" AND c.value_normalized LIKE '%' || normalize_arabic(")
+                .push_bind(value)
+                .push(") || '%'");
+        }
+        ColumnSearchMode::Range { min, max } => {
+            if min.is_none() && max.is_none() {
+                return Err(ApiError::BadRequest(String::from("range without bounds")));
+            }
+            builder.push(" AND c.numeric_value IS NOT NULL");
+            if let Some(min) = min {
+                builder.push(" AND c.numeric_value >= ").push_bind(min);
+            }
+            if let Some(max) = max {
+                builder.push(" AND c.numeric_value <= ").push_bind(max);
+            }
+        }
+    }
+    builder
+        .push(" ORDER BY s.insert_date DESC,s.id,r.insert_date,r.id OFFSET ")
+        .push_bind(offset)
+        .push(" LIMIT ")
+        .push_bind(limit);
+    let records = builder
+        .build_query_as::<(Uuid, i64, Uuid, String, String)>()
+        .fetch_all(&state.db)
+        .await?;
+
+    let rows_ids = records
+        .iter()
+        .map(|(row_id, ..)| *row_id)
+        .collect::<Vec<_>>();
+    let mut columns = fetch_columns_by_rows_ids(state, &rows_ids).await?;
+
+    let mut matches = Vec::new();
+    for (row_id, version, sheet_id, sheet_name, type_name) in records {
+        let columns = columns.remove(&row_id).unwrap_or_default();
+        matches.push(RowMatch {
+            sheet_id,
+            sheet_name: Arc::from(sheet_name),
+            type_name: Arc::from(type_name),
+            row: Row {
+                id: row_id,
+                columns,
+                version,
+            },
+        });
+    }
+    Ok(matches)
+}
+
+async fn fetch_suggestions(
+    state: &AppState,
+    params: SuggestionParams,
+) -> Result<Vec<String>, ApiError> {
+    let SuggestionParams {
+        sheet_type_name,
+        header_name,
+        prefix,
+        limit,
+    } = params;
+    if !(1..=50).contains(&limit) {
+        return Err(ApiError::BadRequest(format!("limit ({limit})")));
+    }
+    let records = query!(
+        r#"
+        SELECT c.text_value AS "value!"
+        FROM columns c
+        JOIN rows r ON r.id = c.row_id
+        JOIN sheets s ON s.id = r.sheet_id
+        WHERE s.deleted_at IS NULL AND s.type_name = $1 AND c.header_name = $2
+          AND c.text_value IS NOT NULL
+          AND c.value_normalized LIKE normalize_arabic($3) || '%'
+        GROUP BY c.text_value
+        ORDER BY count(*) DESC, c.text_value
+        LIMIT $4"#,
+        sheet_type_name,
+        header_name,
+        prefix,
+        limit,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok(records.into_iter().map(|x| x.value).collect())
+}
@@ -0,0 +1,156 @@
+use actix_web::{
+    get,
+    rt::time::timeout,
+    web::{self, Bytes, Data, ReqData},
+    HttpResponse, Scope,
+};
+use futures_util::stream;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::{
+    auth::{check_role, Target, User},
+    AppState,
+};
+
+use models::{ApiError, Role, SheetEvent, SheetEventKind};
+
+pub const EVENTS_CAPACITY: usize = 256;
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// events are paired with the id of the user who made the change
+pub type Events = broadcast::Sender<(Uuid, SheetEvent<Uuid, Arc<str>>)>;
+
+pub fn scope() -> Scope {
+    web::scope("/events").service(sheet_events)
+}
+
+// server sent events are text only so the payload is json instead of cbor
+#[get("/sheet/{id}")]
+async fn sheet_events(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    check_role(&state, &user, Target::Sheet(&id), Role::Viewer).await?;
+    let user_id = user.id;
+    let receiver = state.events.subscribe();
+    let events = stream::unfold(receiver, move |mut receiver| async move {
+        let message = loop {
+            let event = match timeout(KEEP_ALIVE, receiver.recv()).await {
+                Err(_) => break String::from(": keep-alive\n\n"),
+                Ok(Ok((author, event))) if author != user_id && event.sheet_id == id => event,
+                Ok(Ok(_)) => continue,
+                Ok(Err(RecvError::Lagged(_))) => SheetEvent {
+                    sheet_id: id,
+                    kind: SheetEventKind::Missed,
+                },
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            match serde_json::to_string(&event) {
+                Ok(data) => break format!("data: {data}\n\n"),
+                Err(err) => return Some((Err(ApiError::from(err)), receiver)),
+            }
+        };
+        Some((Ok(Bytes::from(message)), receiver))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events))
+}
+
+pub fn publish(
+    state: &AppState,
+    user_id: &Uuid,
+    sheet_id: Uuid,
+    kind: SheetEventKind<Uuid, Arc<str>>,
+) {
+    // sending only fails when nobody is listening
+    let _ = state.events.send((*user_id, SheetEvent { sheet_id, kind }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::{self, RequireAuth},
+        service::compact,
+        sheet,
+        testing::{admin_session, app_state, insert_sheet, insert_sheet_type},
+    };
+    use actix_web::{body::MessageBody, http::header, test, App};
+    use bigdecimal::BigDecimal;
+    use futures_util::future::poll_fn;
+    use models::{Column, ColumnValue, Row, SheetChange, SheetChangeset, ToSerial};
+    use sqlx::PgPool;
+    use std::{collections::HashMap, pin::pin};
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn row_changes_reach_other_subscribers(pool: PgPool) {
+        let state = app_state(pool);
+        insert_sheet_type(&state, "type").await;
+        let sheet_id = insert_sheet(&state, "type").await;
+        let watcher = admin_session(&state, "watcher").await;
+        let editor = admin_session(&state, "editor").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(auth::scope())
+                .service(sheet::scope().wrap(RequireAuth))
+                .service(scope().wrap(RequireAuth)),
+        )
+        .await;
+
+        let subscribe = test::TestRequest::get()
+            .uri(&format!("/events/sheet/{sheet_id}"))
+            .insert_header((header::AUTHORIZATION, format!("Bearer {watcher}")))
+            .to_request();
+        let stream = test::call_service(&app, subscribe).await;
+        assert!(stream.status().is_success());
+        let mut body = pin!(stream.into_body());
+
+        let row = Row {
+            id: Uuid::new_v4(),
+            columns: HashMap::from([(
+                Arc::<str>::from("value"),
+                Column {
+                    is_basic: true,
+                    value: ColumnValue::Decimal(BigDecimal::from(7)),
+                },
+            )]),
+            version: 0,
+        };
+        let row_id = row.id;
+        let changeset = SheetChangeset {
+            sheet_id,
+            changes: vec![SheetChange::AddRow(row)],
+            sheet_version: 0,
+            rows_versions: Vec::new(),
+        };
+        let add_row = test::TestRequest::post()
+            .uri("/sheet/changeset")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {editor}")))
+            .set_payload(compact(&changeset.to_serial()).unwrap())
+            .to_request();
+        assert!(test::call_service(&app, add_row)
+            .await
+            .status()
+            .is_success());
+
+        let chunk = poll_fn(|cx| body.as_mut().poll_next(cx))
+            .await
+            .expect("the stream ended")
+            .unwrap();
+        let message = std::str::from_utf8(&chunk).unwrap();
+        let data = message
+            .strip_prefix("data: ")
+            .expect("expected an event and not a keep alive")
+            .trim_end();
+        let event = serde_json::from_str::<SheetEvent<Uuid, Arc<str>>>(data).unwrap();
+        assert_eq!(event.sheet_id, sheet_id);
+        assert_eq!(event.kind, SheetEventKind::RowsAdded(vec![row_id]));
+    }
+}
@@ -0,0 +1,135 @@
+use sqlx::query;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use uuid::Uuid;
+
+use models::{
+    ApiError, Column, ColumnType, ColumnValue, Row, SchemaViolation, SheetChange, ViolationKind,
+};
+
+use crate::AppState;
+
+pub struct SheetSchema {
+    basic: HashMap<String, ColumnType>,
+    calculated: HashSet<String>,
+}
+
+impl SheetSchema {
+    pub async fn of_type(state: &AppState, type_name: &str) -> Result<Self, ApiError> {
+        let exists = query!(
+            r#"
+            SELECT count(*) AS "count!" FROM sheet_types WHERE name = $1"#,
+            type_name,
+        )
+        .fetch_one(&state.db)
+        .await?
+        .count;
+        if exists == 0 {
+            return Err(ApiError::NotFound(type_name.to_string()));
+        }
+        let basic = query!(
+            r#"
+            SELECT header,value_type FROM sheet_type_columns WHERE type_name = $1"#,
+            type_name,
+        )
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .map(|record| {
+            let column_type = record
+                .value_type
+                .parse::<ColumnType>()
+                .map_err(ApiError::Internal)?;
+            Ok((record.header, column_type))
+        })
+        .collect::<Result<HashMap<_, _>, ApiError>>()?;
+        let calculated = query!(
+            r#"
+            SELECT header FROM sheet_type_calculated_columns WHERE type_name = $1"#,
+            type_name,
+        )
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .map(|record| record.header)
+        .collect();
+        Ok(Self { basic, calculated })
+    }
+
+    pub async fn of_sheet(state: &AppState, sheet_id: Uuid) -> Result<Self, ApiError> {
+        let Some(record) = query!(
+            r#"
+            SELECT type_name FROM sheets WHERE id = $1"#,
+            sheet_id,
+        )
+        .fetch_optional(&state.db)
+        .await?
+        else {
+            return Err(ApiError::NotFound(sheet_id.to_string()));
+        };
+        Self::of_type(state, &record.type_name).await
+    }
+
+    pub fn check_value(
+        &self,
+        row_id: &Uuid,
+        header: &str,
+        value: &ColumnValue<Arc<str>>,
+    ) -> Option<SchemaViolation> {
+        let kind = match self.basic.get(header) {
+            Some(expected) if *expected == value.column_type() => return None,
+            Some(expected) => ViolationKind::WrongType {
+                expected: *expected,
+                found: value.column_type(),
+            },
+            None if self.calculated.contains(header) => ViolationKind::CalculatedHeader,
+            None => ViolationKind::UnknownHeader,
+        };
+        Some(SchemaViolation {
+            row_id: row_id.to_string(),
+            header: header.to_string(),
+            kind,
+        })
+    }
+
+    pub fn check_row(&self, row: &Row<Uuid, Arc<str>>) -> Vec<SchemaViolation> {
+        row.columns
+            .iter()
+            .filter(|(_, column)| column.is_basic)
+            .filter_map(|(header, Column { value, .. })| self.check_value(&row.id, header, value))
+            .collect()
+    }
+
+    pub fn check_change(&self, change: &SheetChange<Uuid, Arc<str>>) -> Result<(), ApiError> {
+        let violations = match change {
+            SheetChange::AddRow(row) => self.check_row(row),
+            SheetChange::UpdateColumn {
+                row_id,
+                header,
+                value,
+            }
+            | SheetChange::AddColumn {
+                row_id,
+                header,
+                value,
+            } => Vec::from_iter(self.check_value(row_id, header, value)),
+            // sheet fields hold free form headers
+            SheetChange::Rename(_)
+            | SheetChange::DeleteRow(_)
+            | SheetChange::DeleteColumn { .. }
+            | SheetChange::SetField { .. }
+            | SheetChange::DeleteField(_) => Vec::new(),
+        };
+        into_result(violations)
+    }
+}
+
+pub fn into_result(violations: Vec<SchemaViolation>) -> Result<(), ApiError> {
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::Invalid(violations))
+    }
+}
@@ -1,237 +1,479 @@
-use crate::{column::save_cloumn_value, service::extract, AppState};
+use crate::{
+    audit::{self, fetch_sheet_history, AuditRecord, HistoryCursor},
+    auth::{check_role, Target, User},
+    column::{
+        delete_column_by_column_id, save_cloumn_value, update_column_by_column_id, StoredValue,
+    },
+    events::publish,
+    schema::{into_result, SheetSchema},
+    service::{compact, decode_cursor, encode_cursor, extract},
+    AppState,
+};
 use actix_web::{
-    get, post, put,
-    web::{self, Data},
-    HttpResponse, Responder, Result, Scope,
+    delete, get, post, put,
+    web::{self, Data, ReqData},
+    HttpResponse, Scope,
 };
-use sqlx::{query, query_as, Transaction};
-use std::{collections::HashMap, error::Error};
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, Acquire, Postgres, QueryBuilder, Transaction};
+use std::collections::HashMap;
 use uuid::Uuid;
 
+use std::str::FromStr;
 use std::sync::Arc;
 
-use models::{Column, Name, Row, SearchSheetParams, Sheet, ToSerial};
+use models::{
+    ApiError, AuditAction, ChangesetResult, Column, ColumnId, ColumnValue, Conflict,
+    ConflictTarget, Name, Role, Row, RowsPage, SearchSheetParams, Sheet, SheetChange,
+    SheetChangeset, SheetEventKind, SheetsPage, SheetsSort, SortDirection, ToSerial, TrashParams,
+};
+
+const MAX_ROWS_ORDER_HEADERS: usize = 8;
+const MAX_ROWS_PAGE_LIMIT: i64 = 100;
+const MAX_HISTORY_PAGE_LIMIT: i64 = 100;
 
-use std::io::Cursor;
+type RowColumnRecord = (
+    Uuid,
+    i64,
+    NaiveDateTime,
+    Option<String>,
+    Option<String>,
+    Option<BigDecimal>,
+    Option<NaiveDate>,
+);
 
 pub fn scope() -> Scope {
     web::scope("/sheet")
         .service(ge_sheet_by_id)
         .service(search)
         .service(save)
-        .service(update_name)
-        .service(delete_sheet_rows)
-        .service(add_rows_to_sheet)
-        .service(get_number_of_sheet_rows_by_id)
+        .service(apply_changeset)
+        .service(get_sheet_rows_after)
+        .service(get_sheet_history)
+        .service(trashed_sheets)
+        .service(trash_sheet)
+        .service(restore_sheet)
+        .service(purge_sheet)
 }
 
 #[post("/search")]
-async fn search(state: Data<AppState>, params: web::Bytes) -> impl Responder {
-    let params = match extract::<SearchSheetParams>(params) {
-        Ok(params) => params,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
-    fn compact(dep: Vec<Name<Uuid>>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut buf = vec![];
-        ciborium::ser::into_writer(
-            &dep.into_iter()
-                .map(|name| name.to_serial())
-                .collect::<Vec<_>>(),
-            Cursor::new(&mut buf),
-        )?;
-        Ok(buf)
-    }
-    match search_by_params(&state, params).await {
-        Ok(dep) => match compact(dep) {
-            Ok(buf) => HttpResponse::Ok().body(buf),
-            Err(err) => HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-        },
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    }
+async fn search(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    params: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let params = extract::<SearchSheetParams>(params)?;
+    let target = Target::SheetType(&params.sheet_type_name);
+    check_role(&state, &user, target, Role::Viewer).await?;
+    let page = search_by_params(&state, params).await?.to_serial();
+    Ok(HttpResponse::Ok().body(compact(&page)?))
 }
 
-#[post("/")]
-async fn save(state: Data<AppState>, sheet: web::Bytes) -> impl Responder {
-    let sheet = match extract::<Sheet<Uuid, Arc<str>>>(sheet) {
-        Ok(sheet) => sheet,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
-    match save_sheet(&state, sheet).await {
-        Ok(_) => HttpResponse::Ok().into(),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    }
+#[post("/trash")]
+async fn trashed_sheets(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    params: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let params = extract::<TrashParams>(params)?;
+    let target = Target::SheetType(&params.sheet_type_name);
+    check_role(&state, &user, target, Role::Admin).await?;
+    let names = fetch_trashed_sheets(&state, params)
+        .await?
+        .into_iter()
+        .map(|name| name.to_serial())
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().body(compact(&names)?))
 }
 
-#[put("/name")]
-async fn update_name(state: Data<AppState>, name: web::Bytes) -> impl Responder {
-    let name = match extract::<Name<Uuid>>(name) {
-        Ok(name) => name,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
+#[delete("/{id}")]
+async fn trash_sheet(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    check_role(&state, &user, Target::Sheet(&id), Role::Admin).await?;
+    set_sheet_trashed(&state, &user.id, &id, true).await?;
+    Ok(HttpResponse::Ok().into())
+}
 
-    match update_sheet_name(&state, name).await {
-        Ok(_) => HttpResponse::Ok().into(),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    }
+#[put("/{id}/restore")]
+async fn restore_sheet(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    check_role(&state, &user, Target::TrashedSheet(&id), Role::Admin).await?;
+    set_sheet_trashed(&state, &user.id, &id, false).await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+#[delete("/{id}/purge")]
+async fn purge_sheet(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    check_role(&state, &user, Target::TrashedSheet(&id), Role::Admin).await?;
+    purge_trashed_sheet(&state, &user.id, &id).await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+#[post("/")]
+async fn save(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    sheet: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let sheet = extract::<Sheet<Uuid, Arc<str>>>(sheet)?;
+    let target = Target::SheetType(&sheet.type_name);
+    check_role(&state, &user, target, Role::Editor).await?;
+    let schema = SheetSchema::of_type(&state, &sheet.type_name).await?;
+    let violations = sheet.rows.iter().flat_map(|row| schema.check_row(row));
+    into_result(violations.collect())?;
+    save_sheet(&state, &user.id, sheet).await?;
+    Ok(HttpResponse::Ok().into())
 }
 
 #[get("/{id}")]
-async fn ge_sheet_by_id(state: Data<AppState>, path: web::Path<Uuid>) -> impl Responder {
+async fn ge_sheet_by_id(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
     let id = path.into_inner();
-    async fn helper(state: &AppState, id: Uuid) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let sheet = fetch_custom_sheet_by_id(state, id).await?;
-        let len = fetch_sheet_rows_length(state, &id).await?;
-        let mut res = vec![];
-        ciborium::ser::into_writer(&(sheet.to_serial(), len), Cursor::new(&mut res))?;
-        Ok(res)
-    }
-    match helper(&state, id).await {
-        Ok(buf) => HttpResponse::Ok().body(buf),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    }
+    check_role(&state, &user, Target::Sheet(&id), Role::Viewer).await?;
+    let sheet = fetch_custom_sheet_by_id(&state, id).await?;
+    let len = fetch_sheet_rows_length(&state, &id).await?;
+    Ok(HttpResponse::Ok().body(compact(&(sheet.to_serial(), len))?))
 }
 
-#[get("/{id}/{offset}/{limit}")]
-async fn get_number_of_sheet_rows_by_id(
+#[get("/{id}/rows/{limit}")]
+async fn get_sheet_rows_after(
     state: Data<AppState>,
-    path: web::Path<(Uuid, i64, i64)>,
-) -> impl Responder {
-    let (id, offset, limit) = path.into_inner();
-    let ids = match fetch_rows_ids_by_sheet_id_in_limit(&state, &id, offset, limit).await {
-        Ok(ids) => ids,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
-    let mut rows = Vec::new();
-    for id in ids {
-        if let Ok(columns) = fetch_columns_by_row_id(&state, &id).await {
-            rows.push(Row { id, columns })
-        };
+    user: ReqData<User>,
+    path: web::Path<(Uuid, i64)>,
+    params: web::Query<Vec<(String, String)>>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, limit) = path.into_inner();
+    check_role(&state, &user, Target::Sheet(&id), Role::Viewer).await?;
+    if !(1..=MAX_ROWS_PAGE_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!("rows limit ({limit})")));
     }
-    fn compact(dep: Vec<Row<Uuid, Arc<str>>>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut buf = vec![];
-        ciborium::ser::into_writer(
-            &dep.into_iter()
-                .map(|name| name.to_serial())
-                .collect::<Vec<_>>(),
-            Cursor::new(&mut buf),
-        )?;
-        Ok(buf)
+    let mut after = None;
+    let mut order = Vec::new();
+    for (key, value) in params.into_inner() {
+        if key == "after" {
+            after = Some(decode_cursor::<RowsCursor>(&value)?);
+        } else {
+            order.push((value, key.parse().map_err(ApiError::BadRequest)?));
+        }
     }
+    let page = fetch_rows_page(&state, &id, after, limit, order).await?;
+    Ok(HttpResponse::Ok().body(compact(&page.to_serial())?))
+}
 
-    match compact(rows) {
-        Ok(buf) => HttpResponse::Ok().body(buf),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    }
+#[derive(Deserialize)]
+struct HistoryQuery {
+    after: Option<String>,
 }
 
-#[post("/{sheet_id}/rows")]
-async fn add_rows_to_sheet(
+#[get("/{id}/history/{limit}")]
+async fn get_sheet_history(
     state: Data<AppState>,
-    sheet_id: web::Path<Uuid>,
-    rows: web::Bytes,
-) -> impl Responder {
-    let sheet_id = sheet_id.into_inner();
-
-    let rows = match extract::<Vec<Row<Uuid, Arc<str>>>>(rows) {
-        Ok(rows) => rows,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
-
-    let mut transaction = match state.db.begin().await {
-        Ok(v) => v,
-        Err(err) => {
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
-    };
-
-    for row in rows {
-        if let Err(err) = save_row(&mut transaction, &sheet_id, row).await {
-            transaction.rollback().await.unwrap_or_default();
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
+    user: ReqData<User>,
+    path: web::Path<(Uuid, i64)>,
+    params: web::Query<HistoryQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, limit) = path.into_inner();
+    check_role(&state, &user, Target::Sheet(&id), Role::Viewer).await?;
+    if !(1..=MAX_HISTORY_PAGE_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!("history limit ({limit})")));
     }
-    if let Err(err) = transaction.commit().await {
-        return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-    }
-    HttpResponse::Ok().into()
+    let after = params
+        .into_inner()
+        .after
+        .map(|cursor| decode_cursor::<HistoryCursor>(&cursor))
+        .transpose()?;
+    let page = fetch_sheet_history(&state, &id, after, limit).await?;
+    Ok(HttpResponse::Ok().body(compact(&page.to_serial())?))
 }
 
-#[post("/delete/{sheet_id}/rows")]
-async fn delete_sheet_rows(
+#[post("/changeset")]
+async fn apply_changeset(
     state: Data<AppState>,
-    sheet_id: web::Path<Uuid>,
-    rows: web::Bytes,
-) -> impl Responder {
-    let sheet_id = sheet_id.into_inner();
-    let rows = match extract::<Vec<Uuid>>(rows) {
-        Ok(rows) => rows,
-        Err(err) => return HttpResponse::InternalServerError().body(err.to_string().into_bytes()),
-    };
-
-    let mut transaction = match state.db.begin().await {
-        Ok(v) => v,
-        Err(err) => {
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
+    user: ReqData<User>,
+    changeset: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let changeset = extract::<SheetChangeset<Uuid, Arc<str>>>(changeset)?;
+    let sheet_id = changeset.sheet_id;
+    let role = if changeset
+        .changes
+        .iter()
+        .any(|change| matches!(change, SheetChange::DeleteRow(_)))
+    {
+        Role::Admin
+    } else {
+        Role::Editor
     };
-
-    for row_id in rows {
-        if let Err(err) = delete_row_by_id(&mut transaction, &sheet_id, row_id).await {
-            transaction.rollback().await.unwrap_or_default();
-            return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
-        }
-    }
-    if let Err(err) = transaction.commit().await {
-        return HttpResponse::InternalServerError().body(err.to_string().into_bytes());
+    check_role(&state, &user, Target::Sheet(&sheet_id), role).await?;
+    let result = apply_sheet_changes(&state, &user.id, changeset).await?;
+    let conflicted = !result.conflicts.is_empty()
+        || result
+            .errors
+            .iter()
+            .flatten()
+            .any(|err| matches!(err, ApiError::Conflict(_)));
+    let mut response = if conflicted {
+        HttpResponse::Conflict()
+    } else {
+        HttpResponse::Ok()
     };
-    HttpResponse::Ok().into()
+    Ok(response.body(compact(&result.to_serial())?))
 }
 
-pub async fn fetch_columns_by_row_id(
+pub async fn fetch_columns_by_rows_ids(
     state: &AppState,
-    row_id: &Uuid,
-) -> Result<HashMap<Arc<str>, Column<Arc<str>>>, Box<dyn Error>> {
+    rows_ids: &[Uuid],
+) -> Result<HashMap<Uuid, HashMap<Arc<str>, Column<Arc<str>>>>, ApiError> {
     let records = query!(
         r#"
-        select header_name,value
-        from columns WHERE row_id = $1"#,
-        row_id,
+        select row_id,header_name,text_value,numeric_value,date_value
+        from columns WHERE row_id = ANY($1)"#,
+        rows_ids,
     )
     .fetch_all(&state.db)
     .await?;
-    let mut map = HashMap::<Arc<str>, Column<Arc<str>>>::new();
+    let mut rows = HashMap::<Uuid, HashMap<Arc<str>, Column<Arc<str>>>>::new();
     for record in records.into_iter() {
-        map.insert(
+        let stored = StoredValue {
+            text_value: record.text_value,
+            numeric_value: record.numeric_value,
+            date_value: record.date_value,
+        };
+        rows.entry(record.row_id).or_default().insert(
             Arc::from(record.header_name),
             Column {
                 is_basic: true,
-                value: serde_json::from_value(record.value)?,
+                value: ColumnValue::try_from(stored)?,
             },
         );
     }
-    Ok(map)
+    Ok(rows)
+}
+
+#[derive(Serialize, Deserialize)]
+struct RowsCursor {
+    headers: Vec<String>,
+    keys: Vec<Option<ColumnValue<Arc<str>>>>,
+    inserted_at: NaiveDateTime,
+    id: Arc<str>,
+}
+
+enum OrderKey {
+    Numeric(Option<BigDecimal>),
+    Date(Option<NaiveDate>),
+    Text(Option<String>),
+    InsertedAt(NaiveDateTime),
+    Id(Uuid),
+}
+
+impl OrderKey {
+    fn is_null(&self) -> bool {
+        match self {
+            Self::Numeric(v) => v.is_none(),
+            Self::Date(v) => v.is_none(),
+            Self::Text(v) => v.is_none(),
+            Self::InsertedAt(_) | Self::Id(_) => false,
+        }
+    }
+
+    fn push_bind(&self, builder: &mut QueryBuilder<Postgres>) {
+        match self {
+            Self::Numeric(v) => builder.push_bind(v.clone()),
+            Self::Date(v) => builder.push_bind(*v),
+            Self::Text(v) => builder.push_bind(v.clone()),
+            Self::InsertedAt(v) => builder.push_bind(*v),
+            Self::Id(v) => builder.push_bind(*v),
+        };
+    }
+}
+
+fn order_expressions(order: &[(String, SortDirection)]) -> Vec<(String, SortDirection)> {
+    let mut expressions = Vec::new();
+    for (i, (_, direction)) in order.iter().enumerate() {
+        expressions.push((format!("p{i}.numeric_value"), *direction));
+        expressions.push((format!("p{i}.date_value"), *direction));
+        expressions.push((format!("p{i}.text_value COLLATE \"C\""), *direction));
+    }
+    expressions.push(("r.inserted_at".to_string(), SortDirection::Ascending));
+    expressions.push(("r.id".to_string(), SortDirection::Ascending));
+    expressions
+}
+
+fn cursor_keys(cursor: RowsCursor) -> Result<Vec<OrderKey>, ApiError> {
+    let id = Uuid::from_str(&cursor.id)
+        .map_err(|err| ApiError::BadRequest(format!("cursor id ({err})")))?;
+    let mut keys = Vec::new();
+    for value in cursor.keys {
+        let StoredValue {
+            text_value,
+            numeric_value,
+            date_value,
+        } = value.map(StoredValue::from).unwrap_or(StoredValue {
+            text_value: None,
+            numeric_value: None,
+            date_value: None,
+        });
+        keys.push(OrderKey::Numeric(numeric_value));
+        keys.push(OrderKey::Date(date_value));
+        keys.push(OrderKey::Text(text_value));
+    }
+    keys.push(OrderKey::InsertedAt(cursor.inserted_at));
+    keys.push(OrderKey::Id(id));
+    Ok(keys)
 }
 
-async fn fetch_rows_ids_by_sheet_id_in_limit(
+// rows strictly after the cursor in the (NULLS LAST) lexicographic order of the expressions
+fn push_after_cursor(
+    builder: &mut QueryBuilder<Postgres>,
+    expressions: &[(String, SortDirection)],
+    keys: &[OrderKey],
+) {
+    builder.push(" AND (FALSE");
+    for (j, ((expression, direction), key)) in expressions.iter().zip(keys).enumerate() {
+        if key.is_null() {
+            continue;
+        }
+        builder.push(" OR (");
+        for ((expression, _), key) in expressions.iter().zip(keys).take(j) {
+            builder.push(format!("{expression} IS NOT DISTINCT FROM "));
+            key.push_bind(builder);
+            builder.push(" AND ");
+        }
+        let operator = match direction {
+            SortDirection::Ascending => ">",
+            SortDirection::Descending => "<",
+        };
+        builder.push(format!("({expression} {operator} "));
+        key.push_bind(builder);
+        builder.push(format!(" OR {expression} IS NULL))"));
+    }
+    builder.push(")");
+}
+
+async fn fetch_rows_page(
     state: &AppState,
     sheet_id: &Uuid,
-    offset: i64,
+    after: Option<RowsCursor>,
     limit: i64,
-) -> Result<Vec<Uuid>, Box<dyn Error>> {
-    let records = query!(
+    order: Vec<(String, SortDirection)>,
+) -> Result<RowsPage<Uuid, Arc<str>>, ApiError> {
+    if order.len() > MAX_ROWS_ORDER_HEADERS {
+        return Err(ApiError::BadRequest(format!(
+            "order headers ({})",
+            order.len()
+        )));
+    }
+    if after.as_ref().is_some_and(|cursor| {
+        cursor.keys.len() != order.len()
+            || !cursor
+                .headers
+                .iter()
+                .eq(order.iter().map(|(header, _)| header))
+    }) {
+        return Err(ApiError::BadRequest("cursor order".to_string()));
+    }
+    let expressions = order_expressions(&order);
+    let mut builder = QueryBuilder::<Postgres>::new(
         r#"
-        select id
-        from rows WHERE sheet_id = $1 ORDER BY insert_date OFFSET $2 LIMIT $3"#,
-        sheet_id,
-        offset,
-        limit,
-    )
-    .fetch_all(&state.db)
-    .await?;
-    Ok(records.into_iter().map(|x| x.id).collect())
+        SELECT r.id,r.version,r.inserted_at,c.header_name,c.text_value,c.numeric_value,c.date_value
+        FROM (SELECT r.id,r.version,r.inserted_at,row_number() OVER (ORDER BY "#,
+    );
+    let mut separated = builder.separated(",");
+    for (expression, direction) in &expressions {
+        separated.push(match direction {
+            SortDirection::Ascending => format!("{expression} ASC NULLS LAST"),
+            SortDirection::Descending => format!("{expression} DESC NULLS LAST"),
+        });
+    }
+    builder.push(") AS position FROM rows r");
+    for (i, (header, _)) in order.iter().enumerate() {
+        builder
+            .push(format!(
+                " LEFT JOIN columns p{i} ON p{i}.row_id = r.id AND p{i}.header_name = "
+            ))
+            .push_bind(header.clone());
+    }
+    builder.push(" WHERE r.sheet_id = ").push_bind(sheet_id);
+    if let Some(cursor) = after {
+        push_after_cursor(&mut builder, &expressions, &cursor_keys(cursor)?);
+    }
+    builder
+        .push(" ORDER BY position LIMIT ")
+        .push_bind(limit)
+        .push(
+            r#") r
+        LEFT JOIN columns c ON c.row_id = r.id
+        ORDER BY r.position"#,
+        );
+    let records = builder
+        .build_query_as::<RowColumnRecord>()
+        .fetch_all(&state.db)
+        .await?;
+    let mut rows = Vec::<Row<Uuid, Arc<str>>>::new();
+    let mut last_inserted_at = None;
+    for (id, version, inserted_at, header, text_value, numeric_value, date_value) in records {
+        if rows.last().map(|row| row.id) != Some(id) {
+            rows.push(Row {
+                id,
+                columns: HashMap::new(),
+                version,
+            });
+            last_inserted_at = Some(inserted_at);
+        }
+        let (Some(header), Some(row)) = (header, rows.last_mut()) else {
+            continue;
+        };
+        let stored = StoredValue {
+            text_value,
+            numeric_value,
+            date_value,
+        };
+        let column = Column {
+            is_basic: true,
+            value: ColumnValue::try_from(stored)?,
+        };
+        row.columns.insert(Arc::from(header), column);
+    }
+    let next = match (rows.last(), last_inserted_at) {
+        (Some(row), Some(inserted_at)) if rows.len() as i64 == limit => {
+            let cursor = RowsCursor {
+                keys: order
+                    .iter()
+                    .map(|(header, _)| {
+                        row.columns
+                            .get(header.as_str())
+                            .map(|column| column.value.clone())
+                    })
+                    .collect(),
+                headers: order.into_iter().map(|(header, _)| header).collect(),
+                inserted_at,
+                id: row.id.to_serial(),
+            };
+            Some(encode_cursor(&cursor)?)
+        }
+        _ => None,
+    };
+    Ok(RowsPage { rows, next })
 }
 
-async fn fetch_sheet_rows_length(state: &AppState, sheet_id: &Uuid) -> Result<i64, Box<dyn Error>> {
+async fn fetch_sheet_rows_length(state: &AppState, sheet_id: &Uuid) -> Result<i64, ApiError> {
     let records = query!(
         r#"
         select count(id) as len
@@ -245,182 +487,284 @@ async fn fetch_sheet_rows_length(state: &AppState, sheet_id: &Uuid) -> Result<i6
 
 pub async fn delete_row_by_id(
     transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
     sheet_id: &Uuid,
     row_id: Uuid,
-) -> Result<(), Box<dyn Error>> {
-    query!(
+) -> Result<(), ApiError> {
+    let columns = query!(
+        r#"
+        SELECT c.header_name,c.text_value,c.numeric_value,c.date_value
+        FROM columns c JOIN rows r ON r.id = c.row_id
+        WHERE r.sheet_id = $1 AND r.id = $2"#,
+        sheet_id,
+        row_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let deleted = query!(
         r#"
         DELETE FROM rows 
         WHERE sheet_id = $1 AND id = $2"#,
         sheet_id,
         row_id,
     )
-    .execute(transaction)
+    .execute(&mut *transaction)
     .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::Conflict(format!("{sheet_id}/{row_id}")));
+    }
+    for column in columns {
+        let old_value = StoredValue {
+            text_value: column.text_value,
+            numeric_value: column.numeric_value,
+            date_value: column.date_value,
+        };
+        let entry = AuditRecord {
+            user_id,
+            sheet_id,
+            row_id: Some(&row_id),
+            header: Some(&column.header_name),
+            action: AuditAction::DeleteColumn,
+            old_value: Some(old_value.into_json()?),
+            new_value: None,
+        };
+        audit::record(&mut *transaction, entry).await?;
+    }
+    let entry = AuditRecord {
+        user_id,
+        sheet_id,
+        row_id: Some(&row_id),
+        header: None,
+        action: AuditAction::DeleteRow,
+        old_value: None,
+        new_value: None,
+    };
+    audit::record(transaction, entry).await?;
     Ok(())
 }
 
 async fn fetch_custom_sheet_by_id(
     state: &AppState,
     id: Uuid,
-) -> Result<Sheet<Uuid, Arc<str>>, Box<dyn Error>> {
+) -> Result<Sheet<Uuid, Arc<str>>, ApiError> {
     let record = query!(
         r#"
         select *
-        from sheets WHERE id = $1"#,
+        from sheets WHERE id = $1 AND deleted_at IS NULL"#,
         id
     )
     .fetch_one(&state.db)
     .await?;
+    let fields = fetch_sheet_fields(state, &id).await?;
     Ok(Sheet {
         id,
         sheet_name: Arc::from(record.sheet_name),
         type_name: Arc::from(record.type_name),
         insert_date: record.insert_date,
+        fields,
         rows: Vec::new(),
+        version: record.version,
     })
 }
 
+async fn fetch_sheet_fields(
+    state: &AppState,
+    sheet_id: &Uuid,
+) -> Result<HashMap<Arc<str>, ColumnValue<Arc<str>>>, ApiError> {
+    let records = query!(
+        r#"
+        SELECT header_name,text_value,numeric_value,date_value
+        FROM sheet_fields WHERE sheet_id = $1"#,
+        sheet_id,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let mut fields = HashMap::new();
+    for record in records {
+        let stored = StoredValue {
+            text_value: record.text_value,
+            numeric_value: record.numeric_value,
+            date_value: record.date_value,
+        };
+        fields.insert(
+            Arc::from(record.header_name),
+            ColumnValue::try_from(stored)?,
+        );
+    }
+    Ok(fields)
+}
+
+fn push_search_filters<'a>(
+    builder: &mut QueryBuilder<'a, Postgres>,
+    params: &'a SearchSheetParams,
+) {
+    builder
+        .push(" FROM sheets s WHERE s.deleted_at IS NULL AND s.type_name = ")
+        .push_bind(&params.sheet_type_name);
+    if let Some(begin) = params.begin {
+        builder.push(" AND s.insert_date >= ").push_bind(begin);
+    }
+    if let Some(end) = params.end {
+        builder.push(" AND s.insert_date <= ").push_bind(end);
+    }
+    if let Some(sheet_name) = &params.sheet_name {
+        builder
+            .push(" AND s.sheet_name_normalized LIKE '%' || normalize_arabic(")
+            .push_bind(sheet_name)
+            .push(") || '%'");
+    }
+}
+
 async fn search_by_params(
     state: &AppState,
     params: SearchSheetParams,
-) -> Result<Vec<Name<Uuid>>, Box<dyn Error>> {
-    let SearchSheetParams {
+) -> Result<SheetsPage<Uuid>, ApiError> {
+    if !(1..=100).contains(&params.limit) || params.offset < 0 {
+        return Err(ApiError::BadRequest(format!(
+            "offset ({}) limit ({})",
+            params.offset, params.limit
+        )));
+    }
+
+    let mut count = QueryBuilder::new("SELECT count(s.id)");
+    push_search_filters(&mut count, &params);
+    let (total,) = count
+        .build_query_as::<(i64,)>()
+        .fetch_one(&state.db)
+        .await?;
+
+    let mut page = QueryBuilder::new("SELECT s.id,s.sheet_name");
+    push_search_filters(&mut page, &params);
+    page.push(match params.sort_by {
+        SheetsSort::Name => " ORDER BY s.sheet_name",
+        SheetsSort::InsertDate => " ORDER BY s.insert_date",
+        SheetsSort::RowsCount => {
+            " ORDER BY (SELECT count(r.id) FROM rows r WHERE r.sheet_id = s.id)"
+        }
+    });
+    page.push(match params.direction {
+        SortDirection::Ascending => " ASC",
+        SortDirection::Descending => " DESC",
+    });
+    page.push(", s.id OFFSET ")
+        .push_bind(params.offset)
+        .push(" LIMIT ")
+        .push_bind(params.limit);
+    let names = page
+        .build_query_as::<(Uuid, String)>()
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .map(|(id, the_name)| Name { id, the_name })
+        .collect();
+
+    Ok(SheetsPage { names, total })
+}
+
+async fn fetch_trashed_sheets(
+    state: &AppState,
+    params: TrashParams,
+) -> Result<Vec<Name<Uuid>>, ApiError> {
+    let TrashParams {
         offset,
         sheet_type_name,
-        begin,
-        end,
-        sheet_name,
     } = params;
-    let names = match (begin, end, sheet_name) {
-        (None, None, None) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 OFFSET $2 LIMIT 5"#,
-                sheet_type_name,
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-        (None, None, Some(sheet_name)) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 AND sheet_name LIKE $2
-				OFFSET $3 LIMIT 5"#,
-                sheet_type_name,
-                format!("%{}%", sheet_name),
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-        (Some(begin), None, None) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 AND insert_date > $2
-				OFFSET $3 LIMIT 5"#,
-                sheet_type_name,
-                begin,
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-        (Some(begin), None, Some(sheet_name)) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 AND
-				(insert_date > $2 AND sheet_name LIKE $3)
-				OFFSET $4 LIMIT 5"#,
-                sheet_type_name,
-                begin,
-                format!("%{}%", sheet_name),
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-        (None, Some(end), None) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 AND insert_date < $2
-				OFFSET $3 LIMIT 5"#,
-                sheet_type_name,
-                end,
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-        (None, Some(end), Some(sheet_name)) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 AND
-				(insert_date < $2 AND sheet_name LIKE $3)
-				OFFSET $4 LIMIT 5"#,
-                sheet_type_name,
-                end,
-                format!("%{}%", sheet_name),
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-        (Some(begin), Some(end), None) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 AND insert_date BETWEEN $2 AND $3
-				OFFSET $4 LIMIT 5"#,
-                sheet_type_name,
-                begin,
-                end,
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-        (Some(begin), Some(end), Some(sheet_name)) => {
-            query_as!(
-                Name,
-                r#"
-				SELECT id,sheet_name as the_name
-				FROM sheets WHERE type_name = $1 AND
-				(insert_date BETWEEN $2 AND $3 AND sheet_name LIKE $4)
-				OFFSET $5 LIMIT 5"#,
-                sheet_type_name,
-                begin,
-                end,
-                format!("%{}%", sheet_name),
-                offset,
-            )
-            .fetch_all(&state.db)
-            .await?
-        }
-    };
+    let names = query_as!(
+        Name,
+        r#"
+        SELECT id,sheet_name as the_name
+        FROM sheets WHERE type_name = $1 AND deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC OFFSET $2 LIMIT 5"#,
+        sheet_type_name,
+        offset,
+    )
+    .fetch_all(&state.db)
+    .await?;
     Ok(names)
 }
 
-async fn save_row<'a>(
+async fn set_sheet_trashed(
+    state: &AppState,
+    user_id: &Uuid,
+    id: &Uuid,
+    trashed: bool,
+) -> Result<(), ApiError> {
+    let mut transaction = state.db.begin().await?;
+    let updated = if trashed {
+        query!(
+            r#"
+            UPDATE sheets SET deleted_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND deleted_at IS NULL"#,
+            id,
+        )
+        .execute(&mut transaction)
+        .await?
+    } else {
+        query!(
+            r#"
+            UPDATE sheets SET deleted_at = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL"#,
+            id,
+        )
+        .execute(&mut transaction)
+        .await?
+    };
+    if updated.rows_affected() == 0 {
+        return Err(ApiError::NotFound(id.to_string()));
+    }
+    let action = if trashed {
+        AuditAction::DeleteSheet
+    } else {
+        AuditAction::RestoreSheet
+    };
+    let entry = AuditRecord {
+        user_id,
+        sheet_id: id,
+        row_id: None,
+        header: None,
+        action,
+        old_value: None,
+        new_value: None,
+    };
+    audit::record(&mut transaction, entry).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn purge_trashed_sheet(state: &AppState, user_id: &Uuid, id: &Uuid) -> Result<(), ApiError> {
+    let mut transaction = state.db.begin().await?;
+    let deleted = query!(
+        r#"
+        DELETE FROM sheets
+        WHERE id = $1 AND deleted_at IS NOT NULL"#,
+        id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(id.to_string()));
+    }
+    let entry = AuditRecord {
+        user_id,
+        sheet_id: id,
+        row_id: None,
+        header: None,
+        action: AuditAction::PurgeSheet,
+        old_value: None,
+        new_value: None,
+    };
+    audit::record(&mut transaction, entry).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn save_row(
     transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
     sheet_id: &Uuid,
     row: Row<Uuid, Arc<str>>,
-) -> Result<(), Box<dyn Error>> {
-    let Row { id, columns } = row;
+) -> Result<(), ApiError> {
+    let Row { id, columns, .. } = row;
     query!(
         r#"
 	INSERT INTO rows(id,sheet_id)
@@ -430,21 +774,45 @@ async fn save_row<'a>(
     )
     .execute(&mut *transaction)
     .await?;
+    let entry = AuditRecord {
+        user_id,
+        sheet_id,
+        row_id: Some(&id),
+        header: None,
+        action: AuditAction::AddRow,
+        old_value: None,
+        new_value: None,
+    };
+    audit::record(&mut *transaction, entry).await?;
     for (header_name, column) in columns {
         if column.is_basic {
-            save_cloumn_value(&mut *transaction, &id, header_name, column.value).await?;
+            save_cloumn_value(
+                &mut *transaction,
+                user_id,
+                sheet_id,
+                &id,
+                header_name,
+                column.value,
+            )
+            .await?;
         }
     }
     Ok(())
 }
 
-async fn save_sheet(state: &AppState, sheet: Sheet<Uuid, Arc<str>>) -> Result<(), Box<dyn Error>> {
+async fn save_sheet(
+    state: &AppState,
+    user_id: &Uuid,
+    sheet: Sheet<Uuid, Arc<str>>,
+) -> Result<(), ApiError> {
     let Sheet {
         id,
         sheet_name,
         type_name,
         insert_date,
+        fields,
         rows,
+        ..
     } = sheet;
     let mut transaction = state.db.begin().await?;
     query!(
@@ -458,8 +826,27 @@ async fn save_sheet(state: &AppState, sheet: Sheet<Uuid, Arc<str>>) -> Result<()
     )
     .execute(&mut transaction)
     .await?;
+    let entry = AuditRecord {
+        user_id,
+        sheet_id: &id,
+        row_id: None,
+        header: None,
+        action: AuditAction::CreateSheet,
+        old_value: None,
+        new_value: Some(serde_json::json!(ColumnValue::String(sheet_name))),
+    };
+    if let Err(err) = audit::record(&mut transaction, entry).await {
+        transaction.rollback().await.unwrap_or_default();
+        return Err(err);
+    }
+    for (header, value) in fields {
+        if let Err(err) = set_sheet_field(&mut transaction, user_id, &id, header, value).await {
+            transaction.rollback().await.unwrap_or_default();
+            return Err(err);
+        };
+    }
     for row in rows {
-        if let Err(err) = save_row(&mut transaction, &id, row).await {
+        if let Err(err) = save_row(&mut transaction, user_id, &id, row).await {
             transaction.rollback().await.unwrap_or_default();
             return Err(err);
         };
@@ -469,15 +856,743 @@ async fn save_sheet(state: &AppState, sheet: Sheet<Uuid, Arc<str>>) -> Result<()
     Ok(())
 }
 
-async fn update_sheet_name(state: &AppState, name: Name<Uuid>) -> Result<(), Box<dyn Error>> {
+async fn update_sheet_name(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+    name: Name<Uuid>,
+) -> Result<(), ApiError> {
     let Name { id, the_name } = name;
+    let record = query!(
+        r#"
+        SELECT sheet_name FROM sheets WHERE id = $1 FOR UPDATE;"#,
+        id,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
     query!(
         r#"
         UPDATE sheets SET sheet_name = $2 WHERE id = $1;"#,
         id,
         the_name,
     )
-    .execute(&state.db)
+    .execute(&mut *transaction)
+    .await?;
+    let entry = AuditRecord {
+        user_id,
+        sheet_id: &id,
+        row_id: None,
+        header: None,
+        action: AuditAction::RenameSheet,
+        old_value: Some(serde_json::json!(ColumnValue::String(record.sheet_name))),
+        new_value: Some(serde_json::json!(ColumnValue::String(the_name))),
+    };
+    audit::record(transaction, entry).await?;
+    Ok(())
+}
+
+async fn apply_sheet_changes(
+    state: &AppState,
+    user_id: &Uuid,
+    changeset: SheetChangeset<Uuid, Arc<str>>,
+) -> Result<ChangesetResult<Uuid, Arc<str>>, ApiError> {
+    let SheetChangeset {
+        sheet_id,
+        changes,
+        sheet_version,
+        rows_versions,
+    } = changeset;
+    let schema = SheetSchema::of_sheet(state, sheet_id).await?;
+    let mut transaction = state.db.begin().await?;
+    let conflicts = find_conflicts(
+        &mut transaction,
+        &sheet_id,
+        sheet_version,
+        rows_versions,
+        &changes,
+    )
+    .await?;
+    if !conflicts.is_empty() {
+        transaction.rollback().await?;
+        return Ok(ChangesetResult {
+            applied: false,
+            errors: Vec::new(),
+            conflicts,
+            sheet_version,
+            rows_versions: Vec::new(),
+        });
+    }
+
+    let touched_rows = changes
+        .iter()
+        .filter_map(|change| match change {
+            SheetChange::AddRow(row) => Some(row.id),
+            SheetChange::UpdateColumn { row_id, .. }
+            | SheetChange::AddColumn { row_id, .. }
+            | SheetChange::DeleteColumn { row_id, .. } => Some(*row_id),
+            SheetChange::Rename(_)
+            | SheetChange::DeleteRow(_)
+            | SheetChange::SetField { .. }
+            | SheetChange::DeleteField(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let new_name = changes.iter().rev().find_map(|change| match change {
+        SheetChange::Rename(name) => Some(name.clone()),
+        _ => None,
+    });
+    let fields_changed = changes.iter().any(|change| {
+        matches!(
+            change,
+            SheetChange::SetField { .. } | SheetChange::DeleteField(_)
+        )
+    });
+    let added_rows = changes
+        .iter()
+        .filter_map(|change| match change {
+            SheetChange::AddRow(row) => Some(row.id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let deleted_rows = changes
+        .iter()
+        .filter_map(|change| match change {
+            SheetChange::DeleteRow(row_id) => Some(*row_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut errors = Vec::with_capacity(changes.len());
+    for change in changes {
+        if let Err(err) = schema.check_change(&change) {
+            errors.push(Some(err));
+            continue;
+        }
+        let mut savepoint = (&mut transaction).begin().await?;
+        match apply_sheet_change(&mut savepoint, user_id, &sheet_id, change).await {
+            Ok(()) => {
+                savepoint.commit().await?;
+                errors.push(None);
+            }
+            Err(err) => {
+                savepoint.rollback().await?;
+                errors.push(Some(err));
+            }
+        }
+    }
+    let applied = errors.iter().all(Option::is_none);
+    if !applied {
+        transaction.rollback().await?;
+        return Ok(ChangesetResult {
+            applied,
+            errors,
+            conflicts,
+            sheet_version,
+            rows_versions: Vec::new(),
+        });
+    }
+    let sheet_version = query!(
+        r#"
+        SELECT version FROM sheets WHERE id = $1"#,
+        sheet_id,
+    )
+    .fetch_one(&mut transaction)
+    .await?
+    .version;
+    let rows_versions = query!(
+        r#"
+        SELECT id,version FROM rows WHERE sheet_id = $1 AND id = ANY($2)"#,
+        sheet_id,
+        &touched_rows,
+    )
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|record| (record.id, record.version))
+    .collect::<Vec<_>>();
+    transaction.commit().await?;
+
+    if let Some(name) = new_name {
+        let version = sheet_version;
+        publish(
+            state,
+            user_id,
+            sheet_id,
+            SheetEventKind::Renamed { name, version },
+        );
+    }
+    if fields_changed {
+        let version = sheet_version;
+        publish(
+            state,
+            user_id,
+            sheet_id,
+            SheetEventKind::FieldsUpdated { version },
+        );
+    }
+    let updated_rows = rows_versions
+        .iter()
+        .filter(|(id, _)| !added_rows.contains(id))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !added_rows.is_empty() {
+        publish(
+            state,
+            user_id,
+            sheet_id,
+            SheetEventKind::RowsAdded(added_rows),
+        );
+    }
+    if !deleted_rows.is_empty() {
+        publish(
+            state,
+            user_id,
+            sheet_id,
+            SheetEventKind::RowsDeleted(deleted_rows),
+        );
+    }
+    if !updated_rows.is_empty() {
+        publish(
+            state,
+            user_id,
+            sheet_id,
+            SheetEventKind::RowsUpdated(updated_rows),
+        );
+    }
+    Ok(ChangesetResult {
+        applied,
+        errors,
+        conflicts,
+        sheet_version,
+        rows_versions,
+    })
+}
+
+async fn find_conflicts(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    sheet_id: &Uuid,
+    sheet_version: i64,
+    rows_versions: Vec<(Uuid, i64)>,
+    changes: &[SheetChange<Uuid, Arc<str>>],
+) -> Result<Vec<Conflict<Uuid, Arc<str>>>, ApiError> {
+    check_rows_versions(&rows_versions, changes)?;
+    let sheet = query!(
+        r#"
+        SELECT sheet_name,version FROM sheets WHERE id = $1 FOR UPDATE"#,
+        sheet_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let ids = rows_versions.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let current = query!(
+        r#"
+        SELECT id,version FROM rows WHERE sheet_id = $1 AND id = ANY($2) FOR UPDATE"#,
+        sheet_id,
+        &ids,
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|record| (record.id, record.version))
+    .collect::<HashMap<_, _>>();
+    // a row another user deleted is stale as well, it keeps the version the client sent
+    let stale = rows_versions
+        .into_iter()
+        .filter_map(|(id, version)| match current.get(&id) {
+            Some(current) if *current != version => Some((id, *current)),
+            Some(_) => None,
+            None => Some((id, version)),
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut conflicts = Vec::new();
+    for change in changes {
+        let conflict = match change {
+            SheetChange::Rename(name) if sheet.version != sheet_version => Conflict {
+                target: ConflictTarget::SheetName,
+                mine: Some(ColumnValue::String(name.clone())),
+                theirs: Some(ColumnValue::String(Arc::from(sheet.sheet_name.as_str()))),
+                version: sheet.version,
+            },
+            SheetChange::SetField { header, value } if sheet.version != sheet_version => Conflict {
+                target: ConflictTarget::Field(header.clone()),
+                mine: Some(value.clone()),
+                theirs: fetch_field_value(transaction, sheet_id, header).await?,
+                version: sheet.version,
+            },
+            SheetChange::DeleteField(header) if sheet.version != sheet_version => Conflict {
+                target: ConflictTarget::Field(header.clone()),
+                mine: None,
+                theirs: fetch_field_value(transaction, sheet_id, header).await?,
+                version: sheet.version,
+            },
+            SheetChange::DeleteRow(row_id) => {
+                let Some(version) = stale.get(row_id) else {
+                    continue;
+                };
+                Conflict {
+                    target: ConflictTarget::Row(*row_id),
+                    mine: None,
+                    theirs: None,
+                    version: *version,
+                }
+            }
+            SheetChange::UpdateColumn {
+                row_id,
+                header,
+                value,
+            }
+            | SheetChange::AddColumn {
+                row_id,
+                header,
+                value,
+            } => {
+                let Some(version) = stale.get(row_id) else {
+                    continue;
+                };
+                Conflict {
+                    target: ConflictTarget::Cell {
+                        row_id: *row_id,
+                        header: header.clone(),
+                    },
+                    mine: Some(value.clone()),
+                    theirs: fetch_column_value(transaction, row_id, header).await?,
+                    version: *version,
+                }
+            }
+            SheetChange::DeleteColumn { row_id, header } => {
+                let Some(version) = stale.get(row_id) else {
+                    continue;
+                };
+                Conflict {
+                    target: ConflictTarget::Cell {
+                        row_id: *row_id,
+                        header: header.clone(),
+                    },
+                    mine: None,
+                    theirs: fetch_column_value(transaction, row_id, header).await?,
+                    version: *version,
+                }
+            }
+            _ => continue,
+        };
+        conflicts.push(conflict);
+    }
+    Ok(conflicts)
+}
+
+// every existing row the changeset touches must come with the version the client edited
+fn check_rows_versions(
+    rows_versions: &[(Uuid, i64)],
+    changes: &[SheetChange<Uuid, Arc<str>>],
+) -> Result<(), ApiError> {
+    let added = changes
+        .iter()
+        .filter_map(|change| match change {
+            SheetChange::AddRow(row) => Some(row.id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut missing = Vec::<Uuid>::new();
+    for change in changes {
+        let row_id = match change {
+            SheetChange::DeleteRow(row_id)
+            | SheetChange::UpdateColumn { row_id, .. }
+            | SheetChange::AddColumn { row_id, .. }
+            | SheetChange::DeleteColumn { row_id, .. } => row_id,
+            _ => continue,
+        };
+        let versioned = rows_versions.iter().any(|(id, _)| id == row_id);
+        if !versioned && !added.contains(row_id) && !missing.contains(row_id) {
+            missing.push(*row_id);
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(ApiError::MissingVersion(
+        missing.iter().map(|row_id| row_id.to_string()).collect(),
+    ))
+}
+
+async fn fetch_column_value(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    row_id: &Uuid,
+    header: &str,
+) -> Result<Option<ColumnValue<Arc<str>>>, ApiError> {
+    let record = query!(
+        r#"
+        SELECT text_value,numeric_value,date_value
+        FROM columns WHERE row_id = $1 AND header_name = $2"#,
+        row_id,
+        header,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    match record {
+        Some(record) => {
+            let stored = StoredValue {
+                text_value: record.text_value,
+                numeric_value: record.numeric_value,
+                date_value: record.date_value,
+            };
+            Ok(Some(ColumnValue::try_from(stored)?))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn fetch_field_value(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    sheet_id: &Uuid,
+    header: &str,
+) -> Result<Option<ColumnValue<Arc<str>>>, ApiError> {
+    let record = query!(
+        r#"
+        SELECT text_value,numeric_value,date_value
+        FROM sheet_fields WHERE sheet_id = $1 AND header_name = $2"#,
+        sheet_id,
+        header,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    match record {
+        Some(record) => {
+            let stored = StoredValue {
+                text_value: record.text_value,
+                numeric_value: record.numeric_value,
+                date_value: record.date_value,
+            };
+            Ok(Some(ColumnValue::try_from(stored)?))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn set_sheet_field(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+    sheet_id: &Uuid,
+    header: Arc<str>,
+    value: ColumnValue<Arc<str>>,
+) -> Result<(), ApiError> {
+    let old_value = fetch_field_value(transaction, sheet_id, &header).await?;
+    let new_value = serde_json::json!(value);
+    let StoredValue {
+        text_value,
+        numeric_value,
+        date_value,
+    } = StoredValue::from(value);
+    query!(
+        r#"
+        INSERT INTO sheet_fields(sheet_id,header_name,text_value,numeric_value,date_value)
+        VALUES($1,$2,$3,$4,$5)
+        ON CONFLICT (sheet_id,header_name) DO UPDATE
+            SET text_value = $3, numeric_value = $4, date_value = $5"#,
+        sheet_id,
+        header.to_string(),
+        text_value,
+        numeric_value,
+        date_value,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let entry = AuditRecord {
+        user_id,
+        sheet_id,
+        row_id: None,
+        header: Some(&header),
+        action: AuditAction::SetField,
+        old_value: old_value.map(|value| serde_json::json!(value)),
+        new_value: Some(new_value),
+    };
+    audit::record(transaction, entry).await?;
+    Ok(())
+}
+
+async fn delete_sheet_field(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+    sheet_id: &Uuid,
+    header: Arc<str>,
+) -> Result<(), ApiError> {
+    let deleted = query!(
+        r#"
+        DELETE FROM sheet_fields WHERE sheet_id = $1 AND header_name = $2
+        RETURNING text_value,numeric_value,date_value"#,
+        sheet_id,
+        header.to_string(),
+    )
+    .fetch_optional(&mut *transaction)
     .await?;
+    let Some(record) = deleted else {
+        return Ok(());
+    };
+    let old_value = StoredValue {
+        text_value: record.text_value,
+        numeric_value: record.numeric_value,
+        date_value: record.date_value,
+    };
+    let entry = AuditRecord {
+        user_id,
+        sheet_id,
+        row_id: None,
+        header: Some(&header),
+        action: AuditAction::DeleteField,
+        old_value: Some(old_value.into_json()?),
+        new_value: None,
+    };
+    audit::record(transaction, entry).await?;
     Ok(())
 }
+
+async fn apply_sheet_change(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+    sheet_id: &Uuid,
+    change: SheetChange<Uuid, Arc<str>>,
+) -> Result<(), ApiError> {
+    match change {
+        SheetChange::Rename(the_name) => {
+            let name = Name {
+                id: *sheet_id,
+                the_name: the_name.to_string(),
+            };
+            update_sheet_name(transaction, user_id, name).await
+        }
+        SheetChange::DeleteRow(row_id) => {
+            delete_row_by_id(transaction, user_id, sheet_id, row_id).await
+        }
+        SheetChange::AddRow(row) => save_row(transaction, user_id, sheet_id, row).await,
+        SheetChange::UpdateColumn {
+            row_id,
+            header,
+            value,
+        } => {
+            let ids = ColumnId {
+                sheet_id: *sheet_id,
+                row_id,
+                header,
+            };
+            update_column_by_column_id(transaction, user_id, ids, value).await
+        }
+        SheetChange::AddColumn {
+            row_id,
+            header,
+            value,
+        } => save_cloumn_value(transaction, user_id, sheet_id, &row_id, header, value).await,
+        SheetChange::DeleteColumn { row_id, header } => {
+            let ids = ColumnId {
+                sheet_id: *sheet_id,
+                row_id,
+                header,
+            };
+            delete_column_by_column_id(transaction, user_id, ids).await
+        }
+        SheetChange::SetField { header, value } => {
+            set_sheet_field(transaction, user_id, sheet_id, header, value).await
+        }
+        SheetChange::DeleteField(header) => {
+            delete_sheet_field(transaction, user_id, sheet_id, header).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        app_state, count_statements, insert_row, insert_sheet, insert_sheet_type,
+    };
+    use sqlx::PgPool;
+
+    async fn sheet_with_rows(state: &AppState, len: i32) -> Uuid {
+        let sheet_id = insert_sheet(state, "type").await;
+        for value in 0..len {
+            insert_row(state, &sheet_id, value).await;
+        }
+        sheet_id
+    }
+
+    fn value_order() -> Vec<(String, SortDirection)> {
+        vec![("value".to_string(), SortDirection::Descending)]
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn rows_page_statements_do_not_grow_with_rows(pool: PgPool) {
+        let state = app_state(pool);
+        let small = sheet_with_rows(&state, 1).await;
+        let large = sheet_with_rows(&state, 40).await;
+
+        let (page, small_count) =
+            count_statements(fetch_rows_page(&state, &small, None, 20, value_order())).await;
+        let page = page.unwrap();
+        assert_eq!(page.rows.len(), 1);
+        assert_eq!(page.rows[0].columns.len(), 2);
+        assert!(page.next.is_none());
+
+        let (page, large_count) =
+            count_statements(fetch_rows_page(&state, &large, None, 20, value_order())).await;
+        let page = page.unwrap();
+        assert_eq!(page.rows.len(), 20);
+        assert!(page.rows.iter().all(|row| row.columns.len() == 2));
+        assert_eq!(small_count, large_count);
+
+        let cursor = decode_cursor::<RowsCursor>(&page.next.unwrap()).unwrap();
+        let (page, next_count) = count_statements(fetch_rows_page(
+            &state,
+            &large,
+            Some(cursor),
+            20,
+            value_order(),
+        ))
+        .await;
+        assert_eq!(page.unwrap().rows.len(), 20);
+        assert_eq!(small_count, next_count);
+    }
+
+    #[test]
+    fn rows_cursor_survives_encoding() {
+        let id = Uuid::new_v4();
+        let inserted_at = NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(10, 30, 0)
+            .unwrap();
+        let keys = vec![
+            Some(ColumnValue::Decimal(BigDecimal::from(7))),
+            Some(ColumnValue::String(Arc::from("text"))),
+            Some(ColumnValue::Date(inserted_at.date())),
+            None,
+        ];
+        let cursor = RowsCursor {
+            headers: vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "d".to_string(),
+            ],
+            keys: keys.clone(),
+            inserted_at,
+            id: id.to_serial(),
+        };
+        let decoded = decode_cursor::<RowsCursor>(&encode_cursor(&cursor).unwrap()).unwrap();
+        assert_eq!(decoded.headers, cursor.headers);
+        assert_eq!(decoded.keys, keys);
+        assert_eq!(decoded.inserted_at, inserted_at);
+        assert_eq!(decoded.id, cursor.id);
+        assert!(matches!(
+            cursor_keys(decoded).unwrap().last(),
+            Some(OrderKey::Id(key)) if *key == id
+        ));
+        assert!(decode_cursor::<RowsCursor>("zz").is_err());
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn rows_pages_follow_each_other(pool: PgPool) {
+        let state = app_state(pool);
+        let sheet_id = sheet_with_rows(&state, 45).await;
+        let mut values = Vec::new();
+        let mut after = None;
+        loop {
+            let page = fetch_rows_page(&state, &sheet_id, after, 20, value_order())
+                .await
+                .unwrap();
+            for row in &page.rows {
+                match row.columns.get("value").map(|column| &column.value) {
+                    Some(ColumnValue::Decimal(value)) => values.push(value.clone()),
+                    other => panic!("unexpected value {other:?}"),
+                }
+            }
+            let Some(next) = page.next else {
+                break;
+            };
+            after = Some(decode_cursor::<RowsCursor>(&next).unwrap());
+        }
+        let expected = (0..45).rev().map(BigDecimal::from).collect::<Vec<_>>();
+        assert_eq!(values, expected);
+    }
+
+    fn update_value(row_id: Uuid, value: i32) -> SheetChange<Uuid, Arc<str>> {
+        SheetChange::UpdateColumn {
+            row_id,
+            header: Arc::from("value"),
+            value: ColumnValue::Decimal(BigDecimal::from(value)),
+        }
+    }
+
+    async fn row_version(state: &AppState, row_id: &Uuid) -> i64 {
+        query!("SELECT version FROM rows WHERE id = $1", row_id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap()
+            .version
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn changeset_requires_touched_rows_versions(pool: PgPool) {
+        let state = app_state(pool);
+        insert_sheet_type(&state, "type").await;
+        let sheet_id = insert_sheet(&state, "type").await;
+        let row_id = insert_row(&state, &sheet_id, 1).await;
+        let changeset = SheetChangeset {
+            sheet_id,
+            changes: vec![update_value(row_id, 2)],
+            sheet_version: 0,
+            rows_versions: Vec::new(),
+        };
+        let result = apply_sheet_changes(&state, &Uuid::new_v4(), changeset).await;
+        assert_eq!(
+            result.err(),
+            Some(ApiError::MissingVersion(vec![row_id.to_string()]))
+        );
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn changeset_on_a_deleted_row_conflicts(pool: PgPool) {
+        let state = app_state(pool);
+        insert_sheet_type(&state, "type").await;
+        let sheet_id = insert_sheet(&state, "type").await;
+        let row_id = insert_row(&state, &sheet_id, 1).await;
+        let version = row_version(&state, &row_id).await;
+        query!("DELETE FROM rows WHERE id = $1", row_id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+
+        let changeset = SheetChangeset {
+            sheet_id,
+            changes: vec![update_value(row_id, 2), SheetChange::DeleteRow(row_id)],
+            sheet_version: 0,
+            rows_versions: vec![(row_id, version)],
+        };
+        let result = apply_sheet_changes(&state, &Uuid::new_v4(), changeset)
+            .await
+            .unwrap();
+        assert!(!result.applied);
+        assert_eq!(result.conflicts.len(), 2);
+        assert_eq!(
+            result.conflicts[0].target,
+            ConflictTarget::Cell {
+                row_id,
+                header: Arc::from("value"),
+            }
+        );
+        assert_eq!(result.conflicts[1].target, ConflictTarget::Row(row_id));
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn updating_a_missing_row_is_a_conflict(pool: PgPool) {
+        let state = app_state(pool);
+        let sheet_id = insert_sheet(&state, "type").await;
+        let mut transaction = state.db.begin().await.unwrap();
+        let ids = ColumnId {
+            sheet_id,
+            row_id: Uuid::new_v4(),
+            header: Arc::from("value"),
+        };
+        let value = ColumnValue::Decimal(BigDecimal::from(2));
+        let updated =
+            update_column_by_column_id(&mut transaction, &Uuid::new_v4(), ids, value).await;
+        assert!(matches!(updated, Err(ApiError::Conflict(_))));
+        let deleted =
+            delete_row_by_id(&mut transaction, &Uuid::new_v4(), &sheet_id, Uuid::new_v4()).await;
+        assert!(matches!(deleted, Err(ApiError::Conflict(_))));
+    }
+}
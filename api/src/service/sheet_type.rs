@@ -0,0 +1,601 @@
+use actix_web::{
+    delete, get, post, put,
+    web::{self, Data, ReqData},
+    HttpResponse, Scope,
+};
+use client_models::{
+    calculation_order, ColumnConfig, ColumnProps, Config, ConfigValue, HeaderGetter,
+    IdentityDiffsOps, ImportConfig, OperationConfig, ResultType, RowIdentity, SheetConfig,
+    SheetTypeConfig, ValueType,
+};
+use sqlx::{query, Transaction};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    auth::{require_admin, User},
+    service::{compact, extract},
+    AppState,
+};
+
+use models::ApiError;
+
+pub fn scope() -> Scope {
+    web::scope("/sheet-types")
+        .service(get_sheet_types)
+        .service(get_sheet_type)
+        .service(create_sheet_type)
+        .service(update_sheet_type)
+        .service(delete_sheet_type)
+}
+
+#[get("/")]
+async fn get_sheet_types(state: Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let sheet_types = fetch_sheet_types(&state, None).await?;
+    Ok(HttpResponse::Ok().body(compact(&sheet_types)?))
+}
+
+#[get("/{name}")]
+async fn get_sheet_type(
+    state: Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+    let Some(sheet_type) = fetch_sheet_types(&state, Some(&name)).await?.pop() else {
+        return Err(ApiError::NotFound(name));
+    };
+    Ok(HttpResponse::Ok().body(compact(&sheet_type)?))
+}
+
+#[post("/")]
+async fn create_sheet_type(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    sheet_type: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&user)?;
+    let sheet_type = extract::<SheetTypeConfig>(sheet_type)?;
+    check_sheet_type(&sheet_type)?;
+    let mut transaction = state.db.begin().await?;
+    save_sheet_type(&mut transaction, sheet_type).await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+#[put("/{name}")]
+async fn update_sheet_type(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    path: web::Path<String>,
+    sheet_type: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&user)?;
+    let name = path.into_inner();
+    let sheet_type = extract::<SheetTypeConfig>(sheet_type)?;
+    if *sheet_type.sheet.sheet_type_name != name {
+        return Err(ApiError::BadRequest(format!(
+            "sheet type name can not change ({name})"
+        )));
+    }
+    check_sheet_type(&sheet_type)?;
+    let mut transaction = state.db.begin().await?;
+    if !remove_sheet_type(&mut transaction, &name).await? {
+        return Err(ApiError::NotFound(name));
+    }
+    save_sheet_type(&mut transaction, sheet_type).await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+#[delete("/{name}")]
+async fn delete_sheet_type(
+    state: Data<AppState>,
+    user: ReqData<User>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&user)?;
+    let name = path.into_inner();
+    let mut transaction = state.db.begin().await?;
+    let sheets = query!(
+        r#"
+        SELECT count(*) AS "count!" FROM sheets WHERE type_name = $1"#,
+        name,
+    )
+    .fetch_one(&mut transaction)
+    .await?
+    .count;
+    if sheets != 0 {
+        return Err(ApiError::Conflict(format!("{name} ({sheets})")));
+    }
+    if !remove_sheet_type(&mut transaction, &name).await? {
+        return Err(ApiError::NotFound(name));
+    }
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().into())
+}
+
+fn check_sheet_type(sheet_type: &SheetTypeConfig) -> Result<(), ApiError> {
+    let SheetConfig {
+        sheet_type_name,
+        row,
+        ..
+    } = &sheet_type.sheet;
+    if sheet_type_name.trim().is_empty() {
+        return Err(ApiError::BadRequest(String::from("empty sheet type name")));
+    }
+    let mut headers = HashSet::new();
+    for value in row {
+        let header = value.clone().get_header();
+        if !headers.insert(header.clone()) {
+            return Err(ApiError::BadRequest(format!(
+                "duplicated header ({header})"
+            )));
+        }
+    }
+    let calculated = row
+        .iter()
+        .filter_map(|value| match value {
+            ConfigValue::Basic(_) => None,
+            ConfigValue::Calculated(column) => Some(column.clone()),
+        })
+        .collect();
+    calculation_order(calculated).map_err(|cycle| ApiError::BadRequest(cycle.to_string()))?;
+    Ok(())
+}
+
+fn column_parts(column: ColumnConfig) -> (&'static str, ColumnProps) {
+    match column {
+        ColumnConfig::String(props) => ("string", props),
+        ColumnConfig::Decimal(props) => ("decimal", props),
+        ColumnConfig::Date(props) => ("date", props),
+    }
+}
+
+fn column_from_parts(value_type: &str, props: ColumnProps) -> Result<ColumnConfig, ApiError> {
+    match value_type {
+        "string" => Ok(ColumnConfig::String(props)),
+        "decimal" => Ok(ColumnConfig::Decimal(props)),
+        "date" => Ok(ColumnConfig::Date(props)),
+        _ => Err(ApiError::Internal(format!(
+            "unknown value type ({value_type})"
+        ))),
+    }
+}
+
+fn result_type_part(result_type: ResultType) -> &'static str {
+    match result_type {
+        ResultType::Decimal => "decimal",
+        ResultType::String => "string",
+        ResultType::Date => "date",
+    }
+}
+
+fn result_type_from_part(result_type: &str) -> Result<ResultType, ApiError> {
+    match result_type {
+        "decimal" => Ok(ResultType::Decimal),
+        "string" => Ok(ResultType::String),
+        "date" => Ok(ResultType::Date),
+        _ => Err(ApiError::Internal(format!(
+            "unknown result type ({result_type})"
+        ))),
+    }
+}
+
+fn identity_op_parts(op: IdentityDiffsOps) -> (&'static str, Option<i32>) {
+    match op {
+        IdentityDiffsOps::Sum => ("sum", None),
+        IdentityDiffsOps::Prod => ("prod", None),
+        IdentityDiffsOps::Max => ("max", None),
+        IdentityDiffsOps::Min => ("min", None),
+        IdentityDiffsOps::Nth(n) => ("nth", Some(n as i32)),
+    }
+}
+
+fn identity_op_from_parts(op: &str, nth: Option<i32>) -> Result<IdentityDiffsOps, ApiError> {
+    match (op, nth) {
+        ("sum", _) => Ok(IdentityDiffsOps::Sum),
+        ("prod", _) => Ok(IdentityDiffsOps::Prod),
+        ("max", _) => Ok(IdentityDiffsOps::Max),
+        ("min", _) => Ok(IdentityDiffsOps::Min),
+        ("nth", Some(n)) => Ok(IdentityDiffsOps::Nth(n as usize)),
+        _ => Err(ApiError::Internal(format!("unknown identity op ({op})"))),
+    }
+}
+
+// fills a server without sheet types from a config file, once any sheet type
+// exists the seed is ignored so admins edits are never overwritten
+pub async fn seed_sheet_types(state: &AppState, config: Config) -> Result<usize, ApiError> {
+    let mut transaction = state.db.begin().await?;
+    let count = query!(
+        r#"
+        SELECT count(*) AS "count!" FROM sheet_types"#,
+    )
+    .fetch_one(&mut transaction)
+    .await?
+    .count;
+    if count != 0 {
+        return Ok(0);
+    }
+    let sheet_types = Vec::<SheetTypeConfig>::from(config);
+    let seeded = sheet_types.len();
+    for sheet_type in sheet_types {
+        check_sheet_type(&sheet_type)?;
+        save_sheet_type(&mut transaction, sheet_type).await?;
+    }
+    transaction.commit().await?;
+    Ok(seeded)
+}
+
+async fn remove_sheet_type(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    name: &str,
+) -> Result<bool, ApiError> {
+    let deleted = query!(
+        r#"
+        DELETE FROM sheet_types WHERE name = $1"#,
+        name,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(deleted.rows_affected() != 0)
+}
+
+async fn save_sheet_type(
+    transaction: &mut Transaction<'_, sqlx::Postgres>,
+    sheet_type: SheetTypeConfig,
+) -> Result<(), ApiError> {
+    let SheetTypeConfig {
+        priorities,
+        sheet:
+            SheetConfig {
+                sheet_type_name,
+                importing:
+                    ImportConfig {
+                        main_entry,
+                        repeated_entry,
+                        unique,
+                        repeated,
+                        primary,
+                    },
+                row,
+                row_identity: RowIdentity { id, diff_ops },
+                strict_formulas,
+            },
+    } = sheet_type;
+    let name = sheet_type_name.to_string();
+    let priorities = priorities.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    query!(
+        r#"
+        INSERT INTO sheet_types(name,priorities,import_main_entry,import_repeated_entry,strict_formulas)
+        VALUES($1,$2,$3,$4,$5)"#,
+        name,
+        &priorities,
+        &main_entry,
+        &repeated_entry,
+        strict_formulas,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    for (position, value) in row.into_iter().enumerate() {
+        let position = position as i32;
+        match value {
+            ConfigValue::Basic(column) => {
+                let (
+                    value_type,
+                    ColumnProps {
+                        header,
+                        is_completable,
+                    },
+                ) = column_parts(column);
+                query!(
+                    r#"
+                    INSERT INTO sheet_type_columns(type_name,position,header,value_type,is_completable)
+                    VALUES($1,$2,$3,$4,$5)"#,
+                    name,
+                    position,
+                    header,
+                    value_type,
+                    is_completable,
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+            ConfigValue::Calculated(OperationConfig {
+                header,
+                value,
+                result_type,
+            }) => {
+                let operation = serde_json::to_value(value)?;
+                query!(
+                    r#"
+                    INSERT INTO sheet_type_calculated_columns(type_name,position,header,operation,result_type)
+                    VALUES($1,$2,$3,$4,$5)"#,
+                    name,
+                    position,
+                    header,
+                    operation,
+                    result_type_part(result_type),
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+        }
+    }
+
+    for (section, mappings) in [
+        ("unique", unique),
+        ("repeated", repeated),
+        ("primary", primary),
+    ] {
+        for (header, path) in mappings {
+            query!(
+                r#"
+                INSERT INTO sheet_type_import_mappings(type_name,section,header,path)
+                VALUES($1,$2,$3,$4)"#,
+                name,
+                section,
+                header,
+                &path,
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+
+    query!(
+        r#"
+        INSERT INTO sheet_type_row_identities(type_name,id_header)
+        VALUES($1,$2)"#,
+        name,
+        &*id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    for (header, op) in diff_ops {
+        let (op, nth) = identity_op_parts(op);
+        query!(
+            r#"
+            INSERT INTO sheet_type_identity_ops(type_name,header,op,nth)
+            VALUES($1,$2,$3,$4)"#,
+            name,
+            &*header,
+            op,
+            nth,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn fetch_sheet_types(
+    state: &AppState,
+    name: Option<&str>,
+) -> Result<Vec<SheetTypeConfig>, ApiError> {
+    let sheet_types = query!(
+        r#"
+        SELECT name,priorities,import_main_entry,import_repeated_entry,strict_formulas
+        FROM sheet_types WHERE $1::text IS NULL OR name = $1
+        ORDER BY name"#,
+        name,
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut rows = HashMap::<String, Vec<(i32, ConfigValue)>>::new();
+    let columns = query!(
+        r#"
+        SELECT type_name,position,header,value_type,is_completable
+        FROM sheet_type_columns WHERE $1::text IS NULL OR type_name = $1"#,
+        name,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for record in columns {
+        let props = ColumnProps {
+            header: record.header,
+            is_completable: record.is_completable,
+        };
+        let column = column_from_parts(&record.value_type, props)?;
+        rows.entry(record.type_name)
+            .or_default()
+            .push((record.position, ConfigValue::Basic(column)));
+    }
+    let calculated_columns = query!(
+        r#"
+        SELECT type_name,position,header,operation,result_type
+        FROM sheet_type_calculated_columns WHERE $1::text IS NULL OR type_name = $1"#,
+        name,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for record in calculated_columns {
+        let value = serde_json::from_value::<ValueType>(record.operation)?;
+        let result_type = result_type_from_part(&record.result_type)?;
+        let header = record.header;
+        rows.entry(record.type_name).or_default().push((
+            record.position,
+            ConfigValue::Calculated(OperationConfig {
+                header,
+                value,
+                result_type,
+            }),
+        ));
+    }
+
+    let mut mappings = HashMap::<(String, String), HashMap<String, Vec<String>>>::new();
+    let records = query!(
+        r#"
+        SELECT type_name,section,header,path
+        FROM sheet_type_import_mappings WHERE $1::text IS NULL OR type_name = $1"#,
+        name,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for record in records {
+        mappings
+            .entry((record.type_name, record.section))
+            .or_default()
+            .insert(record.header, record.path);
+    }
+
+    let mut identities = HashMap::<String, RowIdentity<Arc<str>>>::new();
+    let records = query!(
+        r#"
+        SELECT type_name,id_header
+        FROM sheet_type_row_identities WHERE $1::text IS NULL OR type_name = $1"#,
+        name,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for record in records {
+        let identity = RowIdentity {
+            id: Arc::from(record.id_header),
+            diff_ops: HashMap::new(),
+        };
+        identities.insert(record.type_name, identity);
+    }
+    let records = query!(
+        r#"
+        SELECT type_name,header,op,nth
+        FROM sheet_type_identity_ops WHERE $1::text IS NULL OR type_name = $1"#,
+        name,
+    )
+    .fetch_all(&state.db)
+    .await?;
+    for record in records {
+        let op = identity_op_from_parts(&record.op, record.nth)?;
+        if let Some(identity) = identities.get_mut(&record.type_name) {
+            identity.diff_ops.insert(Arc::from(record.header), op);
+        }
+    }
+
+    let mut result = Vec::with_capacity(sheet_types.len());
+    for record in sheet_types {
+        let mut row = rows.remove(&record.name).unwrap_or_default();
+        row.sort_by_key(|(position, _)| *position);
+        let mut mapping = |section: &str| {
+            mappings
+                .remove(&(record.name.clone(), section.to_string()))
+                .unwrap_or_default()
+        };
+        let importing = ImportConfig {
+            main_entry: record.import_main_entry,
+            repeated_entry: record.import_repeated_entry,
+            unique: mapping("unique"),
+            repeated: mapping("repeated"),
+            primary: mapping("primary"),
+        };
+        let row_identity = identities
+            .remove(&record.name)
+            .unwrap_or_else(|| RowIdentity {
+                id: Arc::from(""),
+                diff_ops: HashMap::new(),
+            });
+        result.push(SheetTypeConfig {
+            priorities: record.priorities.into_iter().map(Arc::from).collect(),
+            sheet: SheetConfig {
+                sheet_type_name: Arc::from(record.name),
+                importing,
+                row: row.into_iter().map(|(_, value)| value).collect(),
+                row_identity,
+                strict_formulas: record.strict_formulas,
+            },
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::app_state;
+    use client_models::parse_formula;
+    use sqlx::PgPool;
+
+    fn sheet_type(row: Vec<ConfigValue>) -> SheetTypeConfig {
+        SheetTypeConfig {
+            priorities: Arc::from([]),
+            sheet: SheetConfig {
+                sheet_type_name: Arc::from("invoices"),
+                importing: ImportConfig {
+                    main_entry: Vec::new(),
+                    repeated_entry: Vec::new(),
+                    unique: HashMap::new(),
+                    repeated: HashMap::new(),
+                    primary: HashMap::new(),
+                },
+                row,
+                row_identity: RowIdentity {
+                    id: Arc::from("price"),
+                    diff_ops: HashMap::new(),
+                },
+                strict_formulas: false,
+            },
+        }
+    }
+
+    fn basic(header: &str) -> ConfigValue {
+        ConfigValue::Basic(ColumnConfig::Decimal(ColumnProps {
+            header: header.to_string(),
+            is_completable: false,
+        }))
+    }
+
+    fn calculated(header: &str, formula: &str) -> ConfigValue {
+        ConfigValue::Calculated(OperationConfig {
+            header: header.to_string(),
+            value: parse_formula(formula).unwrap(),
+            result_type: ResultType::Decimal,
+        })
+    }
+
+    #[sqlx::test(migrations = "db/migrations")]
+    async fn sheet_types_are_seeded_only_once(pool: PgPool) {
+        let state = app_state(pool);
+        let seed = Config::from(vec![sheet_type(vec![
+            basic("price"),
+            calculated("net", "price - 1"),
+        ])]);
+        assert_eq!(seed_sheet_types(&state, seed.clone()).await, Ok(1));
+        let saved = fetch_sheet_types(&state, None).await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(&*saved[0].sheet.sheet_type_name, "invoices");
+        assert_eq!(saved[0].sheet.row.len(), 2);
+        assert_eq!(seed_sheet_types(&state, seed).await, Ok(0));
+        assert_eq!(fetch_sheet_types(&state, None).await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn sheet_types_with_calculation_cycles_are_rejected() {
+        let valid = sheet_type(vec![
+            basic("price"),
+            calculated("total", "net * 2"),
+            calculated("net", "price - 1"),
+        ]);
+        assert_eq!(check_sheet_type(&valid), Ok(()));
+
+        let cycle = sheet_type(vec![
+            basic("price"),
+            calculated("net", "total - price"),
+            calculated("total", "net * 2"),
+        ]);
+        assert_eq!(
+            check_sheet_type(&cycle),
+            Err(ApiError::BadRequest(String::from(
+                "الاعمدة المحسوبة تعتمد على بعضها (net -> total -> net)"
+            )))
+        );
+
+        let itself = sheet_type(vec![basic("price"), calculated("net", "net + price")]);
+        assert_eq!(
+            check_sheet_type(&itself),
+            Err(ApiError::BadRequest(String::from(
+                "الاعمدة المحسوبة تعتمد على بعضها (net -> net)"
+            )))
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::cell::Cell;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use sqlx::{query, PgPool};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{events, AppState};
+
+thread_local! {
+    static STATEMENTS: Cell<usize> = const { Cell::new(0) };
+}
+
+// sqlx logs every executed statement under this target, #[sqlx::test] runs each test
+// on its own current thread runtime so a thread local counter only sees that test
+struct StatementsCounter;
+
+impl Log for StatementsCounter {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == "sqlx::query"
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            STATEMENTS.with(|count| count.set(count.get() + 1));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static COUNTER: StatementsCounter = StatementsCounter;
+
+/// number of statements `future` executed
+pub async fn count_statements<T>(future: impl std::future::Future<Output = T>) -> (T, usize) {
+    if log::set_logger(&COUNTER).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+    let before = STATEMENTS.with(Cell::get);
+    let output = future.await;
+    (output, STATEMENTS.with(Cell::get) - before)
+}
+
+pub fn app_state(pool: PgPool) -> AppState {
+    let (events, _) = broadcast::channel(events::EVENTS_CAPACITY);
+    AppState { db: pool, events }
+}
+
+/// bearer token of a new admin, the password is never checked so it is left empty
+pub async fn admin_session(state: &AppState, username: &str) -> Uuid {
+    let user_id = Uuid::new_v4();
+    query("INSERT INTO users(id,username,password,is_admin) VALUES($1,$2,'',TRUE)")
+        .bind(user_id)
+        .bind(username)
+        .execute(&state.db)
+        .await
+        .expect("failed to insert user");
+    let token = Uuid::new_v4();
+    query("INSERT INTO sessions(token,user_id) VALUES($1,$2)")
+        .bind(token)
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .expect("failed to insert session");
+    token
+}
+
+/// a sheet type with the text column `name` and the numeric column `value`
+pub async fn insert_sheet_type(state: &AppState, name: &str) {
+    query("INSERT INTO sheet_types(name) VALUES($1)")
+        .bind(name)
+        .execute(&state.db)
+        .await
+        .expect("failed to insert sheet type");
+    for (position, header, value_type) in [(0, "name", "string"), (1, "value", "decimal")] {
+        query(
+            r#"
+	INSERT INTO sheet_type_columns(type_name,position,header,value_type)
+	VALUES($1,$2,$3,$4)"#,
+        )
+        .bind(name)
+        .bind(position)
+        .bind(header)
+        .bind(value_type)
+        .execute(&state.db)
+        .await
+        .expect("failed to insert sheet type column");
+    }
+}
+
+pub async fn insert_sheet(state: &AppState, type_name: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    query(
+        r#"
+	INSERT INTO sheets(id,sheet_name,type_name,insert_date)
+	VALUES($1,$2,$3,CURRENT_DATE)"#,
+    )
+    .bind(id)
+    .bind("sheet")
+    .bind(type_name)
+    .execute(&state.db)
+    .await
+    .expect("failed to insert sheet");
+    id
+}
+
+/// a row holding the text column `name` and the numeric column `value`
+pub async fn insert_row(state: &AppState, sheet_id: &Uuid, value: i32) -> Uuid {
+    let id = Uuid::new_v4();
+    query("INSERT INTO rows(id,sheet_id) VALUES($1,$2)")
+        .bind(id)
+        .bind(sheet_id)
+        .execute(&state.db)
+        .await
+        .expect("failed to insert row");
+    query(
+        r#"
+	INSERT INTO columns(id,row_id,header_name,text_value)
+	VALUES($1,$2,'name',$3)"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(id)
+    .bind(format!("row {value}"))
+    .execute(&state.db)
+    .await
+    .expect("failed to insert text column");
+    query(
+        r#"
+	INSERT INTO columns(id,row_id,header_name,numeric_value)
+	VALUES($1,$2,'value',$3)"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(id)
+    .bind(value)
+    .execute(&state.db)
+    .await
+    .expect("failed to insert numeric column");
+    id
+}
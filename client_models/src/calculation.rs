@@ -0,0 +1,528 @@
+use bigdecimal::{BigDecimal, FromPrimitive, One, ToPrimitive, Zero};
+use chrono::{Datelike, NaiveDate};
+use models::ColumnValue;
+use std::{cmp::Ordering, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    FunctionCall, FunctionKind, Operation, OperationConfig, OperationKind, ResultType, ValueType,
+};
+
+// shown in place of the calculated value, spreadsheet users already know these codes
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    Missing(String),
+    DivisionByZero,
+    InvalidNumber,
+    WrongType,
+}
+
+impl CalcError {
+    pub fn diagnostic(&self) -> String {
+        match self {
+            Self::Missing(header) => format!("العمود ({header}) ليس له قيمة"),
+            Self::DivisionByZero => String::from("قسمة على صفر"),
+            Self::InvalidNumber => String::from("النتيجة ليست رقما صالحا"),
+            Self::WrongType => String::from("نوع القيمة لا يناسب العملية"),
+        }
+    }
+}
+
+impl Display for CalcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(_) => write!(f, "#N/A"),
+            Self::DivisionByZero => write!(f, "#DIV/0!"),
+            Self::InvalidNumber => write!(f, "#NUM!"),
+            Self::WrongType => write!(f, "#VALUE!"),
+        }
+    }
+}
+
+pub type CalcErrors = HashMap<Rc<str>, CalcError>;
+
+type CalcResult = Result<Option<ColumnValue<Rc<str>>>, CalcError>;
+
+// what the formulas of a row can read, failed calculated columns are kept
+// apart so the columns reading them fail with the same error
+struct CalcContext {
+    columns: HashMap<Rc<str>, ColumnValue<Rc<str>>>,
+    errors: CalcErrors,
+    // a missing operand is an error instead of being skipped
+    strict: bool,
+}
+
+// evaluates the calculated columns of a row which must be in calculation order,
+// a failed column has no value and is only listed in the returned errors
+pub fn calculate_columns(
+    columns: HashMap<Rc<str>, ColumnValue<Rc<str>>>,
+    calc_columns: &[OperationConfig],
+    strict: bool,
+) -> (HashMap<Rc<str>, ColumnValue<Rc<str>>>, CalcErrors) {
+    let mut context = CalcContext {
+        columns,
+        errors: HashMap::new(),
+        strict,
+    };
+    let mut values = HashMap::new();
+    for OperationConfig {
+        header,
+        value,
+        result_type,
+    } in calc_columns
+    {
+        let header = Rc::<str>::from(header.as_str());
+        let result =
+            resolve_operation(value, &context).and_then(|value| typed_value(value, *result_type));
+        match result {
+            Ok(value) => {
+                context.columns.insert(header.clone(), value.clone());
+                values.insert(header, value);
+            }
+            Err(err) => {
+                context.columns.remove(&header);
+                context.errors.insert(header, err);
+            }
+        }
+    }
+    (values, context.errors)
+}
+
+fn typed_value(
+    value: Option<ColumnValue<Rc<str>>>,
+    result_type: ResultType,
+) -> Result<ColumnValue<Rc<str>>, CalcError> {
+    match (value, result_type) {
+        (None, ResultType::Decimal) => Ok(ColumnValue::Decimal(BigDecimal::zero())),
+        (None, _) => Ok(ColumnValue::String(Rc::from(""))),
+        (Some(value), ResultType::String) => Ok(ColumnValue::String(Rc::from(value_text(&value)))),
+        (Some(value @ ColumnValue::Decimal(_)), ResultType::Decimal)
+        | (Some(value @ ColumnValue::Date(_)), ResultType::Date) => Ok(value),
+        (Some(_), _) => Err(CalcError::WrongType),
+    }
+}
+
+// whole numbers such as tax ids are joined without the displayed decimals
+fn value_text(value: &ColumnValue<Rc<str>>) -> String {
+    match value {
+        ColumnValue::String(value) => value.to_string(),
+        ColumnValue::Decimal(value) if value.is_integer() => value.with_scale(0).to_string(),
+        ColumnValue::Decimal(value) => value.normalized().to_string(),
+        ColumnValue::Date(value) => value.to_string(),
+    }
+}
+
+fn resolve_operation(value: &ValueType, context: &CalcContext) -> CalcResult {
+    match value {
+        ValueType::Const(value) => Ok(Some(ColumnValue::Decimal(value.clone()))),
+        ValueType::Text(value) => Ok(Some(ColumnValue::String(Rc::from(value.as_str())))),
+        ValueType::Variable(header) => match context.columns.get(header.as_str()) {
+            Some(value) => Ok(Some(value.clone())),
+            None => match context.errors.get(header.as_str()) {
+                Some(err) => Err(err.clone()),
+                None if context.strict => Err(CalcError::Missing(header.clone())),
+                None => Ok(None),
+            },
+        },
+        ValueType::Operation(operation) => {
+            let Operation { op, lhs, rhs } = operation.as_ref();
+            let lhs = resolve_operation(lhs, context)?;
+            let rhs = resolve_operation(rhs, context)?;
+            match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) => apply_operation(op, lhs, rhs).map(Some),
+                // a comparison with a missing side has no answer
+                _ if is_comparison(op) => Ok(None),
+                (Some(lhs), None) => Ok(Some(lhs)),
+                (None, Some(rhs)) => Ok(Some(rhs)),
+                (None, None) => Ok(None),
+            }
+        }
+        ValueType::Function(call) => resolve_function(call, context),
+    }
+}
+
+fn is_comparison(op: &OperationKind) -> bool {
+    matches!(
+        op,
+        OperationKind::Greater
+            | OperationKind::GreaterOrEqual
+            | OperationKind::Less
+            | OperationKind::LessOrEqual
+            | OperationKind::Equal
+            | OperationKind::NotEqual
+    )
+}
+
+fn compare(lhs: &ColumnValue<Rc<str>>, rhs: &ColumnValue<Rc<str>>) -> Result<Ordering, CalcError> {
+    match (lhs, rhs) {
+        (ColumnValue::Decimal(lhs), ColumnValue::Decimal(rhs)) => Ok(lhs.cmp(rhs)),
+        (ColumnValue::Date(lhs), ColumnValue::Date(rhs)) => Ok(lhs.cmp(rhs)),
+        (ColumnValue::String(lhs), ColumnValue::String(rhs)) => Ok(lhs.cmp(rhs)),
+        _ => Err(CalcError::WrongType),
+    }
+}
+
+fn add_days(date: NaiveDate, days: &BigDecimal) -> Result<NaiveDate, CalcError> {
+    days.to_i64()
+        .and_then(chrono::Duration::try_days)
+        .and_then(|days| date.checked_add_signed(days))
+        .ok_or(CalcError::InvalidNumber)
+}
+
+fn apply_operation(
+    op: &OperationKind,
+    lhs: ColumnValue<Rc<str>>,
+    rhs: ColumnValue<Rc<str>>,
+) -> Result<ColumnValue<Rc<str>>, CalcError> {
+    if is_comparison(op) {
+        let ordering = compare(&lhs, &rhs)?;
+        let truth = match op {
+            OperationKind::Greater => ordering.is_gt(),
+            OperationKind::GreaterOrEqual => ordering.is_ge(),
+            OperationKind::Less => ordering.is_lt(),
+            OperationKind::LessOrEqual => ordering.is_le(),
+            OperationKind::Equal => ordering.is_eq(),
+            _ => ordering.is_ne(),
+        };
+        return Ok(ColumnValue::Decimal(BigDecimal::from(i32::from(truth))));
+    }
+    match (op, lhs, rhs) {
+        (OperationKind::Concat, lhs, rhs) => Ok(ColumnValue::String(Rc::from(
+            value_text(&lhs) + &value_text(&rhs),
+        ))),
+        (op, ColumnValue::Decimal(lhs), ColumnValue::Decimal(rhs)) => match op {
+            OperationKind::Multiply => Ok(ColumnValue::Decimal(lhs * rhs)),
+            OperationKind::Add => Ok(ColumnValue::Decimal(lhs + rhs)),
+            OperationKind::Minus => Ok(ColumnValue::Decimal(lhs - rhs)),
+            OperationKind::Divide if rhs.is_zero() => Err(CalcError::DivisionByZero),
+            OperationKind::Divide => Ok(ColumnValue::Decimal(lhs / rhs)),
+            _ => Err(CalcError::WrongType),
+        },
+        (OperationKind::Add, ColumnValue::Date(date), ColumnValue::Decimal(days))
+        | (OperationKind::Add, ColumnValue::Decimal(days), ColumnValue::Date(date)) => {
+            add_days(date, &days).map(ColumnValue::Date)
+        }
+        (OperationKind::Minus, ColumnValue::Date(date), ColumnValue::Decimal(days)) => {
+            add_days(date, &-days).map(ColumnValue::Date)
+        }
+        (OperationKind::Minus, ColumnValue::Date(lhs), ColumnValue::Date(rhs)) => Ok(
+            ColumnValue::Decimal(BigDecimal::from((lhs - rhs).num_days())),
+        ),
+        _ => Err(CalcError::WrongType),
+    }
+}
+
+fn decimal(value: Option<ColumnValue<Rc<str>>>) -> Result<Option<BigDecimal>, CalcError> {
+    match value {
+        Some(ColumnValue::Decimal(value)) => Ok(Some(value)),
+        Some(_) => Err(CalcError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn resolve_function(call: &FunctionCall, context: &CalcContext) -> CalcResult {
+    let FunctionCall { function, args } = call;
+    let arg = |index: usize| match args.get(index) {
+        Some(arg) => resolve_operation(arg, context),
+        None => Ok(None),
+    };
+    let decimal_arg = |index: usize| decimal(arg(index)?);
+    let result = match function {
+        FunctionKind::Round => {
+            let digits = decimal_arg(1)?
+                .and_then(|digits| digits.to_i64())
+                .unwrap_or(0);
+            decimal_arg(0)?.map(|value| value.round(digits))
+        }
+        FunctionKind::Abs => decimal_arg(0)?.map(|value| value.abs()),
+        FunctionKind::Min | FunctionKind::Max => {
+            let mut result = None::<ColumnValue<Rc<str>>>;
+            for value in args {
+                let Some(value) = resolve_operation(value, context)? else {
+                    continue;
+                };
+                let replace = match &result {
+                    Some(current) => {
+                        let ordering = compare(&value, current)?;
+                        match function {
+                            FunctionKind::Min => ordering.is_lt(),
+                            _ => ordering.is_gt(),
+                        }
+                    }
+                    None => true,
+                };
+                if replace {
+                    result = Some(value);
+                }
+            }
+            return Ok(result);
+        }
+        FunctionKind::Power => match (decimal_arg(0)?, decimal_arg(1)?) {
+            (Some(base), Some(exponent)) => Some(power(base, exponent)?),
+            (base, _) => base,
+        },
+        FunctionKind::Mod => match (decimal_arg(0)?, decimal_arg(1)?) {
+            (Some(_), Some(divisor)) if divisor.is_zero() => return Err(CalcError::DivisionByZero),
+            (Some(value), Some(divisor)) => {
+                // the result takes the sign of the divisor like spreadsheets do
+                let rem = value % &divisor;
+                if !rem.is_zero() && rem.sign() != divisor.sign() {
+                    Some(rem + divisor)
+                } else {
+                    Some(rem)
+                }
+            }
+            (value, _) => value,
+        },
+        // only the taken branch is evaluated so IF can guard a division
+        FunctionKind::If => {
+            return match decimal_arg(0)? {
+                Some(condition) if !condition.is_zero() => arg(1),
+                Some(_) => arg(2),
+                None => Ok(None),
+            }
+        }
+        FunctionKind::Day | FunctionKind::Month | FunctionKind::Year => {
+            return match arg(0)? {
+                Some(ColumnValue::Date(date)) => {
+                    let part = match function {
+                        FunctionKind::Day => i64::from(date.day()),
+                        FunctionKind::Month => i64::from(date.month()),
+                        _ => i64::from(date.year()),
+                    };
+                    Ok(Some(ColumnValue::Decimal(BigDecimal::from(part))))
+                }
+                Some(_) => Err(CalcError::WrongType),
+                None => Ok(None),
+            }
+        }
+    };
+    Ok(result.map(ColumnValue::Decimal))
+}
+
+fn power(base: BigDecimal, exponent: BigDecimal) -> Result<BigDecimal, CalcError> {
+    // small whole exponents stay exact, the rest go through f64
+    let whole = exponent
+        .to_i64()
+        .filter(|n| exponent.is_integer() && n.abs() <= 64);
+    match whole {
+        Some(n) if n < 0 && base.is_zero() => Err(CalcError::DivisionByZero),
+        Some(n) => {
+            let value = (0..n.abs()).fold(BigDecimal::one(), |value, _| value * &base);
+            Ok(if n < 0 {
+                BigDecimal::one() / value
+            } else {
+                value
+            })
+        }
+        None => base
+            .to_f64()
+            .zip(exponent.to_f64())
+            .and_then(|(base, exponent)| BigDecimal::from_f64(base.powf(exponent)))
+            .ok_or(CalcError::InvalidNumber),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_formula;
+
+    fn decimal_value(value: &str) -> ColumnValue<Rc<str>> {
+        ColumnValue::Decimal(value.parse().unwrap())
+    }
+
+    fn date_value(value: &str) -> ColumnValue<Rc<str>> {
+        ColumnValue::Date(value.parse().unwrap())
+    }
+
+    fn text_value(value: &str) -> ColumnValue<Rc<str>> {
+        ColumnValue::String(Rc::from(value))
+    }
+
+    fn row() -> HashMap<Rc<str>, ColumnValue<Rc<str>>> {
+        HashMap::from([
+            (Rc::from("a"), decimal_value("10")),
+            (Rc::from("zero"), decimal_value("0")),
+            (Rc::from("t"), text_value("x")),
+            (Rc::from("d"), date_value("2024-01-31")),
+        ])
+    }
+
+    fn eval_with(formula: &str, strict: bool) -> CalcResult {
+        let context = CalcContext {
+            columns: row(),
+            errors: HashMap::new(),
+            strict,
+        };
+        resolve_operation(&parse_formula(formula).unwrap(), &context)
+    }
+
+    fn eval(formula: &str) -> CalcResult {
+        eval_with(formula, false)
+    }
+
+    fn decimal(formula: &str) -> BigDecimal {
+        match eval(formula) {
+            Ok(Some(ColumnValue::Decimal(value))) => value,
+            other => panic!("{formula} gave {other:?}"),
+        }
+    }
+
+    fn column(header: &str, formula: &str, result_type: ResultType) -> OperationConfig {
+        OperationConfig {
+            header: header.to_string(),
+            value: parse_formula(formula).unwrap(),
+            result_type,
+        }
+    }
+
+    #[test]
+    fn dividing_by_zero_is_an_error() {
+        assert_eq!(eval("a / zero"), Err(CalcError::DivisionByZero));
+        assert_eq!(eval("MOD(a, zero)"), Err(CalcError::DivisionByZero));
+        assert_eq!(eval("POWER(zero, -1)"), Err(CalcError::DivisionByZero));
+        assert_eq!(decimal("POWER(zero, 0)"), BigDecimal::from(1));
+        assert_eq!(decimal("a / 4"), "2.5".parse().unwrap());
+    }
+
+    #[test]
+    fn mod_takes_the_sign_of_the_divisor() {
+        assert_eq!(decimal("MOD(7, 3)"), BigDecimal::from(1));
+        assert_eq!(decimal("MOD(-7, 3)"), BigDecimal::from(2));
+        assert_eq!(decimal("MOD(7, -3)"), BigDecimal::from(-2));
+        assert_eq!(decimal("MOD(-7, -3)"), BigDecimal::from(-1));
+        assert_eq!(decimal("MOD(6, -3)"), BigDecimal::from(0));
+    }
+
+    #[test]
+    fn if_only_evaluates_the_taken_branch() {
+        assert_eq!(decimal("IF(zero = 0, 0, a / zero)"), BigDecimal::from(0));
+        assert_eq!(decimal("IF(zero, a / zero, 1)"), BigDecimal::from(1));
+        assert_eq!(eval("IF(a, a / zero, 1)"), Err(CalcError::DivisionByZero));
+        assert_eq!(eval("IF(missing, 1, 2)"), Ok(None));
+        assert_eq!(eval("IF(t, 1, 2)"), Err(CalcError::WrongType));
+        assert_eq!(
+            eval(r#"IF(a > 5, "big", "small")"#),
+            Ok(Some(text_value("big")))
+        );
+    }
+
+    #[test]
+    fn power_is_exact_for_small_whole_exponents() {
+        assert_eq!(decimal("POWER(2, 10)"), BigDecimal::from(1024));
+        assert_eq!(decimal("POWER(2, -2)"), "0.25".parse().unwrap());
+        assert_eq!(decimal("POWER(0.1, 3)"), "0.001".parse().unwrap());
+        assert_eq!(decimal("POWER(4, 0.5)"), BigDecimal::from(2));
+        assert_eq!(eval("POWER(-8, 0.5)"), Err(CalcError::InvalidNumber));
+        assert_eq!(eval("POWER(t, 2)"), Err(CalcError::WrongType));
+    }
+
+    #[test]
+    fn min_and_max_compare_values_of_one_type() {
+        assert_eq!(decimal("MAX(1, a, 3)"), BigDecimal::from(10));
+        assert_eq!(decimal("MIN(1, a, -3)"), BigDecimal::from(-3));
+        assert_eq!(eval("MIN(d, d + 1)"), Ok(Some(date_value("2024-01-31"))));
+        assert_eq!(decimal("MAX(missing, a)"), BigDecimal::from(10));
+        assert_eq!(eval("MAX(a, t)"), Err(CalcError::WrongType));
+        assert_eq!(eval("MIN(d, a)"), Err(CalcError::WrongType));
+    }
+
+    #[test]
+    fn round_abs_and_date_parts() {
+        assert_eq!(decimal("ROUND(2.346, 2)"), "2.35".parse().unwrap());
+        assert_eq!(decimal("ROUND(a / 3)"), BigDecimal::from(3));
+        assert_eq!(decimal("ABS(-a)"), BigDecimal::from(10));
+        assert_eq!(decimal("DAY(d)"), BigDecimal::from(31));
+        assert_eq!(decimal("MONTH(d)"), BigDecimal::from(1));
+        assert_eq!(decimal("YEAR(d)"), BigDecimal::from(2024));
+        assert_eq!(eval("YEAR(a)"), Err(CalcError::WrongType));
+    }
+
+    #[test]
+    fn dates_move_by_days() {
+        assert_eq!(eval("d + 1"), Ok(Some(date_value("2024-02-01"))));
+        assert_eq!(eval("d - 31"), Ok(Some(date_value("2023-12-31"))));
+        assert_eq!(decimal("d - (d - 10)"), BigDecimal::from(10));
+        assert_eq!(eval("d * 2"), Err(CalcError::WrongType));
+    }
+
+    #[test]
+    fn comparisons_and_concat() {
+        assert_eq!(decimal("a > 5"), BigDecimal::from(1));
+        assert_eq!(decimal(r#"t = "x""#), BigDecimal::from(1));
+        assert_eq!(decimal("a <> 10"), BigDecimal::from(0));
+        assert_eq!(eval("a = t"), Err(CalcError::WrongType));
+        assert_eq!(eval("a > missing"), Ok(None));
+        assert_eq!(
+            eval(r#"t & "-" & a & "-" & 0.50"#),
+            Ok(Some(text_value("x-10-0.5")))
+        );
+        assert_eq!(eval("t + 1"), Err(CalcError::WrongType));
+    }
+
+    #[test]
+    fn missing_columns_are_skipped_unless_strict() {
+        assert_eq!(decimal("a + missing"), BigDecimal::from(10));
+        assert_eq!(eval("missing * 2"), Ok(Some(decimal_value("2"))));
+        assert_eq!(
+            eval_with("a + missing", true),
+            Err(CalcError::Missing("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn failed_columns_fail_the_columns_reading_them() {
+        let columns = [
+            column("ratio", "a / zero", ResultType::Decimal),
+            column("next", "ratio + 1", ResultType::Decimal),
+            column("fine", "a * 2", ResultType::Decimal),
+        ];
+        let (values, errors) = calculate_columns(row(), &columns, false);
+        assert_eq!(errors.get("ratio"), Some(&CalcError::DivisionByZero));
+        assert_eq!(errors.get("next"), Some(&CalcError::DivisionByZero));
+        assert_eq!(errors.get("fine"), None);
+        assert_eq!(values.get("fine"), Some(&decimal_value("20")));
+    }
+
+    #[test]
+    fn failed_columns_have_no_value() {
+        let columns = [
+            column("ratio", "a / zero", ResultType::Decimal),
+            column("total", "a * count", ResultType::Decimal),
+            column("fine", "a * 2", ResultType::Decimal),
+        ];
+        let (values, errors) = calculate_columns(row(), &columns, true);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors.get("total"),
+            Some(&CalcError::Missing("count".to_string()))
+        );
+        assert_eq!(values.len(), 1);
+        assert_eq!(values.get("ratio"), None);
+        assert_eq!(values.get("total"), None);
+        assert_eq!(values.get("fine"), Some(&decimal_value("20")));
+    }
+
+    #[test]
+    fn results_take_the_column_type() {
+        let columns = [
+            column("text", "a * 2", ResultType::String),
+            column("due", "d + 30", ResultType::Date),
+            column("wrong", "a + 1", ResultType::Date),
+            column("empty", "missing", ResultType::Decimal),
+        ];
+        let (values, errors) = calculate_columns(row(), &columns, false);
+        assert_eq!(values.get("text"), Some(&text_value("20")));
+        assert_eq!(values.get("due"), Some(&date_value("2024-03-01")));
+        assert_eq!(errors.get("wrong"), Some(&CalcError::WrongType));
+        assert_eq!(values.get("empty"), Some(&decimal_value("0")));
+    }
+
+    #[test]
+    fn errors_show_spreadsheet_codes() {
+        assert_eq!(CalcError::Missing("a".to_string()).to_string(), "#N/A");
+        assert_eq!(CalcError::DivisionByZero.to_string(), "#DIV/0!");
+        assert_eq!(CalcError::InvalidNumber.to_string(), "#NUM!");
+        assert_eq!(CalcError::WrongType.to_string(), "#VALUE!");
+    }
+}
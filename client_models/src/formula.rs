@@ -0,0 +1,892 @@
+use bigdecimal::BigDecimal;
+use serde::{de::Error, Deserialize, Deserializer};
+use std::{
+    collections::HashMap, fmt::Display, iter::Peekable, ops::RangeInclusive, str::FromStr,
+    vec::IntoIter,
+};
+
+use crate::{FunctionCall, FunctionKind, Operation, OperationConfig, OperationKind, ValueType};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormulaErrorKind {
+    UnexpectedChar(char),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnclosedBracket,
+    UnclosedQuote,
+    InvalidNumber(String),
+    NotAnOperation,
+    UnknownFunction(String),
+    ArgumentsCount(String),
+    MissingOperator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormulaError {
+    // counted in characters so arabic headers point at the right place
+    pub position: usize,
+    pub kind: FormulaErrorKind,
+}
+
+impl Display for FormulaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let position = self.position + 1;
+        match &self.kind {
+            FormulaErrorKind::UnexpectedChar(c) => {
+                write!(f, "حرف غير متوقع ({c}) عند الموضع {position}")
+            }
+            FormulaErrorKind::UnexpectedToken(token) => {
+                write!(f, "({token}) غير متوقع عند الموضع {position}")
+            }
+            FormulaErrorKind::UnexpectedEnd => {
+                write!(f, "المعادلة غير مكتملة عند الموضع {position}")
+            }
+            FormulaErrorKind::UnclosedBracket => {
+                write!(f, "قوس غير مغلق عند الموضع {position}")
+            }
+            FormulaErrorKind::UnclosedQuote => {
+                write!(f, "نص غير مغلق عند الموضع {position}")
+            }
+            FormulaErrorKind::InvalidNumber(number) => {
+                write!(f, "رقم غير صالح ({number}) عند الموضع {position}")
+            }
+            FormulaErrorKind::NotAnOperation => {
+                write!(f, "المعادلة يجب ان تحتوي على عملية حسابية")
+            }
+            FormulaErrorKind::UnknownFunction(name) => {
+                write!(f, "دالة غير معروفة ({name}) عند الموضع {position}")
+            }
+            FormulaErrorKind::ArgumentsCount(name) => {
+                write!(
+                    f,
+                    "عدد مدخلات الدالة ({name}) غير صحيح عند الموضع {position}"
+                )
+            }
+            FormulaErrorKind::MissingOperator => {
+                write!(f, "عملية حسابية ناقصة عند الموضع {position}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(BigDecimal),
+    Text(String),
+    Identifier(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Ampersand,
+    LeftParen,
+    RightParen,
+    Comma,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{number}"),
+            Self::Text(text) => write!(f, "\"{text}\""),
+            Self::Identifier(name) => write!(f, "{name}"),
+            Self::Plus => write!(f, "+"),
+            Self::Minus => write!(f, "-"),
+            Self::Star => write!(f, "*"),
+            Self::Slash => write!(f, "/"),
+            Self::Ampersand => write!(f, "&"),
+            Self::LeftParen => write!(f, "("),
+            Self::RightParen => write!(f, ")"),
+            Self::Comma => write!(f, ","),
+            Self::Greater => write!(f, ">"),
+            Self::GreaterOrEqual => write!(f, ">="),
+            Self::Less => write!(f, "<"),
+            Self::LessOrEqual => write!(f, "<="),
+            Self::Equal => write!(f, "="),
+            Self::NotEqual => write!(f, "<>"),
+        }
+    }
+}
+
+fn symbol(c: char) -> Option<Token> {
+    match c {
+        '+' => Some(Token::Plus),
+        '-' => Some(Token::Minus),
+        '*' => Some(Token::Star),
+        '/' => Some(Token::Slash),
+        '&' => Some(Token::Ampersand),
+        '(' => Some(Token::LeftParen),
+        ')' => Some(Token::RightParen),
+        // arabic keyboards type the arabic comma between arguments
+        ',' | '،' => Some(Token::Comma),
+        '>' => Some(Token::Greater),
+        '<' => Some(Token::Less),
+        '=' => Some(Token::Equal),
+        _ => None,
+    }
+}
+
+fn double_symbol(c: char, next: char) -> Option<Token> {
+    match (c, next) {
+        ('>', '=') => Some(Token::GreaterOrEqual),
+        ('<', '=') => Some(Token::LessOrEqual),
+        ('<', '>') | ('!', '=') => Some(Token::NotEqual),
+        _ => None,
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    symbol(c).is_some() || c == '[' || c == '!' || c == '"'
+}
+
+// headers may hold dots and single spaces between words so an identifier runs until
+// the next symbol, a word that does not start with a letter or follows more than one
+// space is a missing operator and headers like that must be written between [ ]
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, FormulaError> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if let Some(token) = chars.get(i + 1).and_then(|next| double_symbol(c, *next)) {
+            tokens.push((i, token));
+            i += 2;
+        } else if let Some(token) = symbol(c) {
+            tokens.push((i, token));
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number = chars[start..i].iter().collect::<String>();
+            let Ok(value) = number.parse::<BigDecimal>() else {
+                return Err(FormulaError {
+                    position: start,
+                    kind: FormulaErrorKind::InvalidNumber(number),
+                });
+            };
+            tokens.push((start, Token::Number(value)));
+        } else if c == '"' {
+            let start = i;
+            let Some(end) = chars[start + 1..].iter().position(|c| *c == '"') else {
+                return Err(FormulaError {
+                    position: start,
+                    kind: FormulaErrorKind::UnclosedQuote,
+                });
+            };
+            let text = chars[start + 1..start + 1 + end].iter().collect::<String>();
+            tokens.push((start, Token::Text(text)));
+            i = start + end + 2;
+        } else if c == '[' {
+            let start = i;
+            let Some(end) = chars[start..].iter().position(|c| *c == ']') else {
+                return Err(FormulaError {
+                    position: start,
+                    kind: FormulaErrorKind::UnclosedBracket,
+                });
+            };
+            let name = chars[start + 1..start + end].iter().collect::<String>();
+            tokens.push((start, Token::Identifier(name.trim().to_string())));
+            i = start + end + 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            let end = loop {
+                while i < chars.len() && !chars[i].is_whitespace() && !is_delimiter(chars[i]) {
+                    i += 1;
+                }
+                let spaces = chars[i..].iter().take_while(|c| c.is_whitespace()).count();
+                match chars.get(i + spaces) {
+                    Some(next) if spaces > 0 && !is_delimiter(*next) => {
+                        if spaces > 1 || !(next.is_alphabetic() || *next == '_') {
+                            let kind = if next.is_alphanumeric() || *next == '_' {
+                                FormulaErrorKind::MissingOperator
+                            } else {
+                                FormulaErrorKind::UnexpectedChar(*next)
+                            };
+                            return Err(FormulaError {
+                                position: i + spaces,
+                                kind,
+                            });
+                        }
+                        i += spaces;
+                    }
+                    _ => break i,
+                }
+            };
+            let name = chars[start..end].iter().collect::<String>();
+            tokens.push((start, Token::Identifier(name)));
+        } else {
+            return Err(FormulaError {
+                position: i,
+                kind: FormulaErrorKind::UnexpectedChar(c),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<(usize, Token)>>,
+    end: usize,
+}
+
+impl Parser {
+    fn next_token(&mut self) -> Result<(usize, Token), FormulaError> {
+        self.tokens.next().ok_or(FormulaError {
+            position: self.end,
+            kind: FormulaErrorKind::UnexpectedEnd,
+        })
+    }
+
+    // the token after the contents of the bracket opened at `open`
+    fn closing(&mut self, open: usize) -> Result<(usize, Token), FormulaError> {
+        self.tokens.next().ok_or(FormulaError {
+            position: open,
+            kind: FormulaErrorKind::UnclosedBracket,
+        })
+    }
+
+    fn next_if(&mut self, expected: &[Token]) -> Option<Token> {
+        self.tokens
+            .next_if(|(_, token)| expected.contains(token))
+            .map(|(_, token)| token)
+    }
+
+    // comparisons bind looser than arithmetic and evaluate to 1 or 0
+    fn comparison(&mut self) -> Result<ValueType, FormulaError> {
+        let lhs = self.concat()?;
+        let Some(token) = self.next_if(&[
+            Token::Greater,
+            Token::GreaterOrEqual,
+            Token::Less,
+            Token::LessOrEqual,
+            Token::Equal,
+            Token::NotEqual,
+        ]) else {
+            return Ok(lhs);
+        };
+        let op = match token {
+            Token::Greater => OperationKind::Greater,
+            Token::GreaterOrEqual => OperationKind::GreaterOrEqual,
+            Token::Less => OperationKind::Less,
+            Token::LessOrEqual => OperationKind::LessOrEqual,
+            Token::Equal => OperationKind::Equal,
+            _ => OperationKind::NotEqual,
+        };
+        let rhs = self.concat()?;
+        Ok(ValueType::Operation(Box::new(Operation { op, lhs, rhs })))
+    }
+
+    fn concat(&mut self) -> Result<ValueType, FormulaError> {
+        let mut lhs = self.expression()?;
+        while self.next_if(&[Token::Ampersand]).is_some() {
+            let rhs = self.expression()?;
+            lhs = ValueType::Operation(Box::new(Operation {
+                op: OperationKind::Concat,
+                lhs,
+                rhs,
+            }));
+        }
+        Ok(lhs)
+    }
+
+    fn expression(&mut self) -> Result<ValueType, FormulaError> {
+        let mut lhs = self.term()?;
+        while let Some(token) = self.next_if(&[Token::Plus, Token::Minus]) {
+            let op = match token {
+                Token::Plus => OperationKind::Add,
+                _ => OperationKind::Minus,
+            };
+            let rhs = self.term()?;
+            lhs = ValueType::Operation(Box::new(Operation { op, lhs, rhs }));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<ValueType, FormulaError> {
+        let mut lhs = self.factor()?;
+        while let Some(token) = self.next_if(&[Token::Star, Token::Slash]) {
+            let op = match token {
+                Token::Star => OperationKind::Multiply,
+                _ => OperationKind::Divide,
+            };
+            let rhs = self.factor()?;
+            lhs = ValueType::Operation(Box::new(Operation { op, lhs, rhs }));
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> Result<ValueType, FormulaError> {
+        let (position, token) = self.next_token()?;
+        match token {
+            Token::Number(value) => Ok(ValueType::Const(value)),
+            Token::Text(text) => Ok(ValueType::Text(text)),
+            Token::Identifier(name) => {
+                match self.tokens.next_if(|(_, token)| *token == Token::LeftParen) {
+                    Some((open, _)) => self.call(position, open, name),
+                    None => Ok(ValueType::Variable(name)),
+                }
+            }
+            Token::Minus => {
+                let rhs = self.factor()?;
+                Ok(ValueType::Operation(Box::new(Operation {
+                    op: OperationKind::Minus,
+                    lhs: ValueType::Const(BigDecimal::from(0)),
+                    rhs,
+                })))
+            }
+            Token::LeftParen => {
+                let value = self.comparison()?;
+                match self.closing(position)? {
+                    (_, Token::RightParen) => Ok(value),
+                    (position, token) => Err(FormulaError {
+                        position,
+                        kind: FormulaErrorKind::UnexpectedToken(token.to_string()),
+                    }),
+                }
+            }
+            token => Err(FormulaError {
+                position,
+                kind: FormulaErrorKind::UnexpectedToken(token.to_string()),
+            }),
+        }
+    }
+
+    fn call(
+        &mut self,
+        position: usize,
+        open: usize,
+        name: String,
+    ) -> Result<ValueType, FormulaError> {
+        let Some(function) = function_kind(&name) else {
+            return Err(FormulaError {
+                position,
+                kind: FormulaErrorKind::UnknownFunction(name),
+            });
+        };
+        let mut args = Vec::new();
+        if self.next_if(&[Token::RightParen]).is_none() {
+            loop {
+                args.push(self.comparison()?);
+                match self.closing(open)? {
+                    (_, Token::Comma) => continue,
+                    (_, Token::RightParen) => break,
+                    (position, token) => {
+                        return Err(FormulaError {
+                            position,
+                            kind: FormulaErrorKind::UnexpectedToken(token.to_string()),
+                        })
+                    }
+                }
+            }
+        }
+        if !arity(function).contains(&args.len()) {
+            return Err(FormulaError {
+                position,
+                kind: FormulaErrorKind::ArgumentsCount(name),
+            });
+        }
+        Ok(ValueType::Function(FunctionCall { function, args }))
+    }
+}
+
+fn function_kind(name: &str) -> Option<FunctionKind> {
+    match name.to_uppercase().as_str() {
+        "ROUND" => Some(FunctionKind::Round),
+        "ABS" => Some(FunctionKind::Abs),
+        "MIN" => Some(FunctionKind::Min),
+        "MAX" => Some(FunctionKind::Max),
+        "POWER" => Some(FunctionKind::Power),
+        "MOD" => Some(FunctionKind::Mod),
+        "IF" => Some(FunctionKind::If),
+        "DAY" => Some(FunctionKind::Day),
+        "MONTH" => Some(FunctionKind::Month),
+        "YEAR" => Some(FunctionKind::Year),
+        _ => None,
+    }
+}
+
+fn arity(function: FunctionKind) -> RangeInclusive<usize> {
+    match function {
+        FunctionKind::Round => 1..=2,
+        FunctionKind::Abs | FunctionKind::Day | FunctionKind::Month | FunctionKind::Year => 1..=1,
+        FunctionKind::Min | FunctionKind::Max => 1..=usize::MAX,
+        FunctionKind::Power | FunctionKind::Mod => 2..=2,
+        FunctionKind::If => 3..=3,
+    }
+}
+
+pub fn parse_formula(input: &str) -> Result<ValueType, FormulaError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?.into_iter().peekable(),
+        end: input.chars().count(),
+    };
+    let value = parser.comparison()?;
+    match parser.tokens.next() {
+        None => Ok(value),
+        Some((position, token)) => Err(FormulaError {
+            position,
+            kind: FormulaErrorKind::UnexpectedToken(token.to_string()),
+        }),
+    }
+}
+
+impl ValueType {
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Self::Const(_) | Self::Text(_) => Vec::new(),
+            Self::Variable(name) => vec![name.as_str()],
+            Self::Operation(operation) => {
+                let mut variables = operation.lhs.variables();
+                variables.extend(operation.rhs.variables());
+                variables
+            }
+            Self::Function(call) => call.args.iter().flat_map(Self::variables).collect(),
+        }
+    }
+}
+
+impl FromStr for ValueType {
+    type Err = FormulaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_formula(s)
+    }
+}
+
+impl FromStr for Operation {
+    type Err = FormulaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_formula(s)? {
+            ValueType::Operation(operation) => Ok(*operation),
+            ValueType::Const(_)
+            | ValueType::Text(_)
+            | ValueType::Variable(_)
+            | ValueType::Function(_) => Err(FormulaError {
+                position: 0,
+                kind: FormulaErrorKind::NotAnOperation,
+            }),
+        }
+    }
+}
+
+// configs may write an operation as a formula string instead of the nested tree
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum OperationRepr {
+    Formula(String),
+    Tree {
+        op: OperationKind,
+        lhs: ValueType,
+        rhs: ValueType,
+    },
+}
+
+impl TryFrom<OperationRepr> for Operation {
+    type Error = FormulaError;
+
+    fn try_from(repr: OperationRepr) -> Result<Self, Self::Error> {
+        match repr {
+            OperationRepr::Formula(formula) => formula.parse(),
+            OperationRepr::Tree { op, lhs, rhs } => Ok(Self { op, lhs, rhs }),
+        }
+    }
+}
+
+// calculated columns used to hold a bare operation tree before functions existed
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FormulaRepr {
+    Formula(String),
+    Value(ValueType),
+    Operation(Operation),
+}
+
+pub(crate) fn deserialize_formula<'de, D>(deserializer: D) -> Result<ValueType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match FormulaRepr::deserialize(deserializer)? {
+        FormulaRepr::Formula(formula) => parse_formula(&formula).map_err(D::Error::custom),
+        FormulaRepr::Value(value) => Ok(value),
+        FormulaRepr::Operation(operation) => Ok(ValueType::Operation(Box::new(operation))),
+    }
+}
+
+// the headers along the cycle, the first one is repeated at the end
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalculationCycle(pub Vec<String>);
+
+impl Display for CalculationCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "الاعمدة المحسوبة تعتمد على بعضها ({})",
+            self.0.join(" -> ")
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    Active,
+    Done,
+}
+
+struct CalculationSorter<'a> {
+    columns: &'a [OperationConfig],
+    positions: HashMap<&'a str, usize>,
+    visits: Vec<Visit>,
+    path: Vec<usize>,
+    order: Vec<usize>,
+}
+
+impl CalculationSorter<'_> {
+    fn visit(&mut self, index: usize) -> Result<(), CalculationCycle> {
+        match self.visits[index] {
+            Visit::Done => return Ok(()),
+            Visit::Active => {
+                let start = self
+                    .path
+                    .iter()
+                    .position(|i| *i == index)
+                    .unwrap_or_default();
+                let headers = self.path[start..]
+                    .iter()
+                    .chain([&index])
+                    .map(|i| self.columns[*i].header.clone())
+                    .collect();
+                return Err(CalculationCycle(headers));
+            }
+            Visit::New => (),
+        }
+        self.visits[index] = Visit::Active;
+        self.path.push(index);
+        let columns = self.columns;
+        for variable in columns[index].value.variables() {
+            if let Some(dependency) = self.positions.get(variable).copied() {
+                self.visit(dependency)?;
+            }
+        }
+        self.path.pop();
+        self.visits[index] = Visit::Done;
+        self.order.push(index);
+        Ok(())
+    }
+}
+
+// a calculated column comes after the calculated columns it reads,
+// otherwise the config order is kept
+pub fn calculation_order(
+    columns: Vec<OperationConfig>,
+) -> Result<Vec<OperationConfig>, CalculationCycle> {
+    let order = {
+        let mut sorter = CalculationSorter {
+            columns: &columns,
+            positions: columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| (column.header.as_str(), i))
+                .collect(),
+            visits: vec![Visit::New; columns.len()],
+            path: Vec::new(),
+            order: Vec::with_capacity(columns.len()),
+        };
+        for index in 0..columns.len() {
+            sorter.visit(index)?;
+        }
+        sorter.order
+    };
+    let mut columns = columns.into_iter().map(Some).collect::<Vec<_>>();
+    Ok(order
+        .into_iter()
+        .filter_map(|index| columns[index].take())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResultType;
+
+    fn num(value: i32) -> ValueType {
+        ValueType::Const(BigDecimal::from(value))
+    }
+
+    fn var(name: &str) -> ValueType {
+        ValueType::Variable(name.to_string())
+    }
+
+    fn op(op: OperationKind, lhs: ValueType, rhs: ValueType) -> ValueType {
+        ValueType::Operation(Box::new(Operation { op, lhs, rhs }))
+    }
+
+    fn call(function: FunctionKind, args: Vec<ValueType>) -> ValueType {
+        ValueType::Function(FunctionCall { function, args })
+    }
+
+    fn error(input: &str) -> (usize, FormulaErrorKind) {
+        let err = parse_formula(input).unwrap_err();
+        (err.position, err.kind)
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        use OperationKind::*;
+        assert_eq!(
+            parse_formula("a + b * c").unwrap(),
+            op(Add, var("a"), op(Multiply, var("b"), var("c")))
+        );
+        assert_eq!(
+            parse_formula("(a + b) * c").unwrap(),
+            op(Multiply, op(Add, var("a"), var("b")), var("c"))
+        );
+        assert_eq!(
+            parse_formula("a - b - c / 2").unwrap(),
+            op(
+                Minus,
+                op(Minus, var("a"), var("b")),
+                op(Divide, var("c"), num(2))
+            )
+        );
+        assert_eq!(
+            parse_formula("-a * 2").unwrap(),
+            op(Multiply, op(Minus, num(0), var("a")), num(2))
+        );
+    }
+
+    #[test]
+    fn concat_sits_between_arithmetic_and_comparison() {
+        use OperationKind::*;
+        assert_eq!(
+            parse_formula(r#"a & " - " & b + 1"#).unwrap(),
+            op(
+                Concat,
+                op(Concat, var("a"), ValueType::Text(" - ".to_string())),
+                op(Add, var("b"), num(1))
+            )
+        );
+        assert_eq!(
+            parse_formula("a & b = c").unwrap(),
+            op(Equal, op(Concat, var("a"), var("b")), var("c"))
+        );
+        assert_eq!(
+            parse_formula("a + 1 >= b * 2").unwrap(),
+            op(
+                GreaterOrEqual,
+                op(Add, var("a"), num(1)),
+                op(Multiply, var("b"), num(2))
+            )
+        );
+        assert_eq!(
+            parse_formula("a <> b").unwrap(),
+            parse_formula("a != b").unwrap()
+        );
+    }
+
+    #[test]
+    fn headers_may_hold_dots_and_single_spaces() {
+        use OperationKind::*;
+        assert_eq!(
+            parse_formula("القيمة + ض.ق.م - تاريخ الاستحقاق").unwrap(),
+            op(
+                Minus,
+                op(Add, var("القيمة"), var("ض.ق.م")),
+                var("تاريخ الاستحقاق")
+            )
+        );
+        assert_eq!(
+            parse_formula("[بند 3] * 2").unwrap(),
+            op(Multiply, var("بند 3"), num(2))
+        );
+    }
+
+    #[test]
+    fn words_that_can_not_join_a_header_are_missing_operators() {
+        assert_eq!(error("a  b"), (3, FormulaErrorKind::MissingOperator));
+        assert_eq!(error("a ٣"), (2, FormulaErrorKind::MissingOperator));
+        assert_eq!(error("a + b 2"), (6, FormulaErrorKind::MissingOperator));
+    }
+
+    #[test]
+    fn functions_check_their_arguments_count() {
+        assert_eq!(
+            parse_formula("round(a * 0.14، 2)").unwrap(),
+            call(
+                FunctionKind::Round,
+                vec![
+                    op(
+                        OperationKind::Multiply,
+                        var("a"),
+                        ValueType::Const("0.14".parse().unwrap())
+                    ),
+                    num(2)
+                ]
+            )
+        );
+        assert_eq!(
+            parse_formula("MAX(a, b, c)").unwrap(),
+            call(FunctionKind::Max, vec![var("a"), var("b"), var("c")])
+        );
+        assert_eq!(
+            parse_formula("IF(a > 0, a, 0)").unwrap(),
+            call(
+                FunctionKind::If,
+                vec![
+                    op(OperationKind::Greater, var("a"), num(0)),
+                    var("a"),
+                    num(0)
+                ]
+            )
+        );
+        let count = |name: &str| FormulaErrorKind::ArgumentsCount(name.to_string());
+        assert_eq!(error("ROUND()"), (0, count("ROUND")));
+        assert_eq!(error("1 + ABS(a, b)"), (4, count("ABS")));
+        assert_eq!(error("IF(a, b)"), (0, count("IF")));
+        assert_eq!(error("MIN()"), (0, count("MIN")));
+        assert_eq!(error("POWER(a)"), (0, count("POWER")));
+        assert_eq!(
+            error("a + SUM(b)"),
+            (4, FormulaErrorKind::UnknownFunction("SUM".to_string()))
+        );
+    }
+
+    #[test]
+    fn errors_point_at_their_position() {
+        assert_eq!(error("(a + b"), (0, FormulaErrorKind::UnclosedBracket));
+        assert_eq!(error("a * (b + (c"), (9, FormulaErrorKind::UnclosedBracket));
+        assert_eq!(error("ROUND(a, 2"), (5, FormulaErrorKind::UnclosedBracket));
+        assert_eq!(error("[a + 1"), (0, FormulaErrorKind::UnclosedBracket));
+        assert_eq!(error(r#"a & "b"#), (4, FormulaErrorKind::UnclosedQuote));
+        assert_eq!(error("a + "), (4, FormulaErrorKind::UnexpectedEnd));
+        assert_eq!(
+            error("a + * b"),
+            (4, FormulaErrorKind::UnexpectedToken("*".to_string()))
+        );
+        assert_eq!(
+            error("(a + b))"),
+            (7, FormulaErrorKind::UnexpectedToken(")".to_string()))
+        );
+        assert_eq!(error("a $ b"), (2, FormulaErrorKind::UnexpectedChar('$')));
+        assert_eq!(
+            error("a + 1.2.3"),
+            (4, FormulaErrorKind::InvalidNumber("1.2.3".to_string()))
+        );
+        // positions are counted in characters and not bytes
+        assert_eq!(
+            error("القيمة + )"),
+            (9, FormulaErrorKind::UnexpectedToken(")".to_string()))
+        );
+    }
+
+    #[test]
+    fn operation_formulas_must_hold_an_operation() {
+        assert!("a + b".parse::<Operation>().is_ok());
+        assert_eq!(
+            "ROUND(a)".parse::<Operation>().unwrap_err().kind,
+            FormulaErrorKind::NotAnOperation
+        );
+    }
+
+    #[test]
+    fn configs_hold_formulas_or_legacy_trees() {
+        let formula = serde_json::json!({"header": "x", "value": "a * 2"});
+        let tree = serde_json::json!({
+            "header": "x",
+            "value": {"op": "Multiply", "lhs": {"Variable": "a"}, "rhs": {"Const": "2"}},
+        });
+        let expected = OperationConfig {
+            header: "x".to_string(),
+            value: op(OperationKind::Multiply, var("a"), num(2)),
+            result_type: ResultType::Decimal,
+        };
+        assert_eq!(
+            serde_json::from_value::<OperationConfig>(formula).unwrap(),
+            expected
+        );
+        assert_eq!(
+            serde_json::from_value::<OperationConfig>(tree).unwrap(),
+            expected
+        );
+        let broken = serde_json::json!({"header": "x", "value": "a *"});
+        assert!(serde_json::from_value::<OperationConfig>(broken).is_err());
+    }
+
+    #[test]
+    fn variables_are_collected_from_every_argument() {
+        let value = parse_formula("IF(a > b, ROUND(c), d & \"e\")").unwrap();
+        assert_eq!(value.variables(), vec!["a", "b", "c", "d"]);
+    }
+
+    fn calculated(header: &str, formula: &str) -> OperationConfig {
+        OperationConfig {
+            header: header.to_string(),
+            value: parse_formula(formula).unwrap(),
+            result_type: ResultType::default(),
+        }
+    }
+
+    fn order(columns: Vec<OperationConfig>) -> Result<Vec<String>, CalculationCycle> {
+        calculation_order(columns).map(|columns| columns.into_iter().map(|c| c.header).collect())
+    }
+
+    #[test]
+    fn calculated_columns_follow_the_columns_they_read() {
+        let columns = vec![
+            calculated("total", "net + tax"),
+            calculated("tax", "net * 0.15"),
+            calculated("net", "price - discount"),
+            calculated("label", "\"x\""),
+        ];
+        assert_eq!(
+            order(columns),
+            Ok(vec![
+                "net".to_string(),
+                "tax".to_string(),
+                "total".to_string(),
+                "label".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn two_columns_reading_each_other_are_a_cycle() {
+        let columns = vec![
+            calculated("c", "1"),
+            calculated("a", "b + 1"),
+            calculated("b", "a * 2"),
+        ];
+        assert_eq!(
+            order(columns),
+            Err(CalculationCycle(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "a".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn a_column_reading_itself_is_a_cycle() {
+        let cycle = order(vec![calculated("a", "IF(a > 0, a, 1)")]).unwrap_err();
+        assert_eq!(
+            cycle,
+            CalculationCycle(vec!["a".to_string(), "a".to_string()])
+        );
+        assert_eq!(
+            cycle.to_string(),
+            "الاعمدة المحسوبة تعتمد على بعضها (a -> a)"
+        );
+    }
+}
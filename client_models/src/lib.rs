@@ -1,17 +1,29 @@
+use bigdecimal::BigDecimal;
 use ciborium_io::Write;
 use serde::{Deserialize, Serialize};
 use std::hash::Hash;
 use std::rc::Rc;
 use std::{collections::HashMap, fs::File, io::Cursor, sync::Arc};
 
+mod calculation;
+mod formula;
+
+pub use calculation::{calculate_columns, CalcError, CalcErrors};
+pub use formula::{
+    calculation_order, parse_formula, CalculationCycle, FormulaError, FormulaErrorKind,
+};
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum ValueType {
-    Const(f64),
+    Const(BigDecimal),
+    Text(String),
     Variable(String),
     Operation(Box<Operation>),
+    Function(FunctionCall),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "formula::OperationRepr")]
 pub struct Operation {
     pub op: OperationKind,
     pub lhs: ValueType,
@@ -24,12 +36,50 @@ pub enum OperationKind {
     Add,
     Minus,
     Divide,
+    Concat,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionCall {
+    pub function: FunctionKind,
+    pub args: Vec<ValueType>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum FunctionKind {
+    Round,
+    Abs,
+    Min,
+    Max,
+    Power,
+    Mod,
+    If,
+    Day,
+    Month,
+    Year,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ResultType {
+    #[default]
+    Decimal,
+    String,
+    Date,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct OperationConfig {
     pub header: String,
-    pub value: Operation,
+    #[serde(deserialize_with = "formula::deserialize_formula")]
+    pub value: ValueType,
+    #[serde(default)]
+    pub result_type: ResultType,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -74,7 +124,7 @@ where
     pub diff_ops: HashMap<RC, IdentityDiffsOps>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SheetConfig<RC>
 where
     RC: Hash + Eq,
@@ -83,14 +133,52 @@ where
     pub importing: ImportConfig,
     pub row: Vec<ConfigValue>,
     pub row_identity: RowIdentity<RC>,
+    // a formula reading a missing column fails instead of skipping it
+    #[serde(default)]
+    pub strict_formulas: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Config {
     pub priorities: HashMap<Arc<str>, Arc<[Arc<str>]>>,
     pub sheets: Vec<SheetConfig<Arc<str>>>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SheetTypeConfig {
+    pub priorities: Arc<[Arc<str>]>,
+    pub sheet: SheetConfig<Arc<str>>,
+}
+
+impl From<Vec<SheetTypeConfig>> for Config {
+    fn from(sheet_types: Vec<SheetTypeConfig>) -> Self {
+        let mut config = Config::default();
+        for SheetTypeConfig { priorities, sheet } in sheet_types {
+            config
+                .priorities
+                .insert(sheet.sheet_type_name.clone(), priorities);
+            config.sheets.push(sheet);
+        }
+        config
+    }
+}
+
+impl From<Config> for Vec<SheetTypeConfig> {
+    fn from(config: Config) -> Self {
+        let Config { priorities, sheets } = config;
+        sheets
+            .into_iter()
+            .map(|sheet| SheetTypeConfig {
+                priorities: priorities
+                    .get(&sheet.sheet_type_name)
+                    .cloned()
+                    .unwrap_or(Arc::from([])),
+                sheet,
+            })
+            .collect()
+    }
+}
+
 pub trait HeaderGetter {
     fn get_header(self) -> Rc<str>;
 }
@@ -98,7 +186,8 @@ pub trait HeaderGetter {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum ColumnConfig {
     String(ColumnProps),
-    Float(ColumnProps),
+    #[serde(alias = "Float")]
+    Decimal(ColumnProps),
     Date(ColumnProps),
 }
 
@@ -106,7 +195,7 @@ impl HeaderGetter for ColumnConfig {
     fn get_header(self) -> Rc<str> {
         match self {
             Self::String(prop) => Rc::from(prop.header),
-            Self::Float(prop) => Rc::from(prop.header),
+            Self::Decimal(prop) => Rc::from(prop.header),
             Self::Date(prop) => Rc::from(prop.header),
         }
     }
@@ -138,6 +227,7 @@ pub fn get_config_example() {
         ]),
         sheets: vec![
             SheetConfig {
+                strict_formulas: false,
                 row_identity: RowIdentity {
                     id: Arc::from("رقم الفاتورة"),
                     diff_ops: HashMap::from([
@@ -176,40 +266,39 @@ pub fn get_config_example() {
                     )]),
                 },
                 row: vec![
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("رقم الفاتورة".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("رقم الفاتورة".to_string()))),
                     ConfigValue::Basic(ColumnConfig::Date(fcp("التاريخ".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("رقم التسجيل الضريبي".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp(
+                        "رقم التسجيل الضريبي".to_string()
+                    ))),
                     ConfigValue::Basic(ColumnConfig::String(tcp("اسم العميل".to_string()))),
                     ConfigValue::Basic(ColumnConfig::String(tcp("تبع".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("القيمة".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("الخصم".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("القيمة".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("الخصم".to_string()))),
                     ConfigValue::Calculated(OperationConfig {
                         header: "ض.ق.م".to_string(),
-                        value: Operation {
-                            op: OperationKind::Multiply,
-                            lhs: ValueType::Variable("القيمة".to_string()),
-                            rhs: ValueType::Const(0.14),
-                        },
+                        value: "ROUND(القيمة * 0.14, 2)".parse().unwrap(),
+                        result_type: ResultType::Decimal,
                     }),
                     ConfigValue::Calculated(OperationConfig {
                         header: "الاجمالي".to_string(),
-                        value: Operation {
-                            op: OperationKind::Add,
-                            lhs: ValueType::Variable("القيمة".to_string()),
-                            rhs: ValueType::Operation(Box::new(Operation {
-                                op: OperationKind::Minus,
-                                lhs: ValueType::Operation(Box::new(Operation {
-                                    op: OperationKind::Multiply,
-                                    lhs: ValueType::Variable("القيمة".to_string()),
-                                    rhs: ValueType::Const(0.14),
-                                })),
-                                rhs: ValueType::Variable("الخصم".to_string()),
-                            })),
-                        },
+                        value: "القيمة + ض.ق.م - الخصم".parse().unwrap(),
+                        result_type: ResultType::Decimal,
+                    }),
+                    ConfigValue::Calculated(OperationConfig {
+                        header: "العميل".to_string(),
+                        value: r#"اسم العميل & " - " & رقم التسجيل الضريبي"#.parse().unwrap(),
+                        result_type: ResultType::String,
+                    }),
+                    ConfigValue::Calculated(OperationConfig {
+                        header: "تاريخ الاستحقاق".to_string(),
+                        value: "التاريخ + 30".parse().unwrap(),
+                        result_type: ResultType::Date,
                     }),
                 ],
             },
             SheetConfig {
+                strict_formulas: false,
                 row_identity: RowIdentity {
                     id: Arc::from(""),
                     diff_ops: HashMap::new(),
@@ -236,23 +325,21 @@ pub fn get_config_example() {
                     )]),
                 },
                 row: vec![
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("رقم الفاتورة".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("رقم الفاتورة".to_string()))),
                     ConfigValue::Basic(ColumnConfig::Date(fcp("التاريخ".to_string()))),
                     ConfigValue::Basic(ColumnConfig::String(tcp("بيان".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("رقم الاصناف".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("السعر".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("العدد".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("رقم الاصناف".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("السعر".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("العدد".to_string()))),
                     ConfigValue::Calculated(OperationConfig {
                         header: "الاجمالي".to_string(),
-                        value: Operation {
-                            op: OperationKind::Multiply,
-                            lhs: ValueType::Variable("السعر".to_string()),
-                            rhs: ValueType::Variable("العدد".to_string()),
-                        },
+                        value: "السعر * العدد".parse().unwrap(),
+                        result_type: ResultType::Decimal,
                     }),
                 ],
             },
             SheetConfig {
+                strict_formulas: false,
                 row_identity: RowIdentity {
                     id: Arc::from(""),
                     diff_ops: HashMap::new(),
@@ -280,19 +367,16 @@ pub fn get_config_example() {
                     )]),
                 },
                 row: vec![
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("رقم الفاتورة".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("رقم الفاتورة".to_string()))),
                     ConfigValue::Basic(ColumnConfig::Date(fcp("التاريخ".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("كود الصنف".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("كود الصنف".to_string()))),
                     ConfigValue::Basic(ColumnConfig::String(tcp("اسم الصنف".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("الكمية".to_string()))),
-                    ConfigValue::Basic(ColumnConfig::Float(fcp("السعر".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("الكمية".to_string()))),
+                    ConfigValue::Basic(ColumnConfig::Decimal(fcp("السعر".to_string()))),
                     ConfigValue::Calculated(OperationConfig {
                         header: "القيمة".to_string(),
-                        value: Operation {
-                            op: OperationKind::Multiply,
-                            lhs: ValueType::Variable("السعر".to_string()),
-                            rhs: ValueType::Variable("الكمية".to_string()),
-                        },
+                        value: "السعر * الكمية".parse().unwrap(),
+                        result_type: ResultType::Decimal,
                     }),
                 ],
             },
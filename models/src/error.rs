@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, io::Cursor};
+
+use crate::ColumnType;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+    UnknownHeader,
+    CalculatedHeader,
+    WrongType {
+        expected: ColumnType,
+        found: ColumnType,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+    pub row_id: String,
+    pub header: String,
+    pub kind: ViolationKind,
+}
+
+impl Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { header, kind, .. } = self;
+        match kind {
+            ViolationKind::UnknownHeader => write!(f, "{header} عمود غير معروف"),
+            ViolationKind::CalculatedHeader => write!(f, "{header} عمود محسوب"),
+            ViolationKind::WrongType { expected, found } => write!(
+                f,
+                "{header} يجب ان يكون {} وليس {}",
+                expected.as_str(),
+                found.as_str()
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized,
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Invalid(Vec<SchemaViolation>),
+    // the ids of the edited rows sent without the version the client read
+    MissingVersion(Vec<String>),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::BadRequest(_) => 400,
+            Self::Unauthorized => 401,
+            Self::Forbidden(_) => 403,
+            Self::NotFound(_) => 404,
+            Self::Conflict(_) => 409,
+            Self::Invalid(_) => 422,
+            Self::MissingVersion(_) => 400,
+            Self::Internal(_) => 500,
+        }
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        ciborium::ser::into_writer(self, Cursor::new(&mut buf)).unwrap_or_default();
+        buf
+    }
+
+    pub fn from_cbor(code: u16, body: &[u8]) -> Self {
+        ciborium::de::from_reader::<ApiError, _>(Cursor::new(body)).unwrap_or_else(|_| {
+            let body = String::from_utf8_lossy(body).to_string();
+            match code {
+                400 => Self::BadRequest(body),
+                401 => Self::Unauthorized,
+                403 => Self::Forbidden(body),
+                404 => Self::NotFound(body),
+                409 => Self::Conflict(body),
+                422 => Self::Invalid(Vec::new()),
+                _ => Self::Internal(body),
+            }
+        })
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadRequest(msg) => write!(f, "طلب غير صالح ({msg})"),
+            Self::Unauthorized => write!(f, "يجب تسجيل الدخول اولا"),
+            Self::Forbidden(msg) => write!(f, "ليس لديك صلاحية ({msg})"),
+            Self::NotFound(msg) => write!(f, "غير موجود ({msg})"),
+            Self::Conflict(msg) => write!(f, "تعارض مع بيانات موجودة ({msg})"),
+            Self::Invalid(violations) => write!(
+                f,
+                "بيانات غير متوافقة مع نوع الشيت ({})",
+                violations
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" , ")
+            ),
+            Self::MissingVersion(rows_ids) => {
+                write!(f, "صفوف بدون رقم نسخة ({})", rows_ids.join(" , "))
+            }
+            Self::Internal(msg) => write!(f, "خطأ في الخادم ({msg})"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Internal(err.to_string())
+    }
+}
+
+impl From<ciborium::ser::Error<std::io::Error>> for ApiError {
+    fn from(err: ciborium::ser::Error<std::io::Error>) -> Self {
+        Self::Internal(err.to_string())
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for ApiError {
+    fn from(err: ciborium::de::Error<std::io::Error>) -> Self {
+        Self::BadRequest(err.to_string())
+    }
+}
+
+#[cfg(feature = "actix")]
+impl actix_web::ResponseError for ApiError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        actix_web::http::StatusCode::from_u16(self.code())
+            .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        actix_web::HttpResponse::build(self.status_code()).body(self.to_cbor())
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => Self::NotFound(err.to_string()),
+            sqlx::Error::Database(db_err) => match db_err.code().as_deref() {
+                Some("23505") | Some("23503") => Self::Conflict(db_err.message().to_string()),
+                Some("23502") | Some("23514") | Some("22001") => {
+                    Self::BadRequest(db_err.message().to_string())
+                }
+                _ => Self::Internal(err.to_string()),
+            },
+            _ => Self::Internal(err.to_string()),
+        }
+    }
+}
@@ -1,11 +1,16 @@
-use chrono::NaiveDate;
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::hash::Hash;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::{cmp::Ordering, collections::HashMap, marker::Sized, rc::Rc};
 use uuid::Uuid;
 
+mod error;
+pub use error::{ApiError, SchemaViolation, ViolationKind};
+
 pub trait ToSerial<T>: Sized {
     fn to_serial(self) -> T;
 }
@@ -56,13 +61,226 @@ where
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SheetsSort {
+    Name,
+    #[default]
+    InsertDate,
+    RowsCount,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+impl SortDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ascending => "asc",
+            Self::Descending => "desc",
+        }
+    }
+}
+
+impl FromStr for SortDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(Self::Ascending),
+            "desc" => Ok(Self::Descending),
+            _ => Err(format!("unknown sort direction ({s})")),
+        }
+    }
+}
+
+fn default_search_limit() -> i64 {
+    5
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SearchSheetParams {
     pub offset: i64,
+    #[serde(default = "default_search_limit")]
+    pub limit: i64,
     pub begin: Option<NaiveDate>,
     pub end: Option<NaiveDate>,
     pub sheet_name: Option<String>,
     pub sheet_type_name: String,
+    #[serde(default)]
+    pub sort_by: SheetsSort,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SheetsPage<I>
+where
+    I: IdMarker,
+{
+    pub names: Vec<Name<I>>,
+    pub total: i64,
+}
+
+impl ToSerial<SheetsPage<Arc<str>>> for SheetsPage<Uuid> {
+    fn to_serial(self) -> SheetsPage<Arc<str>> {
+        let SheetsPage { names, total } = self;
+        let names = names.into_iter().map(|name| name.to_serial()).collect();
+        SheetsPage { names, total }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ColumnSearchMode {
+    Exact(String),
+    Contains(String),
+    Range {
+        min: Option<BigDecimal>,
+        max: Option<BigDecimal>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColumnSearchParams {
+    pub header_name: String,
+    pub mode: ColumnSearchMode,
+    pub sheet_type_name: Option<String>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SuggestionParams {
+    pub sheet_type_name: String,
+    pub header_name: String,
+    pub prefix: String,
+    pub limit: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AggregateGrouping {
+    #[default]
+    None,
+    Sheet,
+    Month,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AggregateParams {
+    pub sheet_type_name: String,
+    pub headers: Vec<String>,
+    pub begin: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+    #[serde(default)]
+    pub sheets_ids: Vec<Arc<str>>,
+    #[serde(default)]
+    pub group_by: AggregateGrouping,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HeaderAggregate {
+    pub header: String,
+    pub count: i64,
+    pub sum: BigDecimal,
+    pub avg: BigDecimal,
+    pub min: BigDecimal,
+    pub max: BigDecimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AggregateGroup<I>
+where
+    I: IdMarker,
+{
+    pub sheet_id: Option<I>,
+    pub sheet_name: Option<String>,
+    pub month: Option<NaiveDate>,
+    pub aggregates: Vec<HeaderAggregate>,
+}
+
+impl ToSerial<AggregateGroup<Arc<str>>> for AggregateGroup<Uuid> {
+    fn to_serial(self) -> AggregateGroup<Arc<str>> {
+        let AggregateGroup {
+            sheet_id,
+            sheet_name,
+            month,
+            aggregates,
+        } = self;
+        AggregateGroup {
+            sheet_id: sheet_id.map(|id| id.to_serial()),
+            sheet_name,
+            month,
+            aggregates,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrashParams {
+    pub offset: i64,
+    pub sheet_type_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!("unknown role ({s})")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Permissions {
+    pub is_admin: bool,
+    pub roles: HashMap<String, Role>,
+}
+
+impl Permissions {
+    pub fn role(&self, sheet_type_name: &str) -> Option<Role> {
+        if self.is_admin {
+            Some(Role::Admin)
+        } else {
+            self.roles.get(sheet_type_name).copied()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoleGrant {
+    pub username: String,
+    pub sheet_type_name: String,
+    pub role: Option<Role>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -88,7 +306,8 @@ where
     RC: Eq + Hash + ToString,
 {
     String(RC),
-    Float(f64),
+    #[serde(alias = "Float")]
+    Decimal(BigDecimal),
     Date(NaiveDate),
 }
 
@@ -106,13 +325,56 @@ where
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let result = match self {
             Self::String(v) => v.to_string(),
-            Self::Float(v) => format!("{:.2}", v),
+            Self::Decimal(v) => v.round(2).with_scale(2).to_string(),
             Self::Date(v) => v.to_string(),
         };
         write!(f, "{}", result)
     }
 }
 
+impl<T> ColumnValue<T>
+where
+    T: Eq + Hash + ToString,
+{
+    pub fn column_type(&self) -> ColumnType {
+        match self {
+            Self::String(_) => ColumnType::String,
+            Self::Decimal(_) => ColumnType::Decimal,
+            Self::Date(_) => ColumnType::Date,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    String,
+    Decimal,
+    Date,
+}
+
+impl ColumnType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Decimal => "decimal",
+            Self::Date => "date",
+        }
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(Self::String),
+            "decimal" => Ok(Self::Decimal),
+            "date" => Ok(Self::Date),
+            _ => Err(format!("unknown column type ({s})")),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Column<RC>
 where
@@ -128,13 +390,7 @@ where
 {
     fn compare(&self, other: &Column<T>) -> Option<Ordering> {
         match (self.value.clone(), other.value.clone()) {
-            (ColumnValue::Float(n1), ColumnValue::Float(n2)) => Some(if n1 > n2 {
-                Ordering::Greater
-            } else if n1 < n2 {
-                Ordering::Less
-            } else {
-                Ordering::Equal
-            }),
+            (ColumnValue::Decimal(n1), ColumnValue::Decimal(n2)) => Some(n1.cmp(&n2)),
             (ColumnValue::String(s1), ColumnValue::String(s2)) => Some(s1.cmp(&s2)),
             (ColumnValue::Date(b1), ColumnValue::Date(b2)) => Some(b1.cmp(&b2)),
             _ => None,
@@ -150,16 +406,280 @@ where
 {
     pub id: I,
     pub columns: HashMap<RC, Column<RC>>,
+    #[serde(default)]
+    pub version: i64,
 }
 
 impl ToSerial<Row<Arc<str>, Arc<str>>> for Row<Uuid, Arc<str>> {
     fn to_serial(self) -> Row<Arc<str>, Arc<str>> {
-        let Row { id, columns } = self;
+        let Row {
+            id,
+            columns,
+            version,
+        } = self;
         let id = id.to_serial();
-        Row { id, columns }
+        Row {
+            id,
+            columns,
+            version,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RowsPage<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub rows: Vec<Row<I, RC>>,
+    pub next: Option<String>,
+}
+
+impl ToSerial<RowsPage<Arc<str>, Arc<str>>> for RowsPage<Uuid, Arc<str>> {
+    fn to_serial(self) -> RowsPage<Arc<str>, Arc<str>> {
+        let RowsPage { rows, next } = self;
+        RowsPage {
+            rows: rows.into_iter().map(|row| row.to_serial()).collect(),
+            next,
+        }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RowMatch<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub sheet_id: I,
+    pub sheet_name: RC,
+    pub type_name: RC,
+    pub row: Row<I, RC>,
+}
+
+impl ToSerial<RowMatch<Arc<str>, Arc<str>>> for RowMatch<Uuid, Arc<str>> {
+    fn to_serial(self) -> RowMatch<Arc<str>, Arc<str>> {
+        let RowMatch {
+            sheet_id,
+            sheet_name,
+            type_name,
+            row,
+        } = self;
+        RowMatch {
+            sheet_id: sheet_id.to_serial(),
+            sheet_name,
+            type_name,
+            row: row.to_serial(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum SheetChange<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    Rename(RC),
+    DeleteRow(I),
+    AddRow(Row<I, RC>),
+    UpdateColumn {
+        row_id: I,
+        header: RC,
+        value: ColumnValue<RC>,
+    },
+    AddColumn {
+        row_id: I,
+        header: RC,
+        value: ColumnValue<RC>,
+    },
+    DeleteColumn {
+        row_id: I,
+        header: RC,
+    },
+    SetField {
+        header: RC,
+        value: ColumnValue<RC>,
+    },
+    DeleteField(RC),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SheetChangeset<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub sheet_id: I,
+    pub changes: Vec<SheetChange<I, RC>>,
+    pub sheet_version: i64,
+    pub rows_versions: Vec<(I, i64)>,
+}
+
+impl ToSerial<SheetChange<Arc<str>, Arc<str>>> for SheetChange<Uuid, Arc<str>> {
+    fn to_serial(self) -> SheetChange<Arc<str>, Arc<str>> {
+        match self {
+            Self::Rename(name) => SheetChange::Rename(name),
+            Self::DeleteRow(row_id) => SheetChange::DeleteRow(row_id.to_serial()),
+            Self::AddRow(row) => SheetChange::AddRow(row.to_serial()),
+            Self::UpdateColumn {
+                row_id,
+                header,
+                value,
+            } => SheetChange::UpdateColumn {
+                row_id: row_id.to_serial(),
+                header,
+                value,
+            },
+            Self::AddColumn {
+                row_id,
+                header,
+                value,
+            } => SheetChange::AddColumn {
+                row_id: row_id.to_serial(),
+                header,
+                value,
+            },
+            Self::DeleteColumn { row_id, header } => SheetChange::DeleteColumn {
+                row_id: row_id.to_serial(),
+                header,
+            },
+            Self::SetField { header, value } => SheetChange::SetField { header, value },
+            Self::DeleteField(header) => SheetChange::DeleteField(header),
+        }
+    }
+}
+
+impl ToSerial<SheetChangeset<Arc<str>, Arc<str>>> for SheetChangeset<Uuid, Arc<str>> {
+    fn to_serial(self) -> SheetChangeset<Arc<str>, Arc<str>> {
+        let SheetChangeset {
+            sheet_id,
+            changes,
+            sheet_version,
+            rows_versions,
+        } = self;
+        SheetChangeset {
+            sheet_id: sheet_id.to_serial(),
+            changes: changes.into_iter().map(|x| x.to_serial()).collect(),
+            sheet_version,
+            rows_versions: rows_versions
+                .into_iter()
+                .map(|(id, version)| (id.to_serial(), version))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ConflictTarget<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    SheetName,
+    Field(RC),
+    Row(I),
+    Cell { row_id: I, header: RC },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Conflict<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub target: ConflictTarget<I, RC>,
+    pub mine: Option<ColumnValue<RC>>,
+    pub theirs: Option<ColumnValue<RC>>,
+    pub version: i64,
+}
+
+impl ToSerial<Conflict<Arc<str>, Arc<str>>> for Conflict<Uuid, Arc<str>> {
+    fn to_serial(self) -> Conflict<Arc<str>, Arc<str>> {
+        let Conflict {
+            target,
+            mine,
+            theirs,
+            version,
+        } = self;
+        let target = match target {
+            ConflictTarget::SheetName => ConflictTarget::SheetName,
+            ConflictTarget::Field(header) => ConflictTarget::Field(header),
+            ConflictTarget::Row(row_id) => ConflictTarget::Row(row_id.to_serial()),
+            ConflictTarget::Cell { row_id, header } => ConflictTarget::Cell {
+                row_id: row_id.to_serial(),
+                header,
+            },
+        };
+        Conflict {
+            target,
+            mine,
+            theirs,
+            version,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChangesetResult<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub applied: bool,
+    pub errors: Vec<Option<ApiError>>,
+    pub conflicts: Vec<Conflict<I, RC>>,
+    pub sheet_version: i64,
+    pub rows_versions: Vec<(I, i64)>,
+}
+
+impl ToSerial<ChangesetResult<Arc<str>, Arc<str>>> for ChangesetResult<Uuid, Arc<str>> {
+    fn to_serial(self) -> ChangesetResult<Arc<str>, Arc<str>> {
+        let ChangesetResult {
+            applied,
+            errors,
+            conflicts,
+            sheet_version,
+            rows_versions,
+        } = self;
+        ChangesetResult {
+            applied,
+            errors,
+            conflicts: conflicts.into_iter().map(|x| x.to_serial()).collect(),
+            sheet_version,
+            rows_versions: rows_versions
+                .into_iter()
+                .map(|(id, version)| (id.to_serial(), version))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum SheetEventKind<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    Renamed { name: RC, version: i64 },
+    FieldsUpdated { version: i64 },
+    RowsAdded(Vec<I>),
+    RowsDeleted(Vec<I>),
+    RowsUpdated(Vec<(I, i64)>),
+    Missed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SheetEvent<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub sheet_id: I,
+    pub kind: SheetEventKind<I, RC>,
+}
+
 pub trait RowsSort {
     fn sort_rows(&mut self, keys: Rc<[Rc<str>]>);
 }
@@ -195,7 +715,11 @@ where
     pub sheet_name: RC,
     pub type_name: RC,
     pub insert_date: NaiveDate,
+    #[serde(default)]
+    pub fields: HashMap<RC, ColumnValue<RC>>,
     pub rows: Vec<Row<I, RC>>,
+    #[serde(default)]
+    pub version: i64,
 }
 
 impl ToSerial<Sheet<Arc<str>, Arc<str>>> for Sheet<Uuid, Arc<str>> {
@@ -205,7 +729,9 @@ impl ToSerial<Sheet<Arc<str>, Arc<str>>> for Sheet<Uuid, Arc<str>> {
             sheet_name,
             type_name,
             insert_date,
+            fields,
             rows,
+            version,
         } = self;
         let id = id.to_serial();
         let rows = rows
@@ -217,7 +743,130 @@ impl ToSerial<Sheet<Arc<str>, Arc<str>>> for Sheet<Uuid, Arc<str>> {
             sheet_name,
             type_name,
             insert_date,
+            fields,
             rows,
+            version,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    CreateSheet,
+    RenameSheet,
+    DeleteSheet,
+    RestoreSheet,
+    PurgeSheet,
+    AddRow,
+    DeleteRow,
+    AddColumn,
+    UpdateColumn,
+    DeleteColumn,
+    SetField,
+    DeleteField,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::CreateSheet => "create_sheet",
+            Self::RenameSheet => "rename_sheet",
+            Self::DeleteSheet => "delete_sheet",
+            Self::RestoreSheet => "restore_sheet",
+            Self::PurgeSheet => "purge_sheet",
+            Self::AddRow => "add_row",
+            Self::DeleteRow => "delete_row",
+            Self::AddColumn => "add_column",
+            Self::UpdateColumn => "update_column",
+            Self::DeleteColumn => "delete_column",
+            Self::SetField => "set_field",
+            Self::DeleteField => "delete_field",
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create_sheet" => Ok(Self::CreateSheet),
+            "rename_sheet" => Ok(Self::RenameSheet),
+            "delete_sheet" => Ok(Self::DeleteSheet),
+            "restore_sheet" => Ok(Self::RestoreSheet),
+            "purge_sheet" => Ok(Self::PurgeSheet),
+            "add_row" => Ok(Self::AddRow),
+            "delete_row" => Ok(Self::DeleteRow),
+            "add_column" => Ok(Self::AddColumn),
+            "update_column" => Ok(Self::UpdateColumn),
+            "delete_column" => Ok(Self::DeleteColumn),
+            "set_field" => Ok(Self::SetField),
+            "delete_field" => Ok(Self::DeleteField),
+            _ => Err(format!("unknown audit action ({s})")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AuditEntry<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub id: I,
+    pub sheet_id: I,
+    pub row_id: Option<I>,
+    pub header: Option<RC>,
+    pub action: AuditAction,
+    pub old_value: Option<ColumnValue<RC>>,
+    pub new_value: Option<ColumnValue<RC>>,
+    pub username: Option<RC>,
+    pub time: NaiveDateTime,
+}
+
+impl ToSerial<AuditEntry<Arc<str>, Arc<str>>> for AuditEntry<Uuid, Arc<str>> {
+    fn to_serial(self) -> AuditEntry<Arc<str>, Arc<str>> {
+        let AuditEntry {
+            id,
+            sheet_id,
+            row_id,
+            header,
+            action,
+            old_value,
+            new_value,
+            username,
+            time,
+        } = self;
+        AuditEntry {
+            id: id.to_serial(),
+            sheet_id: sheet_id.to_serial(),
+            row_id: row_id.map(|x| x.to_serial()),
+            header,
+            action,
+            old_value,
+            new_value,
+            username,
+            time,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistoryPage<I, RC>
+where
+    RC: Eq + Hash + ToString,
+    I: IdMarker,
+{
+    pub entries: Vec<AuditEntry<I, RC>>,
+    pub next: Option<String>,
+}
+
+impl ToSerial<HistoryPage<Arc<str>, Arc<str>>> for HistoryPage<Uuid, Arc<str>> {
+    fn to_serial(self) -> HistoryPage<Arc<str>, Arc<str>> {
+        let HistoryPage { entries, next } = self;
+        HistoryPage {
+            entries: entries.into_iter().map(|entry| entry.to_serial()).collect(),
+            next,
         }
     }
 }
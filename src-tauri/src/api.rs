@@ -1,6 +1,11 @@
 use anyhow::{Error, Ok};
-use models::{ColumnId, ColumnValue, Name, Row, SearchSheetParams, Sheet, ToSerial};
-use reqwest::StatusCode;
+use client_models::SheetTypeConfig;
+use models::{
+    AggregateGroup, AggregateParams, ApiError, ChangesetResult, ColumnSearchParams, Credentials,
+    HistoryPage, Name, Permissions, RowMatch, RowsPage, SearchSheetParams, Sheet, SheetChangeset,
+    SheetEvent, SheetsPage, SortDirection, SuggestionParams, ToSerial, TrashParams,
+};
+use reqwest::{RequestBuilder, StatusCode};
 use uuid::Uuid;
 
 use std::io::Cursor;
@@ -9,36 +14,108 @@ use std::sync::Arc;
 
 use crate::AppState;
 
-pub async fn save_sheet(app_state: &AppState, sheet: Sheet<Uuid, Arc<str>>) -> anyhow::Result<()> {
+trait Authorized {
+    fn authorized(self, app_state: &AppState) -> Self;
+}
+
+impl Authorized for RequestBuilder {
+    fn authorized(self, app_state: &AppState) -> Self {
+        match app_state.token() {
+            Some(token) => self.bearer_auth(token),
+            None => self,
+        }
+    }
+}
+
+async fn api_error(res: reqwest::Response) -> Error {
+    let code = res.status().as_u16();
+    let body = res.bytes().await.unwrap_or_default();
+    Error::new(ApiError::from_cbor(code, &body))
+}
+
+pub async fn login(app_state: &AppState, credentials: Credentials) -> anyhow::Result<Uuid> {
     let mut buffer = vec![];
-    let sheet = sheet.to_serial();
-    ciborium::ser::into_writer(&sheet, Cursor::new(&mut buffer))?;
+    ciborium::ser::into_writer(&credentials, Cursor::new(&mut buffer))?;
 
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .post(format!("{origin}/sheet/"))
+        .post(format!("{origin}/auth/login"))
         .body(buffer)
         .send()
         .await?;
 
+    if res.status() == StatusCode::OK {
+        let body = res.bytes().await?;
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<Uuid>()?;
+        Ok(body)
+    } else if res.status() == StatusCode::UNAUTHORIZED {
+        Err(Error::msg("اسم المستخدم او كلمة المرور غير صحيحة"))
+    } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn logout(app_state: &AppState) -> anyhow::Result<()> {
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .post(format!("{origin}/auth/logout"))
+        .authorized(app_state)
+        .send()
+        .await?;
+
     if res.status() == StatusCode::OK {
         Ok(())
     } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn current_user(app_state: &AppState) -> anyhow::Result<Name<Uuid>> {
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .get(format!("{origin}/auth/me"))
+        .authorized(app_state)
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::OK {
         let body = res.bytes().await?;
         let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
-        let body = body.deserialized::<String>()?;
-        Err(Error::msg(body))
+        let body = body.deserialized::<Name<Uuid>>()?;
+        Ok(body)
+    } else {
+        Err(api_error(res).await)
     }
 }
 
-pub async fn update_sheet_name(app_state: &AppState, name: Name<Uuid>) -> anyhow::Result<()> {
+pub async fn current_permissions(app_state: &AppState) -> anyhow::Result<Permissions> {
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .get(format!("{origin}/auth/me/permissions"))
+        .authorized(app_state)
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::OK {
+        let body = res.bytes().await?;
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<Permissions>()?;
+        Ok(body)
+    } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn save_sheet(app_state: &AppState, sheet: Sheet<Uuid, Arc<str>>) -> anyhow::Result<()> {
     let mut buffer = vec![];
-    let name = name.to_serial();
-    ciborium::ser::into_writer(&name, Cursor::new(&mut buffer))?;
+    let sheet = sheet.to_serial();
+    ciborium::ser::into_writer(&sheet, Cursor::new(&mut buffer))?;
 
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .put(format!("{origin}/sheet/name"))
+        .post(format!("{origin}/sheet/"))
+        .authorized(app_state)
         .body(buffer)
         .send()
         .await?;
@@ -46,221 +123,334 @@ pub async fn update_sheet_name(app_state: &AppState, name: Name<Uuid>) -> anyhow
     if res.status() == StatusCode::OK {
         Ok(())
     } else {
-        let body = res.bytes().await?;
-        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
-        let body = body.deserialized::<String>()?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn update_columns(
+pub async fn apply_sheet_changeset(
     app_state: &AppState,
-    args: Vec<(ColumnId<Uuid, Arc<str>>, ColumnValue<Arc<str>>)>,
-) -> anyhow::Result<()> {
+    changeset: SheetChangeset<Uuid, Arc<str>>,
+) -> anyhow::Result<ChangesetResult<Uuid, Arc<str>>> {
     let mut buffer = vec![];
-    let args = args
-        .into_iter()
-        .map(|(col, val)| (col.to_serial(), val))
-        .collect::<Vec<_>>();
-    ciborium::ser::into_writer(&args, Cursor::new(&mut buffer))?;
+    let changeset = changeset.to_serial();
+    ciborium::ser::into_writer(&changeset, Cursor::new(&mut buffer))?;
 
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .put(format!("{origin}/columns/"))
+        .post(format!("{origin}/sheet/changeset"))
+        .authorized(app_state)
         .body(buffer)
         .send()
         .await?;
 
-    if res.status() == StatusCode::OK {
-        Ok(())
+    if res.status() == StatusCode::OK || res.status() == StatusCode::CONFLICT {
+        let body = res.bytes().await.unwrap_or_default();
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<ChangesetResult<Uuid, Arc<str>>>()?;
+        Ok(body)
     } else {
-        let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn save_columns(
+pub async fn search_sheets(
     app_state: &AppState,
-    args: Vec<(ColumnId<Uuid, Arc<str>>, ColumnValue<Arc<str>>)>,
-) -> anyhow::Result<()> {
+    params: &SearchSheetParams,
+) -> anyhow::Result<SheetsPage<Uuid>> {
     let mut buffer = vec![];
-    let args = args
-        .into_iter()
-        .map(|(col, val)| (col.to_serial(), val))
-        .collect::<Vec<_>>();
-    ciborium::ser::into_writer(&args, Cursor::new(&mut buffer))?;
+    ciborium::ser::into_writer(&params, Cursor::new(&mut buffer))?;
 
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .post(format!("{origin}/columns/"))
+        .post(format!("{origin}/sheet/search"))
+        .authorized(app_state)
         .body(buffer)
         .send()
         .await?;
 
     if res.status() == StatusCode::OK {
-        Ok(())
+        let body = res.bytes().await.unwrap_or_default();
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<SheetsPage<Uuid>>()?;
+        Ok(body)
     } else {
-        let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn delete_columns(
+pub async fn get_sheet_by_id(
     app_state: &AppState,
-    ids: Vec<ColumnId<Uuid, Arc<str>>>,
-) -> anyhow::Result<()> {
-    let mut buffer = vec![];
-    let ids = ids
-        .into_iter()
-        .map(|col| col.to_serial())
+    id: &Uuid,
+) -> anyhow::Result<(Sheet<Uuid, Arc<str>>, i64)> {
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .get(format!("{origin}/sheet/{id}"))
+        .authorized(app_state)
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::OK {
+        let body = res.bytes().await.unwrap_or_default();
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<(Sheet<Uuid, Arc<str>>, i64)>()?;
+
+        Ok(body)
+    } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn get_sheet_rows_after(
+    app_state: &AppState,
+    id: &Uuid,
+    after: Option<&str>,
+    limit: i64,
+    order: &[(Arc<str>, SortDirection)],
+) -> anyhow::Result<RowsPage<Uuid, Arc<str>>> {
+    let mut params = order
+        .iter()
+        .map(|(header, direction)| (direction.as_str(), header.as_ref()))
         .collect::<Vec<_>>();
-    ciborium::ser::into_writer(&ids, Cursor::new(&mut buffer))?;
+    if let Some(after) = after {
+        params.push(("after", after));
+    }
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .get(format!("{origin}/sheet/{id}/rows/{limit}"))
+        .query(&params)
+        .authorized(app_state)
+        .send()
+        .await?;
 
+    if res.status() == StatusCode::OK {
+        let body = res.bytes().await.unwrap_or_default();
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<RowsPage<Uuid, Arc<str>>>()?;
+
+        Ok(body)
+    } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn get_sheet_history(
+    app_state: &AppState,
+    id: &Uuid,
+    after: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<HistoryPage<Uuid, Arc<str>>> {
+    let mut params = Vec::new();
+    if let Some(after) = after {
+        params.push(("after", after));
+    }
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .post(format!("{origin}/columns/delete"))
-        .body(buffer)
+        .get(format!("{origin}/sheet/{id}/history/{limit}"))
+        .query(&params)
+        .authorized(app_state)
         .send()
         .await?;
 
     if res.status() == StatusCode::OK {
-        Ok(())
+        let body = res.bytes().await.unwrap_or_default();
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<HistoryPage<Uuid, Arc<str>>>()?;
+
+        Ok(body)
     } else {
-        let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn add_rows_to_sheet(
+pub async fn trashed_sheets(
     app_state: &AppState,
-    sheet_id: Uuid,
-    rows: Vec<Row<Uuid, Arc<str>>>,
-) -> anyhow::Result<()> {
+    params: &TrashParams,
+) -> anyhow::Result<Vec<Name<Uuid>>> {
     let mut buffer = vec![];
-    let rows = rows
-        .into_iter()
-        .map(|col| col.to_serial())
-        .collect::<Vec<_>>();
-    ciborium::ser::into_writer(&rows, Cursor::new(&mut buffer))?;
+    ciborium::ser::into_writer(&params, Cursor::new(&mut buffer))?;
 
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .post(format!("{origin}/sheet/{sheet_id}/rows"))
+        .post(format!("{origin}/sheet/trash"))
+        .authorized(app_state)
         .body(buffer)
         .send()
         .await?;
 
+    if res.status() == StatusCode::OK {
+        let body = res.bytes().await.unwrap_or_default();
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<Vec<Name<Uuid>>>()?;
+        Ok(body)
+    } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn trash_sheet(app_state: &AppState, id: &Uuid) -> anyhow::Result<()> {
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .delete(format!("{origin}/sheet/{id}"))
+        .authorized(app_state)
+        .send()
+        .await?;
+
     if res.status() == StatusCode::OK {
         Ok(())
     } else {
-        let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn delete_rows_from_sheet(
-    app_state: &AppState,
-    sheet_id: Uuid,
-    rows: Vec<Uuid>,
-) -> anyhow::Result<()> {
-    let mut buffer = vec![];
-    let rows = rows
-        .into_iter()
-        .map(|col| col.to_serial())
-        .collect::<Vec<_>>();
-    ciborium::ser::into_writer(&rows, Cursor::new(&mut buffer))?;
+pub async fn restore_sheet(app_state: &AppState, id: &Uuid) -> anyhow::Result<()> {
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .put(format!("{origin}/sheet/{id}/restore"))
+        .authorized(app_state)
+        .send()
+        .await?;
 
+    if res.status() == StatusCode::OK {
+        Ok(())
+    } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn purge_sheet(app_state: &AppState, id: &Uuid) -> anyhow::Result<()> {
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .post(format!("{origin}/sheet/delete/{sheet_id}/rows"))
-        .body(buffer)
+        .delete(format!("{origin}/sheet/{id}/purge"))
+        .authorized(app_state)
         .send()
         .await?;
 
     if res.status() == StatusCode::OK {
         Ok(())
     } else {
-        let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn search_for_5_sheets(
+pub async fn search_columns(
     app_state: &AppState,
-    params: &SearchSheetParams,
-) -> anyhow::Result<Vec<Name<Uuid>>> {
+    params: &ColumnSearchParams,
+) -> anyhow::Result<Vec<RowMatch<Uuid, Arc<str>>>> {
     let mut buffer = vec![];
     ciborium::ser::into_writer(&params, Cursor::new(&mut buffer))?;
 
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .post(format!("{origin}/sheet/search"))
+        .post(format!("{origin}/columns/search"))
+        .authorized(app_state)
         .body(buffer)
         .send()
         .await?;
 
     if res.status() == StatusCode::OK {
         let body = res.bytes().await.unwrap_or_default();
-        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))
-            .map(|body| body.deserialized::<Vec<Name<Uuid>>>().unwrap_or_default())
-            .unwrap_or_default();
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<Vec<RowMatch<Uuid, Arc<str>>>>()?;
         Ok(body)
     } else {
-        let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn get_sheet_by_id(
+pub async fn column_suggestions(
     app_state: &AppState,
-    id: &Uuid,
-) -> anyhow::Result<(Sheet<Uuid, Arc<str>>, i64)> {
+    params: &SuggestionParams,
+) -> anyhow::Result<Vec<String>> {
+    let mut buffer = vec![];
+    ciborium::ser::into_writer(&params, Cursor::new(&mut buffer))?;
+
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .get(format!("{origin}/sheet/{id}"))
+        .post(format!("{origin}/columns/suggestions"))
+        .authorized(app_state)
+        .body(buffer)
         .send()
         .await?;
 
     if res.status() == StatusCode::OK {
         let body = res.bytes().await.unwrap_or_default();
         let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
-        let body = body.deserialized::<(Sheet<Uuid, Arc<str>>, i64)>()?;
-
+        let body = body.deserialized::<Vec<String>>()?;
         Ok(body)
     } else {
-        let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        Err(api_error(res).await)
     }
 }
 
-pub async fn get_sheet_rows_between(
+pub async fn aggregate_columns(
     app_state: &AppState,
-    id: &Uuid,
-    offset: i64,
-    limit: i64,
-) -> anyhow::Result<Vec<Row<Uuid, Arc<str>>>> {
+    params: &AggregateParams,
+) -> anyhow::Result<Vec<AggregateGroup<Uuid>>> {
+    let mut buffer = vec![];
+    ciborium::ser::into_writer(&params, Cursor::new(&mut buffer))?;
+
     let origin = &app_state.origin;
     let res = reqwest::Client::new()
-        .get(format!("{origin}/sheet/{id}/{offset}/{limit}"))
+        .post(format!("{origin}/aggregate/"))
+        .authorized(app_state)
+        .body(buffer)
         .send()
         .await?;
 
     if res.status() == StatusCode::OK {
         let body = res.bytes().await.unwrap_or_default();
         let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
-        let body = body.deserialized::<Vec<Row<Uuid, Arc<str>>>>()?;
-
+        let body = body.deserialized::<Vec<AggregateGroup<Uuid>>>()?;
         Ok(body)
     } else {
+        Err(api_error(res).await)
+    }
+}
+
+pub async fn sheet_events(
+    app_state: &AppState,
+    sheet_id: Uuid,
+    mut on_event: impl FnMut(SheetEvent<Uuid, Arc<str>>),
+) -> anyhow::Result<()> {
+    let origin = &app_state.origin;
+    let mut res = reqwest::Client::new()
+        .get(format!("{origin}/events/sheet/{sheet_id}"))
+        .authorized(app_state)
+        .send()
+        .await?;
+
+    if res.status() != StatusCode::OK {
+        return Err(api_error(res).await);
+    }
+    let mut buffer = Vec::<u8>::new();
+    while let Some(chunk) = res.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.windows(2).position(|x| x == b"\n\n") {
+            let message = buffer.drain(..end + 2).collect::<Vec<_>>();
+            for line in String::from_utf8_lossy(&message).lines() {
+                if let Some(data) = line.strip_prefix("data: ") {
+                    on_event(serde_json::from_str(data)?);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn sheet_types(app_state: &AppState) -> anyhow::Result<Vec<SheetTypeConfig>> {
+    let origin = &app_state.origin;
+    let res = reqwest::Client::new()
+        .get(format!("{origin}/sheet-types/"))
+        .authorized(app_state)
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::OK {
         let body = res.bytes().await?;
-        let body = String::from_utf8(body.to_vec())?;
-        Err(Error::msg(body))
+        let body = ciborium::de::from_reader::<ciborium::Value, _>(Cursor::new(body))?;
+        let body = body.deserialized::<Vec<SheetTypeConfig>>()?;
+        Ok(body)
+    } else {
+        Err(api_error(res).await)
     }
 }
@@ -4,11 +4,24 @@
 mod api;
 
 use anyhow::Result;
+use bigdecimal::{BigDecimal, ToPrimitive};
 use chrono::{Local, NaiveDate};
 use client_models::{Config, ConfigValue, ImportConfig, RowIdentity, SheetConfig};
 use dotenv::dotenv;
-use models::{Column, ColumnId, ColumnValue, Name, Row, SearchSheetParams, Sheet};
-use std::{collections::HashMap, env, io::Cursor, ops::Deref, path::Path, sync::Arc};
+use models::{
+    AggregateGroup, AggregateParams, ChangesetResult, Column, ColumnSearchParams, ColumnValue,
+    Credentials, HistoryPage, Name, Role, Row, RowMatch, RowsPage, SearchSheetParams, Sheet,
+    SheetChangeset, SheetEvent, SheetEventKind, SheetsPage, SortDirection, SuggestionParams,
+    TrashParams,
+};
+use std::{
+    collections::HashMap,
+    env,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard},
+};
+use tauri::{async_runtime::JoinHandle, Manager};
 use tokio::io::AsyncReadExt;
 use uuid::Uuid;
 
@@ -17,18 +30,78 @@ use rust_xlsxwriter::{Color, Format, FormatBorder, Workbook};
 use serde_json::Value;
 
 #[tauri::command]
-fn sheets_types_names(sheets_types_names: tauri::State<'_, SheetsTypesNames>) -> Vec<Name<Uuid>> {
-    sheets_types_names.0.clone()
+async fn login(
+    app_state: tauri::State<'_, AppState>,
+    sheet_types: tauri::State<'_, SheetTypes>,
+    username: String,
+    password: String,
+) -> Result<Option<String>, String> {
+    if username.is_empty() || password.is_empty() {
+        return Err("اسم المستخدم وكلمة المرور مطلوبان".to_string());
+    }
+    match api::login(&app_state, Credentials { username, password }).await {
+        Ok(token) => {
+            app_state.set_token(Some(token));
+            // the login still succeeds, the cached copy of the config stays in use
+            let warning = refresh_sheet_types(&app_state, &sheet_types)
+                .await
+                .err()
+                .map(|err| format!("تعذر تحديث اعدادات انواع الشيتات ({err})"));
+            Ok(warning)
+        }
+        Err(err) => Err(err.to_string()),
+    }
 }
 
 #[tauri::command]
-fn sheet_type_name(
-    sheets_types_names: tauri::State<'_, SheetsTypesNames>,
-    id: Option<Uuid>,
-) -> String {
+async fn logout(app_state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let result = api::logout(&app_state).await;
+    app_state.set_token(None);
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn current_user(app_state: tauri::State<'_, AppState>) -> Result<Option<String>, String> {
+    if app_state.token().is_none() {
+        return Ok(None);
+    }
+    match api::current_user(&app_state).await {
+        Ok(user) => Ok(Some(user.the_name)),
+        Err(_) => {
+            app_state.set_token(None);
+            Ok(None)
+        }
+    }
+}
+
+#[tauri::command]
+async fn sheet_role(
+    app_state: tauri::State<'_, AppState>,
+    name: Option<Arc<str>>,
+) -> Result<Option<Role>, String> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    match api::current_permissions(&app_state).await {
+        Ok(permissions) => Ok(permissions.role(&name)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+fn sheets_types_names(sheet_types: tauri::State<'_, SheetTypes>) -> Vec<Name<Uuid>> {
+    sheet_types.read().names.clone()
+}
+
+#[tauri::command]
+fn sheet_type_name(sheet_types: tauri::State<'_, SheetTypes>, id: Option<Uuid>) -> String {
     match id {
-        Some(id) => sheets_types_names
-            .0
+        Some(id) => sheet_types
+            .read()
+            .names
             .clone()
             .into_iter()
             .filter(|x| x.id == id)
@@ -43,12 +116,13 @@ fn sheet_type_name(
 
 #[tauri::command]
 fn sheet_primary_headers(
-    sheet_import: tauri::State<'_, SheetImport>,
+    sheet_types: tauri::State<'_, SheetTypes>,
     name: Option<Arc<str>>,
 ) -> Vec<String> {
     match name {
-        Some(name) => sheet_import
-            .0
+        Some(name) => sheet_types
+            .read()
+            .importing
             .get(&name)
             .unwrap_or_else(|| panic!("expected name ({}) to exist", name))
             .primary
@@ -61,12 +135,13 @@ fn sheet_primary_headers(
 
 #[tauri::command]
 fn sheet_headers(
-    sheets_rows: tauri::State<'_, SheetsRows>,
+    sheet_types: tauri::State<'_, SheetTypes>,
     name: Option<Arc<str>>,
 ) -> Vec<ConfigValue> {
     match name {
-        Some(name) => sheets_rows
-            .0
+        Some(name) => sheet_types
+            .read()
+            .rows
             .get(&name)
             .unwrap_or_else(|| panic!("expected name ({}) to exist", name))
             .to_vec(),
@@ -80,6 +155,7 @@ async fn save_sheet(
     sheetid: Uuid,
     sheetname: Arc<str>,
     typename: Arc<str>,
+    fields: HashMap<Arc<str>, ColumnValue<Arc<str>>>,
     rows: Vec<Row<Uuid, Arc<str>>>,
 ) -> Result<(), String> {
     if sheetname.is_empty() {
@@ -90,7 +166,9 @@ async fn save_sheet(
         sheet_name: sheetname,
         type_name: typename,
         insert_date: Local::now().date_naive(),
+        fields,
         rows,
+        version: 0,
     };
     match api::save_sheet(&app_state, sheet).await {
         Ok(_) => Ok(()),
@@ -99,16 +177,84 @@ async fn save_sheet(
 }
 
 #[tauri::command]
-async fn top_5_sheets(
+async fn search_sheets(
     app_state: tauri::State<'_, AppState>,
     params: SearchSheetParams,
+) -> Result<SheetsPage<Uuid>, String> {
+    match api::search_sheets(&app_state, &params).await {
+        Ok(names) => Ok(names),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn search_columns(
+    app_state: tauri::State<'_, AppState>,
+    params: ColumnSearchParams,
+) -> Result<Vec<RowMatch<Uuid, Arc<str>>>, String> {
+    match api::search_columns(&app_state, &params).await {
+        Ok(matches) => Ok(matches),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn column_suggestions(
+    app_state: tauri::State<'_, AppState>,
+    params: SuggestionParams,
+) -> Result<Vec<String>, String> {
+    match api::column_suggestions(&app_state, &params).await {
+        Ok(suggestions) => Ok(suggestions),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn aggregate_columns(
+    app_state: tauri::State<'_, AppState>,
+    params: AggregateParams,
+) -> Result<Vec<AggregateGroup<Uuid>>, String> {
+    match api::aggregate_columns(&app_state, &params).await {
+        Ok(groups) => Ok(groups),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn trashed_sheets(
+    app_state: tauri::State<'_, AppState>,
+    params: TrashParams,
 ) -> Result<Vec<Name<Uuid>>, String> {
-    match api::search_for_5_sheets(&app_state, &params).await {
+    match api::trashed_sheets(&app_state, &params).await {
         Ok(names) => Ok(names),
         Err(err) => Err(err.to_string()),
     }
 }
 
+#[tauri::command]
+async fn trash_sheet(app_state: tauri::State<'_, AppState>, id: Uuid) -> Result<(), String> {
+    match api::trash_sheet(&app_state, &id).await {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn restore_sheet(app_state: tauri::State<'_, AppState>, id: Uuid) -> Result<(), String> {
+    match api::restore_sheet(&app_state, &id).await {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn purge_sheet(app_state: tauri::State<'_, AppState>, id: Uuid) -> Result<(), String> {
+    match api::purge_sheet(&app_state, &id).await {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
 #[tauri::command]
 async fn get_sheet(
     app_state: tauri::State<'_, AppState>,
@@ -127,151 +273,106 @@ async fn get_sheet(
 async fn get_sheet_rows(
     app_state: tauri::State<'_, AppState>,
     id: Option<Uuid>,
-    offset: i64,
+    after: Option<String>,
     limit: i64,
-) -> Result<Vec<Row<Uuid, Arc<str>>>, String> {
-    match id {
-        Some(id) => match api::get_sheet_rows_between(&app_state, &id, offset, limit).await {
-            Ok(rows) => Ok(rows),
-            Err(err) => Err(err.to_string()),
-        },
-        None => Err("id is none".to_string()),
+    order: Vec<(Arc<str>, SortDirection)>,
+) -> Result<RowsPage<Uuid, Arc<str>>, String> {
+    let Some(id) = id else {
+        return Err("id is none".to_string());
+    };
+    match api::get_sheet_rows_after(&app_state, &id, after.as_deref(), limit, &order).await {
+        Ok(page) => Ok(page),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn get_sheet_history(
+    app_state: tauri::State<'_, AppState>,
+    id: Option<Uuid>,
+    after: Option<String>,
+    limit: i64,
+) -> Result<HistoryPage<Uuid, Arc<str>>, String> {
+    let Some(id) = id else {
+        return Err("id is none".to_string());
+    };
+    match api::get_sheet_history(&app_state, &id, after.as_deref(), limit).await {
+        Ok(page) => Ok(page),
+        Err(err) => Err(err.to_string()),
     }
 }
 
 #[tauri::command]
 async fn get_rows_ids(
-    sheet_rows_ids: tauri::State<'_, SheetRowsIds>,
+    sheet_types: tauri::State<'_, SheetTypes>,
     name: Option<Arc<str>>,
 ) -> Result<RowIdentity<Arc<str>>, String> {
     let Some(name) = name else {
         return Err("id does not exist".to_string());
     };
-    match sheet_rows_ids.0.get(&name) {
+    match sheet_types.read().rows_ids.get(&name) {
         Some(result) => Ok(result.clone()),
         None => Err("id does not exist".to_string()),
     }
 }
 
-type PrioritiesParam = HashMap<Arc<str>, Arc<[Arc<str>]>>;
+#[tauri::command]
+fn sheet_strict_formulas(
+    sheet_types: tauri::State<'_, SheetTypes>,
+    name: Option<Arc<str>>,
+) -> bool {
+    name.and_then(|name| sheet_types.read().strict_formulas.get(&name).copied())
+        .unwrap_or_default()
+}
 
 #[tauri::command]
 async fn get_priorities(
-    priorities: tauri::State<'_, PrioritiesParam>,
+    sheet_types: tauri::State<'_, SheetTypes>,
     name: Option<Arc<str>>,
 ) -> Result<Arc<[Arc<str>]>, String> {
     let Some(name) = name else {
         return Ok(Arc::from([]));
     };
-    match priorities.get(&name) {
+    match sheet_types.read().priorities.get(&name) {
         Some(list) => Ok(list.clone()),
         None => Err("priority does not exist".to_string()),
     }
 }
 
 #[tauri::command]
-async fn update_sheet_name(
+async fn apply_sheet_changeset(
     app_state: tauri::State<'_, AppState>,
-    name: Name<Uuid>,
-) -> Result<(), String> {
-    match api::update_sheet_name(&app_state, name).await {
-        Ok(_) => Ok(()),
+    changeset: SheetChangeset<Uuid, Arc<str>>,
+) -> Result<ChangesetResult<Uuid, Arc<str>>, String> {
+    match api::apply_sheet_changeset(&app_state, changeset).await {
+        Ok(result) => Ok(result),
         Err(err) => Err(err.to_string()),
     }
 }
 
-#[tauri::command]
-async fn add_rows_to_sheet(
-    app_state: tauri::State<'_, AppState>,
-    sheetid: Uuid,
-    rows: Vec<Row<Uuid, Arc<str>>>,
-) -> Result<(), String> {
-    match api::add_rows_to_sheet(&app_state, sheetid, rows).await {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err.to_string()),
-    }
-}
+const SHEET_EVENT: &str = "sheet_event";
 
 #[tauri::command]
-async fn delete_rows_from_sheet(
-    app_state: tauri::State<'_, AppState>,
-    sheetid: Uuid,
-    rowsids: Vec<Uuid>,
-) -> Result<(), String> {
-    match api::delete_rows_from_sheet(&app_state, sheetid, rowsids).await {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err.to_string()),
-    }
-}
-
-#[tauri::command]
-async fn delete_columns(
-    app_state: tauri::State<'_, AppState>,
-    sheetid: Uuid,
-    rowsheaders: Vec<(Uuid, Arc<str>)>,
-) -> Result<(), String> {
-    let columns_ids = rowsheaders
-        .into_iter()
-        .map(|(row_id, header)| ColumnId {
-            sheet_id: sheetid,
-            row_id,
-            header,
-        })
-        .collect::<Vec<_>>();
-    match api::delete_columns(&app_state, columns_ids).await {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err.to_string()),
-    }
-}
-
-#[tauri::command]
-async fn save_columns(
-    app_state: tauri::State<'_, AppState>,
-    sheetid: Uuid,
-    columnsidentifiers: Vec<(Uuid, Arc<str>, ColumnValue<Arc<str>>)>,
-) -> Result<(), String> {
-    let columns_ids = columnsidentifiers
-        .into_iter()
-        .map(|(row_id, header, value)| {
-            (
-                ColumnId {
-                    sheet_id: sheetid,
-                    row_id,
-                    header,
-                },
-                value,
-            )
+fn subscribe_sheet_events(
+    app_handle: tauri::AppHandle,
+    sheet_events_task: tauri::State<'_, SheetEventsTask>,
+    id: Uuid,
+) {
+    let task = tauri::async_runtime::spawn(async move {
+        let app_state = app_handle.state::<AppState>();
+        let _ = api::sheet_events(&app_state, id, |event| {
+            let _ = app_handle.emit_all(SHEET_EVENT, event);
         })
-        .collect::<Vec<_>>();
-    match api::save_columns(&app_state, columns_ids).await {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err.to_string()),
-    }
+        .await;
+        let kind = SheetEventKind::Missed;
+        let _ = app_handle.emit_all(SHEET_EVENT, SheetEvent { sheet_id: id, kind });
+    });
+    sheet_events_task.replace(Some(task));
 }
 
 #[tauri::command]
-async fn update_columns(
-    app_state: tauri::State<'_, AppState>,
-    sheetid: Uuid,
-    columnsidentifiers: Vec<(Uuid, Arc<str>, ColumnValue<Arc<str>>)>,
-) -> Result<(), String> {
-    let columns_ids = columnsidentifiers
-        .into_iter()
-        .map(|(row_id, header, value)| {
-            (
-                ColumnId {
-                    sheet_id: sheetid,
-                    row_id,
-                    header,
-                },
-                value,
-            )
-        })
-        .collect::<Vec<_>>();
-    match api::update_columns(&app_state, columns_ids).await {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err.to_string()),
-    }
+fn unsubscribe_sheet_events(sheet_events_task: tauri::State<'_, SheetEventsTask>) {
+    sheet_events_task.replace(None);
 }
 
 #[tauri::command]
@@ -300,11 +401,9 @@ fn column_from_value(value: &Value) -> Column<Arc<str>> {
     Column {
         is_basic: true,
         value: match value {
-            Value::Number(_) => {
-                ColumnValue::Float(serde_json::from_value(value.to_owned()).unwrap_or(0.0))
-            }
-            Value::String(v) => match v.parse::<f64>() {
-                Ok(v) => ColumnValue::Float(v),
+            Value::Number(v) => ColumnValue::Decimal(v.to_string().parse().unwrap_or_default()),
+            Value::String(v) => match v.parse::<BigDecimal>() {
+                Ok(v) => ColumnValue::Decimal(v),
                 Err(_) => match v
                     .get(..10)
                     .unwrap_or("unparsable string to date")
@@ -326,30 +425,35 @@ async fn file_content(path: &str) -> anyhow::Result<String> {
     Ok(buf)
 }
 
+type ImportedSheet = (
+    HashMap<Arc<str>, ColumnValue<Arc<str>>>,
+    Vec<Row<Uuid, Arc<str>>>,
+);
+
 #[tauri::command]
 async fn import_sheet(
-    sheet_import: tauri::State<'_, SheetImport>,
+    sheet_types: tauri::State<'_, SheetTypes>,
     sheettype: Arc<str>,
-    sheetid: Uuid,
     filepath: String,
-) -> Result<Vec<Row<Uuid, Arc<str>>>, String> {
+) -> Result<ImportedSheet, String> {
+    let importing = sheet_types.read().importing.get(&sheettype).cloned();
     let Some(ImportConfig {
         main_entry,
         repeated_entry,
         unique,
         repeated,
         primary,
-    }) = sheet_import.get(&sheettype)
+    }) = importing
     else {
-        return Ok(vec![]);
+        return Ok(ImportedSheet::default());
     };
     let Ok(main_json) = file_content(&filepath).await else {
-        return Ok(vec![]);
+        return Ok(ImportedSheet::default());
     };
     let Ok(main_json) = serde_json::from_str::<Value>(&main_json) else {
-        return Ok(vec![]);
+        return Ok(ImportedSheet::default());
     };
-    let main_json = get_main_json_entry(&main_json, main_entry);
+    let main_json = get_main_json_entry(&main_json, &main_entry);
     let main_json = match main_json {
         Value::String(s) => serde_json::from_str(s).unwrap_or(Value::Null),
         _ => main_json.clone(),
@@ -360,11 +464,11 @@ async fn import_sheet(
         let column = column_from_value(value);
         unique_columns.insert(Arc::from(header.to_owned()), column);
     }
-    let repeated_json = get_main_json_entry(&main_json, repeated_entry);
+    let repeated_json = get_main_json_entry(&main_json, &repeated_entry);
     let Value::Array(list) = repeated_json else {
-        return Ok(vec![]);
+        return Ok(ImportedSheet::default());
     };
-    let mut result = Vec::new();
+    let mut rows = Vec::new();
     for value in list.iter() {
         let mut columns = unique_columns.clone();
         for (header, entry) in repeated.iter() {
@@ -372,22 +476,19 @@ async fn import_sheet(
             let column = column_from_value(value);
             columns.insert(Arc::from(header.to_owned()), column);
         }
-        result.push(Row {
+        rows.push(Row {
             id: Uuid::new_v4(),
             columns,
+            version: 0,
         });
     }
 
-    let mut primary_row = HashMap::new();
+    let mut fields = HashMap::new();
     for (header, entry) in primary.iter() {
         let value = get_main_json_entry(&main_json, entry);
         let column = column_from_value(value);
-        primary_row.insert(Arc::from(header.to_owned()), column);
+        fields.insert(Arc::from(header.to_owned()), column.value);
     }
-    result.push(Row {
-        id: sheetid,
-        columns: primary_row,
-    });
 
     let old_path = Path::new(&filepath);
     let download_dir = dirs::home_dir().unwrap_or_default().join("Downloads");
@@ -407,22 +508,115 @@ async fn import_sheet(
         println!("failed to move file");
     };
 
-    Ok(result)
+    Ok((fields, rows))
 }
 
-struct SheetsTypesNames(Vec<Name<Uuid>>);
-struct SheetsRows(HashMap<Arc<str>, Vec<ConfigValue>>);
-struct SheetImport(HashMap<Arc<str>, ImportConfig>);
-impl Deref for SheetImport {
-    type Target = HashMap<Arc<str>, ImportConfig>;
+#[derive(Default)]
+struct SheetTypesConfig {
+    names: Vec<Name<Uuid>>,
+    rows: HashMap<Arc<str>, Vec<ConfigValue>>,
+    priorities: HashMap<Arc<str>, Arc<[Arc<str>]>>,
+    importing: HashMap<Arc<str>, ImportConfig>,
+    rows_ids: HashMap<Arc<str>, RowIdentity<Arc<str>>>,
+    strict_formulas: HashMap<Arc<str>, bool>,
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+#[derive(Default)]
+struct SheetTypes(RwLock<SheetTypesConfig>);
+impl SheetTypes {
+    fn read(&self) -> RwLockReadGuard<'_, SheetTypesConfig> {
+        self.0.read().unwrap_or_else(|err| err.into_inner())
     }
+
+    fn set(&self, config: Config) {
+        let Config { priorities, sheets } = config;
+        let Ok(mut old) = self.0.write() else {
+            return;
+        };
+        // keep the ids of known types stable so open pages stay valid
+        let names = sheets
+            .iter()
+            .map(|x| Name {
+                id: old
+                    .names
+                    .iter()
+                    .find(|name| *name.the_name == *x.sheet_type_name)
+                    .map(|name| name.id)
+                    .unwrap_or_else(Uuid::new_v4),
+                the_name: x.sheet_type_name.to_string(),
+            })
+            .collect::<Vec<_>>();
+        let mut rows = HashMap::new();
+        let mut importing = HashMap::new();
+        let mut rows_ids = HashMap::new();
+        let mut strict_formulas = HashMap::new();
+        for SheetConfig {
+            sheet_type_name,
+            row,
+            importing: import,
+            row_identity,
+            strict_formulas: strict,
+        } in sheets.into_iter()
+        {
+            rows.insert(sheet_type_name.clone(), row);
+            importing.insert(sheet_type_name.clone(), import);
+            rows_ids.insert(sheet_type_name.clone(), row_identity);
+            strict_formulas.insert(sheet_type_name, strict);
+        }
+        *old = SheetTypesConfig {
+            names,
+            rows,
+            priorities,
+            importing,
+            rows_ids,
+            strict_formulas,
+        };
+    }
+}
+
+fn config_cache_path() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_default()
+        .join(WORKDIR)
+        .join("config")
 }
-struct SheetRowsIds(HashMap<Arc<str>, RowIdentity<Arc<str>>>);
 
-async fn file_u8_content(path: &str) -> anyhow::Result<Vec<u8>> {
+async fn read_config(path: impl AsRef<Path>) -> anyhow::Result<Config> {
+    let buf = file_u8_content(path).await?;
+    let v: ciborium::Value = ciborium::de::from_reader(Cursor::new(buf))?;
+    Ok(v.deserialized()?)
+}
+
+async fn write_config(path: impl AsRef<Path>, config: &Config) -> anyhow::Result<()> {
+    let mut buf = vec![];
+    ciborium::ser::into_writer(config, Cursor::new(&mut buf))?;
+    if let Some(parent) = path.as_ref().parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, buf).await?;
+    Ok(())
+}
+
+async fn refresh_sheet_types(app_state: &AppState, sheet_types: &SheetTypes) -> anyhow::Result<()> {
+    let config = Config::from(api::sheet_types(app_state).await?);
+    let cached = write_config(config_cache_path(), &config).await;
+    sheet_types.set(config);
+    cached
+}
+
+#[derive(Default)]
+struct SheetEventsTask(Mutex<Option<JoinHandle<()>>>);
+impl SheetEventsTask {
+    fn replace(&self, task: Option<JoinHandle<()>>) {
+        if let Ok(mut old) = self.0.lock() {
+            if let Some(old) = std::mem::replace(&mut *old, task) {
+                old.abort();
+            }
+        }
+    }
+}
+
+async fn file_u8_content(path: impl AsRef<Path>) -> anyhow::Result<Vec<u8>> {
     let mut file = tokio::fs::File::open(path).await?;
     let mut buf = vec![];
     file.read_to_end(&mut buf).await?;
@@ -433,61 +627,47 @@ async fn file_u8_content(path: &str) -> anyhow::Result<Vec<u8>> {
 async fn main() {
     dotenv().ok();
 
-    let file_path = "config";
-    let buf = file_u8_content(file_path).await.unwrap();
-
-    let v: ciborium::Value = ciborium::de::from_reader(Cursor::new(buf)).unwrap();
-    let config: Config = v.deserialized().unwrap();
-
-    let Config { priorities, sheets } = config;
-    let sheets_types_names_vec = sheets
-        .iter()
-        .map(|x| Name {
-            id: Uuid::new_v4(),
-            the_name: x.sheet_type_name.to_string(),
-        })
-        .collect::<Vec<_>>();
-    let mut sheet_map = HashMap::new();
-    let mut sheet_import = HashMap::new();
-    let mut sheet_rows_ids = HashMap::new();
-    for SheetConfig {
-        sheet_type_name,
-        row,
-        importing,
-        row_identity,
-    } in sheets.into_iter()
-    {
-        sheet_map.insert(sheet_type_name.clone(), row);
-        sheet_import.insert(sheet_type_name.clone(), importing);
-        sheet_rows_ids.insert(sheet_type_name, row_identity);
-    }
+    // the server owns the config, until login use the cached copy of it
+    let config = match read_config(config_cache_path()).await {
+        Ok(config) => config,
+        Err(_) => read_config(LEGACY_CONFIG).await.unwrap_or_default(),
+    };
+    let sheet_types = SheetTypes::default();
+    sheet_types.set(config);
 
     tauri::Builder::default()
         .manage(AppState::default())
-        .manage(SheetsTypesNames(sheets_types_names_vec))
-        .manage(SheetsRows(sheet_map))
-        .manage(priorities)
-        .manage(SheetImport(sheet_import))
-        .manage(SheetRowsIds(sheet_rows_ids))
+        .manage(sheet_types)
+        .manage(SheetEventsTask::default())
         .invoke_handler(tauri::generate_handler![
+            login,
+            logout,
+            current_user,
+            sheet_role,
             sheets_types_names,
             sheet_primary_headers,
             sheet_headers,
+            sheet_strict_formulas,
             sheet_type_name,
             save_sheet,
-            top_5_sheets,
+            search_sheets,
+            search_columns,
+            column_suggestions,
+            aggregate_columns,
             get_sheet,
             export_sheet,
-            update_sheet_name,
-            add_rows_to_sheet,
-            delete_rows_from_sheet,
             import_sheet,
             get_priorities,
             get_rows_ids,
-            delete_columns,
-            save_columns,
-            update_columns,
             get_sheet_rows,
+            apply_sheet_changeset,
+            subscribe_sheet_events,
+            unsubscribe_sheet_events,
+            get_sheet_history,
+            trashed_sheets,
+            trash_sheet,
+            restore_sheet,
+            purge_sheet,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -495,6 +675,19 @@ async fn main() {
 
 pub struct AppState {
     pub origin: String,
+    token: RwLock<Option<Uuid>>,
+}
+
+impl AppState {
+    pub fn token(&self) -> Option<Uuid> {
+        self.token.read().ok().and_then(|token| *token)
+    }
+
+    fn set_token(&self, token: Option<Uuid>) {
+        if let Ok(mut old) = self.token.write() {
+            *old = token;
+        }
+    }
 }
 
 impl Default for AppState {
@@ -504,6 +697,7 @@ impl Default for AppState {
 
         AppState {
             origin: format!("http://{host}:{port}"),
+            token: RwLock::new(None),
         }
     }
 }
@@ -513,27 +707,21 @@ pub async fn write_sheet(
     sheet: Sheet<Uuid, Arc<str>>,
 ) -> anyhow::Result<()> {
     let Sheet {
-        id,
         sheet_name,
         type_name,
         insert_date,
+        fields,
         rows,
+        ..
     } = sheet;
     let mut workbook = Workbook::new();
 
-    let primary_row = rows
-        .iter()
-        .filter(|x| x.id == id)
-        .collect::<Vec<_>>()
-        .first()
-        .map(|x| x.columns.clone())
-        .unwrap_or_default();
-
-    let second_row_index = primary_row.len() + 1;
+    let second_row_index = fields.len() + 1;
 
     let worksheet = workbook.add_worksheet();
+    let decimal_format = Format::new().set_num_format("0.00");
 
-    for (row, (header, column)) in primary_row.into_iter().enumerate() {
+    for (row, (header, value)) in fields.into_iter().enumerate() {
         let row = row as u32;
         worksheet.set_row_height(row, 35)?;
 
@@ -547,14 +735,15 @@ pub async fn write_sheet(
                 .set_border(FormatBorder::Thin),
         )?;
 
-        match column.value {
+        match value {
             ColumnValue::String(v) => {
                 worksheet.write_string(row, 1, header.to_string())?;
                 worksheet.write_string(row, 3, v.to_string())?;
             }
-            ColumnValue::Float(v) => {
+            ColumnValue::Decimal(v) => {
+                let v = v.to_f64().unwrap_or_default();
                 worksheet.write_string(row, 1, header.to_string())?;
-                worksheet.write_number(row, 3, v)?;
+                worksheet.write_number_with_format(row, 3, v, &decimal_format)?;
             }
             ColumnValue::Date(v) => {
                 let v = v.to_string();
@@ -583,8 +772,9 @@ pub async fn write_sheet(
                     ColumnValue::String(string) => {
                         worksheet.write_string(row, col, string.to_string())?;
                     }
-                    ColumnValue::Float(number) => {
-                        worksheet.write_number(row, col, *number)?;
+                    ColumnValue::Decimal(number) => {
+                        let number = number.to_f64().unwrap_or_default();
+                        worksheet.write_number_with_format(row, col, number, &decimal_format)?;
                     }
                 },
                 None => (),
@@ -632,3 +822,4 @@ pub async fn write_sheet(
 }
 
 static WORKDIR: &str = "excel_network";
+static LEGACY_CONFIG: &str = "config";
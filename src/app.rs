@@ -4,8 +4,12 @@ use serde::{Deserialize, Serialize};
 
 use tauri_sys::tauri::invoke;
 
+pub mod login;
+pub mod search;
 pub mod sheet;
 
+use login::Login;
+use search::GlobalSearch;
 use sheet::{add::AddSheet, show::ShowSheet, SheetHome};
 
 #[derive(Serialize, Deserialize)]
@@ -29,6 +33,12 @@ pub fn App() -> impl IntoView {
                                 view! {  <Home/> }
                             }
                         />
+                        <Route
+                            path="/login"
+                            view=|| {
+                                view! {  <Login/> }
+                            }
+                        />
                         <Route
                             path="/sheet/:sheet_type_id"
                             view=|| {
@@ -61,6 +71,27 @@ use std::rc::Rc;
 
 #[component]
 pub fn Home() -> impl IntoView {
+    let current_user = Resource::once(|| async move {
+        invoke::<Non, Option<String>>("current_user", &Non {})
+            .await
+            .unwrap_or_default()
+    });
+
+    Effect::new(move |_| {
+        if let Some(None) = current_user.get() {
+            window().location().set_href("/login").unwrap_or_default();
+        }
+    });
+
+    let logout = move |_| {
+        spawn_local(async move {
+            invoke::<Non, ()>("logout", &Non {})
+                .await
+                .unwrap_or_default();
+            window().location().set_href("/login").unwrap_or_default();
+        });
+    };
+
     let sheets_types_names = Resource::once(|| async move {
         invoke::<Non, Rc<[Name<Uuid>]>>("sheets_types_names", &Non {})
             .await
@@ -84,6 +115,13 @@ pub fn Home() -> impl IntoView {
     .trim();
 
     view! {
+        <Space>
+            <span>{move || current_user.get().flatten()}</span>
+            <Button on_click=logout>"تسجيل الخروج"</Button>
+        </Space>
+        <GlobalSearch
+            sheets_types_names=move || sheets_types_names.get().unwrap_or(Rc::from(vec![]))
+        />
         <div style=div_style>
         <Space vertical=true gap=SpaceGap::WH(150,40)>
             <For
@@ -0,0 +1,67 @@
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tauri_sys::tauri::invoke;
+use thaw::{Button, ButtonSize, Input, Space};
+
+use crate::app::sheet::shared::alert;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoginArgs {
+    username: String,
+    password: String,
+}
+
+#[component]
+pub fn Login() -> impl IntoView {
+    let username = RwSignal::from(String::new());
+    let password = RwSignal::from(String::new());
+
+    let login = move |_| {
+        spawn_local(async move {
+            match invoke::<_, Option<String>>(
+                "login",
+                &LoginArgs {
+                    username: username.get().trim().to_string(),
+                    password: password.get(),
+                },
+            )
+            .await
+            {
+                Ok(warning) => {
+                    if let Some(warning) = warning {
+                        alert(warning.as_str()).await;
+                    }
+                    window().location().set_href("/").unwrap_or_default()
+                }
+                Err(err) => alert(err.to_string().as_str()).await,
+            }
+        });
+    };
+
+    let div_style = r#"
+      margin: 0;
+      position: absolute;
+      top: 50%;
+      -ms-transform: translateY(-50%);
+      transform: translateY(-50%);
+      width: 100%;
+    "#
+    .trim();
+
+    view! {
+        <div style=div_style>
+            <Space vertical=true>
+                <Input value=username placeholder="اسم المستخدم".to_string()/>
+                <input
+                    class="thaw-input"
+                    type="password"
+                    placeholder="كلمة المرور"
+                    on:input=move |ev| password.set(event_target_value(&ev))
+                />
+                <Button on_click=login size=ButtonSize::Large>
+                    "تسجيل الدخول"
+                </Button>
+            </Space>
+        </div>
+    }
+}
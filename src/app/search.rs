@@ -0,0 +1,145 @@
+use leptos::*;
+use models::{ColumnSearchMode, ColumnSearchParams, Name, RowMatch};
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use tauri_sys::tauri::invoke;
+use thaw::{Button, Input, Space, Table};
+use uuid::Uuid;
+
+use crate::app::sheet::shared::alert;
+
+const SEARCH_LIMIT: i64 = 20;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SearchArgs {
+    params: ColumnSearchParams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Exact,
+    Contains,
+    Range,
+}
+
+#[component]
+pub fn GlobalSearch(
+    sheets_types_names: impl Fn() -> Rc<[Name<Uuid>]> + 'static + Copy,
+) -> impl IntoView {
+    let header = RwSignal::from(String::new());
+    let value = RwSignal::from(String::new());
+    let min = RwSignal::from(String::new());
+    let max = RwSignal::from(String::new());
+    let mode = RwSignal::from(Mode::Contains);
+    let matches = RwSignal::from(Vec::<RowMatch<Uuid, Rc<str>>>::new());
+
+    let on_mode_change = move |ev| {
+        let new_mode = match event_target_value(&ev).as_str() {
+            "exact" => Mode::Exact,
+            "range" => Mode::Range,
+            _ => Mode::Contains,
+        };
+        mode.set(new_mode);
+    };
+
+    let search = move |_| {
+        let header_name = header.get().trim().to_string();
+        if header_name.is_empty() {
+            return;
+        }
+        let mode = match mode.get() {
+            Mode::Exact => ColumnSearchMode::Exact(value.get().trim().to_string()),
+            Mode::Contains => ColumnSearchMode::Contains(value.get().trim().to_string()),
+            Mode::Range => ColumnSearchMode::Range {
+                min: min.get().trim().parse().ok(),
+                max: max.get().trim().parse().ok(),
+            },
+        };
+        let args = SearchArgs {
+            params: ColumnSearchParams {
+                header_name,
+                mode,
+                sheet_type_name: None,
+                offset: 0,
+                limit: SEARCH_LIMIT,
+            },
+        };
+        spawn_local(async move {
+            match invoke::<_, Vec<RowMatch<Uuid, Rc<str>>>>("search_columns", &args).await {
+                Ok(result) => matches.set(result),
+                Err(err) => alert(err.to_string().as_str()).await,
+            }
+        });
+    };
+
+    let open_match = move |found: &RowMatch<Uuid, Rc<str>>| {
+        let type_id = sheets_types_names()
+            .iter()
+            .find(|x| x.the_name.as_str() == found.type_name.as_ref())
+            .map(|x| x.id);
+        if let Some(type_id) = type_id {
+            window()
+                .location()
+                .set_href(&format!(
+                    "sheet/{}/show/{}?row={}",
+                    type_id, found.sheet_id, found.row.id
+                ))
+                .unwrap_or_default();
+        }
+    };
+
+    view! {
+        <Space vertical=true>
+            <Space>
+                <Input value=header placeholder="العنوان".to_string()/>
+                <select on:change=on_mode_change>
+                    <option value="contains">"يحتوي"</option>
+                    <option value="exact">"يساوي"</option>
+                    <option value="range">"نطاق رقمي"</option>
+                </select>
+                <Show
+                    when=move || mode.get() == Mode::Range
+                    fallback=move || view! {<Input value=value placeholder="القيمة".to_string()/>}
+                >
+                    <Input value=min placeholder="من".to_string()/>
+                    <Input value=max placeholder="الى".to_string()/>
+                </Show>
+                <Button on_click=search>"بحث"</Button>
+            </Space>
+            <Show when=move || !matches.get().is_empty()>
+                <Table>
+                    <thead>
+                        <tr>
+                            <th>"الشيت"</th>
+                            <th>"النوع"</th>
+                            <th>{move || header.get()}</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        <For
+                            each=move || matches.get()
+                            key=|x| x.row.id
+                            children=move |found| {
+                                let value = found
+                                    .row
+                                    .columns
+                                    .get(header.get_untracked().trim())
+                                    .map(|x| x.value.to_string())
+                                    .unwrap_or_default();
+                                let sheet_name = found.sheet_name.to_string();
+                                let type_name = found.type_name.to_string();
+                                view! {
+                                    <tr on:click=move |_| open_match(&found)>
+                                        <td>{sheet_name}</td>
+                                        <td>{type_name}</td>
+                                        <td>{value}</td>
+                                    </tr>
+                                }
+                            }
+                        />
+                    </tbody>
+                </Table>
+            </Show>
+        </Space>
+    }
+}
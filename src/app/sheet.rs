@@ -10,9 +10,10 @@ use crate::{
     atoms::{AddIcon, BackArrow, DownIcon, UpIcon},
     Id,
 };
+use shared::{alert, confirm, NameArg};
 use tauri_sys::tauri::invoke;
 
-use models::{Name, SearchSheetParams};
+use models::{Name, Role, SearchSheetParams, SheetsPage, SheetsSort, SortDirection, TrashParams};
 
 use std::rc::Rc;
 
@@ -21,6 +22,13 @@ struct SheetArgs {
     params: SearchSheetParams,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct TrashArgs {
+    params: TrashParams,
+}
+
+const PAGE_SIZE: i64 = 5;
+
 pub mod add;
 pub mod shared;
 pub mod show;
@@ -46,14 +54,28 @@ pub fn SheetHome() -> impl IntoView {
         None => Rc::from("none"),
     };
 
-    let offset = RwSignal::from(0_u64);
+    let page = RwSignal::from(0_i64);
+    let sort_by = RwSignal::from(SheetsSort::default());
+    let direction = RwSignal::from(SortDirection::default());
     let begin = RwSignal::from(None::<NaiveDate>);
     let end = RwSignal::from(None::<NaiveDate>);
     let sheet_name = RwSignal::from(String::from(""));
 
+    Effect::new(move |_| {
+        sheet_name.track();
+        begin.track();
+        end.track();
+        sort_by.track();
+        direction.track();
+        page.set(0);
+    });
+
     let search_args = move || SheetArgs {
         params: SearchSheetParams {
-            offset: offset.get() as i64,
+            offset: page.get() * PAGE_SIZE,
+            limit: PAGE_SIZE,
+            sort_by: sort_by.get(),
+            direction: direction.get(),
             sheet_type_name: sheet_type_name().to_string(),
             sheet_name: {
                 let name = sheet_name.get();
@@ -69,31 +91,169 @@ pub fn SheetHome() -> impl IntoView {
     };
 
     let bills = Resource::new(search_args, |value| async move {
-        invoke::<_, Rc<[Name<Uuid>]>>("top_5_sheets", &value)
-            .await
-            .unwrap_or(Rc::from(vec![]))
+        match invoke::<_, SheetsPage<Uuid>>("search_sheets", &value).await {
+            Ok(page) => page,
+            Err(err) => {
+                alert(err.to_string().as_str()).await;
+                SheetsPage::default()
+            }
+        }
+    });
+
+    let pages_count = move || {
+        let total = bills.get().map(|x| x.total).unwrap_or_default();
+        ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1)
+    };
+
+    let on_sort_change = move |ev| {
+        let sort = match event_target_value(&ev).as_str() {
+            "name" => SheetsSort::Name,
+            "rows" => SheetsSort::RowsCount,
+            _ => SheetsSort::InsertDate,
+        };
+        sort_by.set(sort);
+    };
+
+    let toggle_direction = move |_| {
+        direction.update(|x| {
+            *x = match x {
+                SortDirection::Ascending => SortDirection::Descending,
+                SortDirection::Descending => SortDirection::Ascending,
+            }
+        })
+    };
+
+    let sheet_role_resource = Resource::new(
+        move || sheet_type_name_resource.get(),
+        move |name| async move {
+            invoke::<NameArg, Option<Role>>("sheet_role", &NameArg { name })
+                .await
+                .unwrap_or_default()
+        },
+    );
+
+    let is_type_admin = move || {
+        sheet_role_resource
+            .get()
+            .flatten()
+            .is_some_and(|role| role == Role::Admin)
+    };
+
+    let show_trash = RwSignal::from(false);
+    let trash_offset = RwSignal::from(0_u64);
+
+    let trash_args = move || {
+        show_trash.get().then(|| TrashArgs {
+            params: TrashParams {
+                offset: trash_offset.get() as i64,
+                sheet_type_name: sheet_type_name().to_string(),
+            },
+        })
+    };
+
+    let trashed = Resource::new(trash_args, |value| async move {
+        let Some(value) = value else {
+            return Rc::from(vec![]);
+        };
+        match invoke::<_, Rc<[Name<Uuid>]>>("trashed_sheets", &value).await {
+            Ok(names) => names,
+            Err(err) => {
+                alert(err.to_string().as_str()).await;
+                Rc::from(vec![])
+            }
+        }
     });
 
+    let restore = move |id: Uuid| {
+        spawn_local(async move {
+            match invoke::<_, ()>("restore_sheet", &Id { id: Some(id) }).await {
+                Ok(_) => {
+                    trashed.refetch();
+                    bills.refetch();
+                }
+                Err(err) => alert(err.to_string().as_str()).await,
+            }
+        });
+    };
+
+    let purge = move |id: Uuid| {
+        spawn_local(async move {
+            if !confirm("سيتم حذف الشيت نهائيا").await {
+                return;
+            }
+            match invoke::<_, ()>("purge_sheet", &Id { id: Some(id) }).await {
+                Ok(_) => trashed.refetch(),
+                Err(err) => alert(err.to_string().as_str()).await,
+            }
+        });
+    };
+
     view! {
         <Space vertical=true>
             <Space>
                 <BackArrow n=2/>
                 <AddIcon/>
+                <Show when=is_type_admin>
+                    <Button on_click=move |_| show_trash.update(|x| *x = !*x)>
+                        {move || if show_trash.get() { "اخفاء سلة المحذوفات" } else { "سلة المحذوفات" }}
+                    </Button>
+                </Show>
             </Space>
+            <Show when=move || show_trash.get()>
+                <Space vertical=true>
+                    <Show
+                        when=move || trash_offset.get() != 0
+                    >
+                        <UpIcon scroll=move |_| trash_offset.update(|x| *x -= 5)/>
+                    </Show>
+                    <For
+                        each=move || trashed.get().unwrap_or(Rc::from(vec![])).to_vec()
+                        key=|s| s.id
+                        children=move |s| {
+                            let id = s.id;
+                            view! {
+                                <Space>
+                                    <h3>{s.the_name}</h3>
+                                    <Button on_click=move |_| restore(id)>"استعادة"</Button>
+                                    <Button on_click=move |_| purge(id)>"حذف نهائي"</Button>
+                                </Space>
+                            }
+                        }
+                    />
+                    <Show
+                        when=move || { trashed.get().unwrap_or(Rc::from(vec![])).len() >= 5 }
+                    >
+                        <DownIcon scroll=move |_| trash_offset.update(|x| *x += 5)/>
+                    </Show>
+                </Space>
+            </Show>
             <Input value=sheet_name placeholder="اسم الشيت".to_string()/>
             <h3>"تاريخ البداية"</h3>
             <DatePicker value=begin/>
             <h3>"تاريخ النهاية"</h3>
             <DatePicker value=end/>
+            <Space>
+                <select on:change=on_sort_change>
+                    <option value="date">"تاريخ الانشاء"</option>
+                    <option value="name">"الاسم"</option>
+                    <option value="rows">"عدد الصفوف"</option>
+                </select>
+                <Button on_click=toggle_direction>
+                    {move || match direction.get() {
+                        SortDirection::Ascending => "تصاعدي",
+                        SortDirection::Descending => "تنازلي",
+                    }}
+                </Button>
+            </Space>
             <br/><br/><br/>
             <Show
-                when=move || offset.get() != 0
+                when=move || page.get() != 0
             >
-                <UpIcon scroll=move |_| offset.update(|x| *x -= 5)/>
+                <UpIcon scroll=move |_| page.update(|x| *x -= 1)/>
             </Show>
-            <br/>
+            <h3>{move || format!("صفحة {} من {}", page.get() + 1, pages_count())}</h3>
             <For
-                each=move || bills.get().unwrap_or(Rc::from(vec![])).to_vec()
+                each=move || bills.get().unwrap_or_default().names
                 key=|s| s.id
                 children=move |s| {
                     view! {
@@ -112,9 +272,9 @@ pub fn SheetHome() -> impl IntoView {
                 }
             />
             <Show
-                when=move || { bills.get().unwrap_or(Rc::from(vec![])).len() >= 5 }
+                when=move || page.get() + 1 < pages_count()
             >
-                <DownIcon scroll=move |_| offset.update(|x| *x += 5)/>
+                <DownIcon scroll=move |_| page.update(|x| *x += 1)/>
             </Show>
             <Outlet/>
         </Space>
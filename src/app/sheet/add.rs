@@ -9,7 +9,8 @@ use std::str::FromStr;
 use thaw::{Button, Input, Space, Table};
 
 use super::shared::{
-    alert, import_sheet_rows, message, open_file, InputRow, NameArg, SheetHead, ShowNewRows,
+    alert, import_sheet, message, open_file, ordered_calc_columns, InputRow, NameArg, SheetFields,
+    SheetHead, ShowNewRows,
 };
 
 use std::collections::HashMap;
@@ -26,6 +27,7 @@ struct SaveSheetArgs {
     sheetid: Uuid,
     sheetname: Rc<str>,
     typename: Rc<str>,
+    fields: SheetFields,
     rows: Vec<Row<Uuid, Rc<str>>>,
 }
 
@@ -35,7 +37,7 @@ use std::rc::Rc;
 pub fn AddSheet() -> impl IntoView {
     let sheet_name = RwSignal::from(String::new());
     let rows = RwSignal::from(Vec::<Row<Uuid, Rc<str>>>::new());
-    let modified_primary_columns = RwSignal::from(HashMap::<Rc<str>, Column<Rc<str>>>::new());
+    let modified_primary_columns = RwSignal::from(SheetFields::new());
     let params = use_params_map();
     let sheet_type_id = move || {
         params.with(|params| match params.get("sheet_type_id") {
@@ -63,6 +65,15 @@ pub fn AddSheet() -> impl IntoView {
         },
     );
 
+    let strict_formulas_resource = Resource::new(
+        move || sheet_type_name_resource.get(),
+        move |name| async move {
+            invoke::<NameArg, bool>("sheet_strict_formulas", &NameArg { name })
+                .await
+                .unwrap_or_default()
+        },
+    );
+
     let sheet_headers_resource = Resource::new(
         move || sheet_type_name_resource.get(),
         move |name| async move {
@@ -89,7 +100,7 @@ pub fn AddSheet() -> impl IntoView {
             .get()
             .into_iter()
             .filter(|x| match x {
-                ColumnConfig::String(v) | ColumnConfig::Float(v) | ColumnConfig::Date(v) => {
+                ColumnConfig::String(v) | ColumnConfig::Decimal(v) | ColumnConfig::Date(v) => {
                     v.header == header
                 }
             })
@@ -108,6 +119,7 @@ pub fn AddSheet() -> impl IntoView {
             })
             .collect::<Vec<_>>()
     });
+    let calc_order = ordered_calc_columns(calc_columns);
 
     let basic_headers = move || {
         basic_columns
@@ -135,35 +147,23 @@ pub fn AddSheet() -> impl IntoView {
     let save_sheet = move |_| {
         let id = sheet_id_sig.get();
         spawn_local(async move {
-            let mut the_rows = rows.get();
-            let index = the_rows.iter().position(|x| x.id == id);
-            if let Some(index) = index {
-                if let Some(primary_row) = the_rows.get_mut(index) {
-                    *primary_row = Row {
-                        id: primary_row.id,
-                        columns: primary_row
-                            .clone()
-                            .columns
-                            .into_iter()
-                            .chain(modified_primary_columns.get())
-                            .collect(),
-                    };
-                };
-            }
             match invoke::<_, ()>(
                 "save_sheet",
                 &SaveSheetArgs {
                     sheetid: id,
                     sheetname: Rc::from(sheet_name.get()),
                     typename: sheet_type_name_resource.get().unwrap_or(Rc::from("")),
-                    rows: the_rows
+                    fields: modified_primary_columns.get(),
+                    rows: rows
+                        .get()
                         .into_iter()
-                        .map(|Row { id, columns }| Row {
-                            id,
-                            columns: columns
+                        .map(|row| Row {
+                            columns: row
+                                .columns
                                 .into_iter()
                                 .filter(|(_, Column { is_basic, value: _ })| is_basic.to_owned())
                                 .collect(),
+                            ..row
                         })
                         .collect::<Vec<_>>(),
                 },
@@ -173,6 +173,7 @@ pub fn AddSheet() -> impl IntoView {
                 Ok(_) => {
                     message("👍").await;
                     rows.set(Vec::new());
+                    modified_primary_columns.set(SheetFields::new());
                     sheet_id_sig.set(Uuid::new_v4());
                     sheet_name.set("".to_string());
                 }
@@ -207,20 +208,8 @@ pub fn AddSheet() -> impl IntoView {
             let Some(filepath) = open_file().await else {
                 return;
             };
-            let the_rows = import_sheet_rows(sheet_id_sig.get(), sheettype, filepath).await;
-            let primary_row = the_rows
-                .iter()
-                .filter(|x| x.id == sheet_id_sig.get())
-                .collect::<Vec<_>>();
-            let primary_row = primary_row
-                .first()
-                .map(|x| x.columns.clone())
-                .unwrap_or_default();
-            for (header, column) in primary_row {
-                modified_primary_columns.update(|map| {
-                    map.insert(header, column);
-                })
-            }
+            let (fields, the_rows) = import_sheet(sheettype, filepath).await;
+            modified_primary_columns.update(|map| map.extend(fields));
             rows.update_untracked(|xs| xs.extend(the_rows));
             rows.update(|xs| xs.sort_rows(sheet_priorities_resource.get().unwrap_or(Rc::from([]))));
         });
@@ -248,7 +237,6 @@ pub fn AddSheet() -> impl IntoView {
                         basic_headers=basic_headers
                         calc_headers=calc_headers
                         rows=rows
-                        sheet_id=move || sheet_id_sig.get()
                         priorities=move || sheet_priorities_resource.get().unwrap_or(Rc::from([]))
                         get_column_type=get_header_type
                     />
@@ -257,7 +245,9 @@ pub fn AddSheet() -> impl IntoView {
                         calc_headers=calc_headers
                         append=append
                         basic_columns=basic_columns
-                        calc_columns=calc_columns
+                        calc_columns=calc_order
+                        sheet_type_name=move || sheet_type_name_resource.get().unwrap_or(Rc::from(""))
+                        strict_formulas=move || strict_formulas_resource.get().unwrap_or_default()
                     />
                 </tbody>
             </Table>
@@ -278,7 +268,7 @@ pub fn AddSheet() -> impl IntoView {
 fn PrimaryRow(
     primary_headers: impl Fn() -> Rc<[Rc<str>]> + 'static + Copy,
     non_primary_headers: impl Fn() -> Rc<[Rc<str>]> + 'static + Copy,
-    new_columns: RwSignal<HashMap<Rc<str>, Column<Rc<str>>>>,
+    new_columns: RwSignal<SheetFields>,
 ) -> impl IntoView {
     let headers = move || merge_primary_row_headers(primary_headers(), non_primary_headers());
 
@@ -3,6 +3,7 @@ use models::Column;
 use models::ColumnValue;
 use models::Row;
 
+use bigdecimal::{BigDecimal, Zero};
 use chrono::Local;
 
 use chrono::NaiveDate;
@@ -22,9 +23,10 @@ use thaw::Space;
 use uuid::Uuid;
 
 use models::RowsSort;
+use models::SuggestionParams;
 
 use client_models::{
-    ColumnConfig, ColumnProps, Operation, OperationConfig, OperationKind, ValueType,
+    calculate_columns, calculation_order, CalcError, ColumnConfig, ColumnProps, OperationConfig,
 };
 
 use std::rc::Rc;
@@ -34,30 +36,30 @@ pub struct NameArg {
     pub name: Option<Rc<str>>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct SuggestionArgs {
+    params: SuggestionParams,
+}
+
+const SUGGESTIONS_LIMIT: i64 = 10;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ImportSheetArgs {
     sheettype: Rc<str>,
-    sheetid: Uuid,
     filepath: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Name {
-    pub id: Uuid,
-    pub the_name: Rc<str>,
-}
+pub type SheetFields = HashMap<Rc<str>, ColumnValue<Rc<str>>>;
 
-pub async fn import_sheet_rows(
-    sheetid: Uuid,
+pub async fn import_sheet(
     sheettype: Rc<str>,
     filepath: String,
-) -> Vec<Row<Uuid, Rc<str>>> {
-    invoke::<ImportSheetArgs, Vec<Row<Uuid, Rc<str>>>>(
+) -> (SheetFields, Vec<Row<Uuid, Rc<str>>>) {
+    invoke::<ImportSheetArgs, (SheetFields, Vec<Row<Uuid, Rc<str>>>)>(
         "import_sheet",
         &ImportSheetArgs {
             sheettype,
             filepath,
-            sheetid,
         },
     )
     .await
@@ -225,8 +227,8 @@ fn ColumnEdit(
         let on_input = move |ev| {
             let value = event_target_value(&ev).trim().to_string();
             let value = match column_value.get() {
-                Some(ColumnValue::Float(_)) => {
-                    ColumnValue::Float(value.parse().unwrap_or_default())
+                Some(ColumnValue::Decimal(_)) => {
+                    ColumnValue::Decimal(value.parse().unwrap_or_default())
                 }
                 Some(ColumnValue::Date(_)) => ColumnValue::Date(value.parse().unwrap_or_default()),
                 _ => ColumnValue::String(Rc::from(value)),
@@ -235,7 +237,7 @@ fn ColumnEdit(
         };
 
         let input_type = match get_column_type(header.to_string()) {
-            Some(ColumnConfig::Float(_)) => "number",
+            Some(ColumnConfig::Decimal(_)) => "number",
             Some(ColumnConfig::Date(_)) => "date",
             _ => "text",
         };
@@ -291,18 +293,11 @@ pub fn ShowNewRows(
     calc_headers: impl Fn() -> Vec<Rc<str>> + 'static + Copy,
     delete_row: impl Fn(Uuid) + 'static + Copy,
     priorities: impl Fn() -> Rc<[Rc<str>]> + 'static + Copy,
-    sheet_id: impl Fn() -> Uuid + 'static + Copy,
     get_column_type: impl Fn(String) -> Option<ColumnConfig> + 'static + Copy,
     rows: RwSignal<Vec<Row<Uuid, Rc<str>>>>,
 ) -> impl IntoView {
     type EditColumn = (Rc<str>, Uuid, Rc<HashMap<Rc<str>, Column<Rc<str>>>>);
     let edit_column = RwSignal::from(None::<EditColumn>);
-    let new_rows = Memo::new(move |_| {
-        rows.get()
-            .into_iter()
-            .filter(|x| x.id != sheet_id())
-            .collect::<Vec<_>>()
-    });
 
     #[component]
     fn ShowColumnEdit(
@@ -380,7 +375,7 @@ pub fn ShowNewRows(
             />
         }
     }
-    let children = move |Row { columns, id }| {
+    let children = move |Row { columns, id, .. }| {
         let columns = Rc::new(columns);
         view! {
             <tr>
@@ -411,7 +406,7 @@ pub fn ShowNewRows(
                 get_column_type=get_column_type
             />
             <For
-                each=move || new_rows.get()
+                each=move || rows.get()
                 key=|row| row.id
                 children=children
             />
@@ -424,10 +419,28 @@ type GetterSetter<T> = RwSignal<(T, bool)>;
 #[derive(Debug, Clone, PartialEq)]
 enum ColumnSignal {
     String(GetterSetter<String>),
-    Float(GetterSetter<f64>),
+    Decimal(GetterSetter<BigDecimal>),
     Date(GetterSetter<NaiveDate>),
 }
 
+const CALC_ERROR_STYLE: &str = "color: #cf1322; background-color: #fff1f0;";
+
+#[component]
+pub fn CalcCell(
+    value: impl Fn() -> Option<String> + 'static,
+    error: impl Fn() -> Option<CalcError> + 'static,
+) -> impl IntoView {
+    let error = Memo::new(move |_| error());
+    view! {
+        <td
+            style=move || error.get().map(|_| CALC_ERROR_STYLE).unwrap_or_default()
+            title=move || error.get().map(|err| err.diagnostic()).unwrap_or_default()
+        >
+            {move || error.get().map(|err| err.to_string()).or_else(&value)}
+        </td>
+    }
+}
+
 #[component]
 pub fn InputRow(
     basic_headers: impl Fn() -> Vec<Rc<str>> + 'static + Clone,
@@ -435,6 +448,8 @@ pub fn InputRow(
     append: impl Fn(Row<Uuid, Rc<str>>) + 'static + Copy,
     basic_columns: Memo<Vec<ColumnConfig>>,
     calc_columns: Memo<Vec<OperationConfig>>,
+    sheet_type_name: impl Fn() -> Rc<str> + 'static + Copy,
+    strict_formulas: impl Fn() -> bool + 'static + Copy,
 ) -> impl IntoView {
     let basic_signals_map = Memo::new(move |_| {
         let mut map = HashMap::<Rc<str>, _>::new();
@@ -461,13 +476,13 @@ pub fn InputRow(
                         ))),
                     );
                 }
-                ColumnConfig::Float(ColumnProps {
+                ColumnConfig::Decimal(ColumnProps {
                     header,
                     is_completable,
                 }) => {
                     map.insert(
                         Rc::from(header),
-                        ColumnSignal::Float(RwSignal::from((0.0, is_completable))),
+                        ColumnSignal::Decimal(RwSignal::from((BigDecimal::zero(), is_completable))),
                     );
                 }
             }
@@ -476,26 +491,29 @@ pub fn InputRow(
     });
 
     let calc_signals_map = Memo::new(move |_| {
-        let mut map = HashMap::<Rc<str>, _>::new();
-        for OperationConfig { header, value } in calc_columns.get().into_iter() {
-            let mut basic_map = HashMap::new();
-            for (header, column_signal) in basic_signals_map.get() {
-                let column_value = match column_signal {
-                    ColumnSignal::String(reader) => ColumnValue::String(Rc::from(reader.get().0)),
-                    ColumnSignal::Float(reader) => ColumnValue::Float(reader.get().0),
-                    ColumnSignal::Date(reader) => ColumnValue::Date(reader.get().0),
-                };
-                basic_map.insert(header, column_value);
-            }
-            map.insert(
-                Rc::from(header),
-                resolve_operation(&value, &basic_map).unwrap_or_default(),
-            );
+        let mut columns_map = HashMap::new();
+        for (header, column_signal) in basic_signals_map.get() {
+            let column_value = match column_signal {
+                ColumnSignal::String(reader) => ColumnValue::String(Rc::from(reader.get().0)),
+                ColumnSignal::Decimal(reader) => ColumnValue::Decimal(reader.get().0),
+                ColumnSignal::Date(reader) => ColumnValue::Date(reader.get().0),
+            };
+            columns_map.insert(header, column_value);
         }
-        map
+        calculate_columns(columns_map, &calc_columns.get(), strict_formulas())
     });
 
     let on_click = move |_| {
+        let (values, errors) = calc_signals_map.get();
+        if strict_formulas() && !errors.is_empty() {
+            let message = errors
+                .iter()
+                .map(|(header, err)| format!("{header}: {}", err.diagnostic()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            spawn_local(async move { alert(&message).await });
+            return;
+        }
         let mut result = HashMap::<Rc<str>, Column<Rc<str>>>::new();
         for (key, value) in basic_signals_map.get() {
             result.insert(
@@ -505,9 +523,9 @@ pub fn InputRow(
                         is_basic: true,
                         value: ColumnValue::String(Rc::from(reader.get().0)),
                     },
-                    ColumnSignal::Float(reader) => Column {
+                    ColumnSignal::Decimal(reader) => Column {
                         is_basic: true,
-                        value: ColumnValue::Float(reader.get().0),
+                        value: ColumnValue::Decimal(reader.get().0),
                     },
                     ColumnSignal::Date(reader) => Column {
                         is_basic: true,
@@ -516,18 +534,19 @@ pub fn InputRow(
                 },
             );
         }
-        for (key, value) in calc_signals_map.get() {
+        for (key, value) in values {
             result.insert(
                 key,
                 Column {
                     is_basic: false,
-                    value: ColumnValue::Float(value),
+                    value,
                 },
             );
         }
         append(Row {
             id: Uuid::new_v4(),
             columns: result,
+            version: 0,
         });
     };
 
@@ -539,7 +558,11 @@ pub fn InputRow(
                 key=|x| x.clone()
                 let:header
             >
-                <MyInput header=header basic_signals_map=basic_signals_map/>
+                <MyInput
+                    header=header
+                    basic_signals_map=basic_signals_map
+                    sheet_type_name=sheet_type_name
+                />
             </For>
             <td>" "</td>
             <For
@@ -547,13 +570,15 @@ pub fn InputRow(
                 key=|x| x.clone()
                 let:header
             >
-                <td>
-                    {move || calc_signals_map
-                        .get()
-                        .get(&header)
-                        .map(|x| format!("{:.2}",* x))
+                <CalcCell
+                    value={
+                        let header = header.clone();
+                        move || calc_signals_map.with(|(values, _)| {
+                            values.get(&header).map(|x| x.to_string())
+                        })
                     }
-                </td>
+                    error=move || calc_signals_map.with(|(_, errors)| errors.get(&header).cloned())
+                />
             </For>
         </tr>
         <tr>
@@ -575,24 +600,56 @@ pub fn InputRow(
 fn MyInput(
     header: Rc<str>,
     basic_signals_map: Memo<HashMap<Rc<str>, ColumnSignal>>,
+    sheet_type_name: impl Fn() -> Rc<str> + 'static + Copy,
 ) -> impl IntoView {
     let cmp_arg = basic_signals_map.get();
-    let (i_type, value) = match cmp_arg.get(&header) {
-        Some(ColumnSignal::String(read)) => ("text", read.get().0.to_string()),
-        Some(ColumnSignal::Float(read)) => ("number", read.get().0.to_string()),
-        Some(ColumnSignal::Date(read)) => ("date", read.get().0.to_string()),
-        None => ("", "".to_string()),
+    let (i_type, value, is_completable) = match cmp_arg.get(&header) {
+        Some(ColumnSignal::String(read)) => ("text", read.get().0.to_string(), read.get().1),
+        Some(ColumnSignal::Decimal(read)) => ("number", read.get().0.to_string(), false),
+        Some(ColumnSignal::Date(read)) => ("date", read.get().0.to_string(), false),
+        None => ("", "".to_string(), false),
+    };
+    let suggestions = RwSignal::from(Vec::<String>::new());
+    let list_id = format!(
+        "suggestions-{}",
+        header.split_whitespace().collect::<Vec<_>>().join("-")
+    );
+    let header_name = header.to_string();
+    let on_input = move |ev| {
+        if !is_completable {
+            return;
+        }
+        let prefix = event_target_value(&ev).trim().to_string();
+        if prefix.is_empty() {
+            suggestions.set(Vec::new());
+            return;
+        }
+        let args = SuggestionArgs {
+            params: SuggestionParams {
+                sheet_type_name: sheet_type_name().to_string(),
+                header_name: header_name.clone(),
+                prefix,
+                limit: SUGGESTIONS_LIMIT,
+            },
+        };
+        spawn_local(async move {
+            if let Ok(result) = invoke::<_, Vec<String>>("column_suggestions", &args).await {
+                suggestions.set(result);
+            }
+        });
     };
     view! {
         <td>
             <input
                 type=i_type
+                list=is_completable.then(|| list_id.clone())
                 value=move || value.clone()
+                on:input=on_input
                 on:change=move |ev| match cmp_arg.get(&header) {
                     Some(ColumnSignal::String(write)) => {
                         write.update(|x| x.0 = event_target_value(&ev).trim().to_string())
                     }
-                    Some(ColumnSignal::Float(write)) => {
+                    Some(ColumnSignal::Decimal(write)) => {
                         write.update(|x| x.0 = event_target_value(&ev).trim().parse().unwrap_or_default())
                     }
                     Some(ColumnSignal::Date(write)) => {
@@ -601,6 +658,17 @@ fn MyInput(
                     None => {}
                 }
             />
+            {is_completable.then(|| view! {
+                <datalist id=list_id>
+                    <For
+                        each=move || suggestions.get()
+                        key=|x| x.clone()
+                        let:suggestion
+                    >
+                        <option value=suggestion/>
+                    </For>
+                </datalist>
+            })}
         </td>
     }
 }
@@ -613,43 +681,18 @@ pub enum EditState {
     None,
 }
 
-pub fn resolve_operation(
-    operation: &Operation,
-    columns_map: &HashMap<Rc<str>, ColumnValue<Rc<str>>>,
-) -> Option<f64> {
-    fn get_op(op: &OperationKind) -> impl Fn(f64, f64) -> f64 {
-        match op {
-            OperationKind::Multiply => |v1, v2| v1 * v2,
-            OperationKind::Add => |v1, v2| v1 + v2,
-            OperationKind::Divide => |v1, v2| v1 / v2,
-            OperationKind::Minus => |v1, v2| v1 - v2,
-        }
-    }
-
-    fn resolve_hs(
-        hs: &ValueType,
-        columns_map: &HashMap<Rc<str>, ColumnValue<Rc<str>>>,
-    ) -> Option<f64> {
-        match hs {
-            ValueType::Const(hs) => Some(*hs),
-            ValueType::Variable(hs) => match columns_map.get(&Rc::from(hs.as_str())) {
-                Some(ColumnValue::Float(hs)) => Some(*hs),
-                _ => None,
-            },
-            ValueType::Operation(lhs) => resolve_operation(lhs, columns_map),
+// the columns in calculation order, a cycle is reported and the config order
+// is kept so the rest of the columns still get computed
+pub fn ordered_calc_columns(
+    calc_columns: Memo<Vec<OperationConfig>>,
+) -> Memo<Vec<OperationConfig>> {
+    let order = Memo::new(move |_| calculation_order(calc_columns.get()));
+    Effect::new(move |_| {
+        if let Err(cycle) = order.get() {
+            spawn_local(async move { alert(cycle.to_string().as_str()).await });
         }
-    }
-
-    let Operation { op, lhs, rhs } = operation;
-    let op = get_op(op);
-    let lhs = resolve_hs(lhs, columns_map);
-    let rhs = resolve_hs(rhs, columns_map);
-    match (lhs, rhs) {
-        (Some(lhs), Some(rhs)) => Some(op(lhs, rhs)),
-        (Some(lhs), None) => Some(lhs),
-        (None, Some(rhs)) => Some(rhs),
-        (None, None) => None,
-    }
+    });
+    Memo::new(move |_| order.get().unwrap_or_else(|_| calc_columns.get()))
 }
 
 pub fn merge_primary_row_headers(
@@ -694,8 +737,8 @@ pub fn PrimaryRowContent(
     is_in_edit_mode: impl Fn() -> bool + 'static + Copy,
     is_deleted: impl Fn(Rc<str>) -> bool + 'static + Copy,
     delete_fun: impl Fn(Rc<str>) + 'static + Copy,
-    columns: Memo<HashMap<Rc<str>, Column<Rc<str>>>>,
-    new_columns: RwSignal<HashMap<Rc<str>, Column<Rc<str>>>>,
+    columns: Memo<SheetFields>,
+    new_columns: RwSignal<SheetFields>,
 ) -> impl IntoView {
     let all_columns = Memo::new(move |_| {
         columns
@@ -708,8 +751,8 @@ pub fn PrimaryRowContent(
     #[component]
     fn RightPrimaryColumns(
         primary: Rc<str>,
-        columns: Memo<HashMap<Rc<str>, Column<Rc<str>>>>,
-        new_columns: RwSignal<HashMap<Rc<str>, Column<Rc<str>>>>,
+        columns: Memo<SheetFields>,
+        new_columns: RwSignal<SheetFields>,
         is_in_edit_mode: impl Fn() -> bool + 'static + Copy,
         is_deleted: impl Fn(Rc<str>) -> bool + 'static + Copy,
         delete_fun: impl Fn(Rc<str>) + 'static + Copy,
@@ -718,7 +761,7 @@ pub fn PrimaryRowContent(
             columns
                 .get()
                 .get(&primary)
-                .map(|x| x.value.to_string())
+                .map(|x| x.to_string())
                 .unwrap_or_default()
         };
 
@@ -726,7 +769,7 @@ pub fn PrimaryRowContent(
             new_columns
                 .get()
                 .get(&primary)
-                .map(|x| " => ".to_string() + &x.value.to_string())
+                .map(|x| " => ".to_string() + &x.to_string())
                 .unwrap_or_default()
         };
 
@@ -795,13 +838,10 @@ pub fn PrimaryRowContent(
         non_primary: Rc<str>,
         is_in_edit_mode: impl Fn() -> bool + 'static + Copy,
         delete_fun: impl Fn(Rc<str>) + 'static + Copy,
-        all_columns: Memo<HashMap<Rc<str>, Column<Rc<str>>>>,
+        all_columns: Memo<SheetFields>,
     ) -> impl IntoView {
         #[component]
-        fn TitleValue(
-            non_primary: Rc<str>,
-            all_columns: Memo<HashMap<Rc<str>, Column<Rc<str>>>>,
-        ) -> impl IntoView {
+        fn TitleValue(non_primary: Rc<str>, all_columns: Memo<SheetFields>) -> impl IntoView {
             let title = {
                 let a = non_primary.clone();
                 move || a.to_string()
@@ -812,7 +852,7 @@ pub fn PrimaryRowContent(
                     all_columns
                         .get()
                         .get(&np)
-                        .map(|x| x.value.to_string())
+                        .map(|x| x.to_string())
                         .unwrap_or_default()
                 }
             };
@@ -894,10 +934,10 @@ pub fn PrimaryRowContent(
 }
 
 #[component]
-pub fn PrimaryRowEditor(new_columns: RwSignal<HashMap<Rc<str>, Column<Rc<str>>>>) -> impl IntoView {
+pub fn PrimaryRowEditor(new_columns: RwSignal<SheetFields>) -> impl IntoView {
     let add_what = RwSignal::from(None::<&str>);
     let header = RwSignal::from("".to_string());
-    let column_value = RwSignal::from(ColumnValue::Float(0.0));
+    let column_value = RwSignal::from(ColumnValue::Decimal(BigDecimal::zero()));
 
     let on_value_input = move |ev| {
         column_value.update(|x| match x {
@@ -907,21 +947,16 @@ pub fn PrimaryRowEditor(new_columns: RwSignal<HashMap<Rc<str>, Column<Rc<str>>>>
             ColumnValue::Date(_) => {
                 *x = ColumnValue::Date(event_target_value(&ev).trim().parse().unwrap_or_default())
             }
-            ColumnValue::Float(_) => {
-                *x = ColumnValue::Float(event_target_value(&ev).trim().parse().unwrap_or_default())
+            ColumnValue::Decimal(_) => {
+                *x =
+                    ColumnValue::Decimal(event_target_value(&ev).trim().parse().unwrap_or_default())
             }
         })
     };
 
     let append_column = move |_| {
         new_columns.update(|map| {
-            map.insert(
-                Rc::from(header.get()),
-                Column {
-                    is_basic: true,
-                    value: column_value.get(),
-                },
-            );
+            map.insert(Rc::from(header.get()), column_value.get());
         });
         add_what.set(None);
     };
@@ -940,7 +975,7 @@ pub fn PrimaryRowEditor(new_columns: RwSignal<HashMap<Rc<str>, Column<Rc<str>>>>
                 <Button
                     on_click=move |_| {
                         add_what.set(Some("number"));
-                        column_value.set(ColumnValue::Float(0.0));
+                        column_value.set(ColumnValue::Decimal(BigDecimal::zero()));
                     }
                 >"+ رقم"</Button>
                 <Button
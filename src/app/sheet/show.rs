@@ -1,24 +1,35 @@
 use crate::app::sheet::shared::{merge_primary_row_headers, PrimaryRowContent, PrimaryRowEditor};
-use crate::atoms::{BackArrow, CollapseIcon, EditIcon, ExcelExport, RenderMode, SaveIcon};
-use crate::Id;
+use crate::atoms::{
+    BackArrow, CollapseIcon, EditIcon, ExcelExport, HistoryIcon, RenderMode, SaveIcon, TrashIcon,
+};
+use crate::{Id, Non};
+use bigdecimal::{BigDecimal, One, Zero};
 use chrono::{Local, NaiveDate};
-use client_models::{ColumnConfig, ConfigValue, HeaderGetter, IdentityDiffsOps, RowIdentity};
+use client_models::{
+    calculate_columns, CalcErrors, ColumnConfig, ConfigValue, HeaderGetter, IdentityDiffsOps,
+    RowIdentity,
+};
+use futures::StreamExt;
 use leptos::spawn_local;
 use leptos::*;
 use leptos_router::*;
-use models::{Column, ColumnValue, Row, RowsSort, Sheet};
+use models::{
+    AuditAction, AuditEntry, ChangesetResult, Column, ColumnValue, Conflict, ConflictTarget,
+    HistoryPage, Role, Row, RowsPage, RowsSort, Sheet, SheetChange, SheetChangeset, SheetEvent,
+    SheetEventKind, SortDirection,
+};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::str::FromStr;
-use std::{collections::HashMap, rc::Rc};
-use tauri_sys::tauri::invoke;
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+use tauri_sys::{event::listen, tauri::invoke};
 use thaw::{Button, Modal, Space, Table};
 use uuid::Uuid;
 
 use super::shared::{
-    alert, import_sheet_rows, message, open_file, resolve_operation, EditState, InputRow, Name,
-    NameArg, SheetHead, ShowNewRows,
+    alert, confirm, import_sheet, message, open_file, ordered_calc_columns, CalcCell, EditState,
+    InputRow, NameArg, SheetFields, SheetHead, ShowNewRows,
 };
 
 #[derive(Debug, Clone)]
@@ -29,32 +40,10 @@ struct ColumnIdentity {
 }
 
 const FETCH_LIMIT: i64 = 7;
+const HISTORY_LIMIT: i64 = 50;
 
 use itertools::Itertools;
 
-fn spawn_my_local_process<T: Serialize + 'static>(
-    it_worth_it: bool,
-    operation: &'static str,
-    args: T,
-    save_edits_successes: RwSignal<i32>,
-    save_edits_dones: RwSignal<i32>,
-) {
-    spawn_local(async move {
-        if it_worth_it {
-            match invoke::<_, ()>(operation, &args).await {
-                Ok(_) => save_edits_successes.update(|x| *x += 1),
-                Err(err) => {
-                    alert(err.to_string().as_str()).await;
-                    save_edits_successes.set(0);
-                }
-            }
-        } else {
-            save_edits_successes.update(|x| *x += 1);
-        }
-        save_edits_dones.update(|x| *x += 1);
-    });
-}
-
 async fn collapse_rows(
     rows: Vec<Row<Uuid, Rc<str>>>,
     row_identity: RowIdentity<Rc<str>>,
@@ -92,52 +81,79 @@ async fn collapse_rows(
 
         let map_rows_to_columns = || rows.iter().filter_map(row_main_column_value);
 
+        // text and date columns have nothing to add up so they keep their first value
+        let is_decimal = !matches!(
+            map_rows_to_columns().next(),
+            Some(ColumnValue::String(_) | ColumnValue::Date(_))
+        );
+
         match value {
             IdentityDiffsOps::Nth(n) => rows.iter().filter_map(row_main_column_value).nth(*n),
+            IdentityDiffsOps::Sum | IdentityDiffsOps::Prod if !is_decimal => {
+                map_rows_to_columns().next()
+            }
             IdentityDiffsOps::Sum => {
-                let r = map_rows_to_columns()
-                    .map(|x| {
-                        if let ColumnValue::Float(n) = x {
-                            n
-                        } else {
-                            0.0
-                        }
-                    })
-                    .sum::<f64>();
-                Some(ColumnValue::Float(r))
+                let r = map_rows_to_columns().fold(BigDecimal::zero(), |acc, x| {
+                    if let ColumnValue::Decimal(n) = x {
+                        acc + n
+                    } else {
+                        acc
+                    }
+                });
+                Some(ColumnValue::Decimal(r))
             }
             IdentityDiffsOps::Prod => {
-                let r = map_rows_to_columns()
-                    .map(|x| {
-                        if let ColumnValue::Float(n) = x {
-                            n
-                        } else {
-                            1.0
-                        }
-                    })
-                    .product::<f64>();
-                Some(ColumnValue::Float(r))
+                let r = map_rows_to_columns().fold(BigDecimal::one(), |acc, x| {
+                    if let ColumnValue::Decimal(n) = x {
+                        acc * n
+                    } else {
+                        acc
+                    }
+                });
+                Some(ColumnValue::Decimal(r))
             }
+            IdentityDiffsOps::Max if !is_decimal => map_rows_to_columns()
+                .filter_map(|x| {
+                    if let ColumnValue::Date(d) = x {
+                        Some(d)
+                    } else {
+                        None
+                    }
+                })
+                .max()
+                .map(ColumnValue::Date)
+                .or_else(|| map_rows_to_columns().next()),
+            IdentityDiffsOps::Min if !is_decimal => map_rows_to_columns()
+                .filter_map(|x| {
+                    if let ColumnValue::Date(d) = x {
+                        Some(d)
+                    } else {
+                        None
+                    }
+                })
+                .min()
+                .map(ColumnValue::Date)
+                .or_else(|| map_rows_to_columns().next()),
             IdentityDiffsOps::Max => map_rows_to_columns()
                 .filter_map(|x| {
-                    if let ColumnValue::Float(n) = x {
-                        Some(n as i64)
+                    if let ColumnValue::Decimal(n) = x {
+                        Some(n)
                     } else {
                         None
                     }
                 })
                 .max()
-                .map(|x| ColumnValue::Float(x as f64)),
+                .map(ColumnValue::Decimal),
             IdentityDiffsOps::Min => map_rows_to_columns()
                 .filter_map(|x| {
-                    if let ColumnValue::Float(n) = x {
-                        Some(n as i64)
+                    if let ColumnValue::Decimal(n) = x {
+                        Some(n)
                     } else {
                         None
                     }
                 })
                 .min()
-                .map(|x| ColumnValue::Float(x as f64)),
+                .map(ColumnValue::Decimal),
         }
     }
 
@@ -174,7 +190,11 @@ async fn collapse_rows(
             })
             .collect::<HashMap<_, _>>();
         let id = Uuid::new_v4();
-        collapsed_rows.push(Row { id, columns });
+        collapsed_rows.push(Row {
+            id,
+            columns,
+            version: 0,
+        });
         collapsed_rows_ids.insert(id, rows.iter().map(|x| x.id).collect::<Vec<_>>());
     }
     collapsed_rows.sort_rows(priorities);
@@ -192,9 +212,12 @@ pub fn ShowSheet() -> impl IntoView {
     let collapsed_deleted_rows = RwSignal::from(Vec::<Uuid>::new());
     let added_rows = RwSignal::from(Vec::<Row<Uuid, Rc<str>>>::new());
     let modified_columns = RwSignal::from(Vec::<ColumnIdentity>::new());
-    let modified_primary_columns = RwSignal::from(HashMap::<Rc<str>, Column<Rc<str>>>::new());
+    let modified_primary_columns = RwSignal::from(SheetFields::new());
     let deleted_primary_columns = RwSignal::from(Vec::<Rc<str>>::new());
     let on_edit = RwSignal::from(false);
+    let show_history = RwSignal::from(false);
+    let conflicts = RwSignal::from(Vec::<Conflict<Uuid, Rc<str>>>::new());
+    let show_conflicts = RwSignal::from(false);
     let params = use_params_map();
     let sheet_type_id = move || {
         params.with(|params| match params.get("sheet_type_id") {
@@ -216,15 +239,43 @@ pub fn ShowSheet() -> impl IntoView {
         .unwrap_or(Rc::from(""))
     });
 
-    let sheet_priorities_resource = Resource::new(
+    let sheet_priorities_resource = Resource::once(move || async move {
+        let name = invoke::<Id, Rc<str>>(
+            "sheet_type_name",
+            &Id {
+                id: sheet_type_id(),
+            },
+        )
+        .await
+        .ok();
+        invoke::<NameArg, Rc<[Rc<str>]>>("get_priorities", &NameArg { name })
+            .await
+            .unwrap_or(Rc::from([]))
+    });
+
+    let sheet_role_resource = Resource::new(
         move || sheet_type_name_resource.get(),
         move |name| async move {
-            invoke::<NameArg, Rc<[Rc<str>]>>("get_priorities", &NameArg { name })
+            invoke::<NameArg, Option<Role>>("sheet_role", &NameArg { name })
                 .await
-                .unwrap_or(Rc::from([]))
+                .unwrap_or_default()
         },
     );
 
+    let can_edit = move || {
+        sheet_role_resource
+            .get()
+            .flatten()
+            .is_some_and(|role| role >= Role::Editor)
+    };
+
+    let can_delete = move || {
+        sheet_role_resource
+            .get()
+            .flatten()
+            .is_some_and(|role| role == Role::Admin)
+    };
+
     let rows_ids_resource = Resource::new(
         move || sheet_type_name_resource.get(),
         move |name| async move {
@@ -237,6 +288,16 @@ pub fn ShowSheet() -> impl IntoView {
         },
     );
 
+    let strict_formulas_resource = Resource::new(
+        move || sheet_type_name_resource.get(),
+        move |name| async move {
+            invoke::<NameArg, bool>("sheet_strict_formulas", &NameArg { name })
+                .await
+                .unwrap_or_default()
+        },
+    );
+    let strict_formulas = move || strict_formulas_resource.get().unwrap_or_default();
+
     let get_row_identity = move || {
         rows_ids_resource.get().unwrap_or(RowIdentity {
             id: Rc::from(""),
@@ -254,13 +315,34 @@ pub fn ShowSheet() -> impl IntoView {
         })
     };
 
+    let trash_sheet = move |_| {
+        let Some(id) = sheet_id() else {
+            return;
+        };
+        spawn_local(async move {
+            if !confirm("سيتم نقل الشيت الى سلة المحذوفات").await {
+                return;
+            }
+            match invoke::<_, ()>("trash_sheet", &Id { id: Some(id) }).await {
+                Ok(_) => {
+                    let href = window().location().href().unwrap_or_default();
+                    let home = href.split("/show/").next().unwrap_or_default().to_string();
+                    window().location().set_href(&home).unwrap_or_default();
+                }
+                Err(err) => alert(err.to_string().as_str()).await,
+            }
+        });
+    };
+
     let sheet_resource = Resource::once(move || async move {
         invoke::<Id, (Sheet<Uuid, Rc<str>>, i64)>("get_sheet", &Id { id: sheet_id() })
             .await
             .ok()
     });
 
-    let rows_offset = RwSignal::from(0);
+    let rows_cursor = RwSignal::from(None::<String>);
+    let rows_loaded = RwSignal::from(false);
+    let rows_fetched = RwSignal::from(0);
 
     let rows_number = Memo::new(move |_| {
         let default = FETCH_LIMIT * 4;
@@ -280,46 +362,65 @@ pub fn ShowSheet() -> impl IntoView {
     let is_collapsable = move || !get_row_identity().id.is_empty();
 
     let sheet_rows_resource = Resource::new(
-        move || rows_offset.get(),
-        move |offset| async move {
+        move || {
+            if rows_loaded.get() {
+                return None;
+            }
+            sheet_priorities_resource
+                .get()
+                .map(|priorities| (rows_cursor.get(), priorities))
+        },
+        move |args| async move {
             #[derive(Serialize, Deserialize)]
-            struct LimitedId {
+            struct RowsAfter {
                 id: Option<Uuid>,
-                offset: i64,
+                after: Option<String>,
                 limit: i64,
+                order: Vec<(Rc<str>, SortDirection)>,
             }
 
-            let rows_number = rows_number.get();
-            let new_rows = invoke::<_, Vec<Row<Uuid, Rc<str>>>>(
+            let Some((after, priorities)) = args else {
+                return;
+            };
+            let RowsPage {
+                rows: new_rows,
+                next,
+            } = invoke::<_, RowsPage<Uuid, Rc<str>>>(
                 "get_sheet_rows",
-                &LimitedId {
+                &RowsAfter {
                     id: sheet_id(),
-                    offset,
+                    after,
                     limit: FETCH_LIMIT,
+                    order: priorities
+                        .iter()
+                        .map(|header| (header.clone(), SortDirection::Ascending))
+                        .collect(),
                 },
             )
             .await
-            .unwrap_or_default();
-
-            if offset <= rows_number {
-                rows_offset.update(|x| *x += FETCH_LIMIT);
-            } else {
-                let sheet_priorities = sheet_priorities_resource.get().unwrap_or(Rc::from([]));
+            .unwrap_or(RowsPage {
+                rows: Vec::new(),
+                next: None,
+            });
 
-                rows_accumalator.update(|xs| xs.sort_rows(sheet_priorities));
-            }
+            let calls = rows_fetched.get_untracked() / FETCH_LIMIT;
+            rows_fetched.update(|x| *x += new_rows.len() as i64);
             if !new_rows.is_empty() {
-                if offset % RENDER_EVERY_CALLS_NUMBER == 0 || offset <= FETCH_LIMIT {
+                if calls % RENDER_EVERY_CALLS_NUMBER == 0 || next.is_none() {
                     rows_accumalator.update(|xs| xs.extend(new_rows));
                 } else {
                     rows_accumalator.update_untracked(|xs| xs.extend(new_rows));
                 }
             }
+            match next {
+                Some(next) => rows_cursor.set(Some(next)),
+                None => rows_loaded.set(true),
+            }
         },
     );
 
     Effect::new(move |_| {
-        if is_collapsable() && rows_offset.get() > rows_number.get() {
+        if is_collapsable() && rows_loaded.get() {
             let sheet_priorities = sheet_priorities_resource.get().unwrap_or(Rc::from([]));
             let row_identity = get_row_identity();
             spawn_local(async move {
@@ -346,8 +447,7 @@ pub fn ShowSheet() -> impl IntoView {
 
     let get_rendered_rows = move || {
         sheet_rows_resource.get();
-        let offset = rows_offset.get();
-        let rows_number = rows_number.get();
+        let rows_loaded = rows_loaded.get();
 
         match render_mode.get() {
             RenderMode::None => (),
@@ -355,7 +455,7 @@ pub fn ShowSheet() -> impl IntoView {
             RenderMode::Collapse => return rows_collapser.get(),
         }
 
-        if offset <= rows_number {
+        if !rows_loaded {
             return rows_accumalator.get();
         }
 
@@ -401,7 +501,7 @@ pub fn ShowSheet() -> impl IntoView {
             .get()
             .into_iter()
             .filter(|x| match x {
-                ColumnConfig::String(v) | ColumnConfig::Float(v) | ColumnConfig::Date(v) => {
+                ColumnConfig::String(v) | ColumnConfig::Decimal(v) | ColumnConfig::Date(v) => {
                     v.header == header
                 }
             })
@@ -437,53 +537,44 @@ pub fn ShowSheet() -> impl IntoView {
             .map(|x| Rc::from(x.header))
             .collect::<Vec<Rc<str>>>()
     };
-    let sheet_rows_with_primary_row_with_calc_values = Memo::new(move |_| {
-        let sheet_id = get_initial_sheet().map(|x| x.id).unwrap_or_default();
-        let c_cols = calc_columns.get();
-        get_rendered_rows()
+    let calc_order = ordered_calc_columns(calc_columns);
+    let calculated_rows = Memo::new(move |_| {
+        let c_cols = calc_order.get();
+        let strict = strict_formulas();
+        let mut errors = HashMap::new();
+        let rows = get_rendered_rows()
             .into_iter()
-            .map(|Row { id, columns }| Row {
-                id,
-                columns: {
-                    let mut columns = columns;
-                    for header in calc_headers().into_iter() {
-                        let mut map = HashMap::new();
-                        for (col_header, Column { is_basic: _, value }) in &columns {
-                            map.insert(col_header.clone(), value.clone());
-                        }
-                        let value = &c_cols;
-                        let value = value
-                            .iter()
-                            .filter(|x| x.header == header.to_string())
-                            .collect::<Vec<_>>();
-                        let value = value.first().unwrap();
-
-                        if id != sheet_id {
-                            columns.insert(
-                                header,
-                                Column {
-                                    is_basic: false,
-                                    value: ColumnValue::Float(
-                                        resolve_operation(&value.value, &map).unwrap_or_default(),
-                                    ),
-                                },
-                            );
-                        }
-                    }
-                    columns
-                },
+            .map(|row| {
+                let map = row
+                    .columns
+                    .iter()
+                    .map(|(header, column)| (header.clone(), column.value.clone()))
+                    .collect();
+                let (values, row_errors) = calculate_columns(map, &c_cols, strict);
+                let mut columns = row.columns;
+                for header in row_errors.keys() {
+                    columns.remove(header);
+                }
+                if !row_errors.is_empty() {
+                    errors.insert(row.id, row_errors);
+                }
+                for (header, value) in values {
+                    columns.insert(
+                        header,
+                        Column {
+                            is_basic: false,
+                            value,
+                        },
+                    );
+                }
+                Row { columns, ..row }
             })
-            .collect::<Vec<_>>()
-    });
-
-    let sheet_rows_without_primary_row_with_calc_values = Memo::new(move |_| {
-        let sheet_id = get_initial_sheet().map(|x| x.id).unwrap_or_default();
-        sheet_rows_with_primary_row_with_calc_values
-            .get()
-            .into_iter()
-            .filter(|x| x.id != sheet_id)
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+        (rows, errors)
     });
+    let sheet_rows_with_calc_values =
+        Memo::new(move |_| calculated_rows.with(|(rows, _)| rows.clone()));
+    let calc_errors = Memo::new(move |_| calculated_rows.with(|(_, errors)| errors.clone()));
 
     let is_collapsed_id = move |id: &Uuid| rows_collapsed_ids.get().contains_key(id);
 
@@ -528,18 +619,8 @@ pub fn ShowSheet() -> impl IntoView {
         added_rows
             .update(|xs| xs.sort_rows(sheet_priorities_resource.get().unwrap_or(Rc::from([]))));
     };
-    let primary_row_columns = Memo::new(move |_| {
-        let Some(id) = get_initial_sheet().map(|x| x.id) else {
-            return HashMap::new();
-        };
-        get_rendered_rows()
-            .into_iter()
-            .filter(|x| x.id == id)
-            .collect::<Vec<_>>()
-            .first()
-            .map(|x| x.columns.clone())
-            .unwrap_or_default()
-    });
+    let primary_row_columns =
+        Memo::new(move |_| get_initial_sheet().map(|x| x.fields).unwrap_or_default());
 
     let expand_collapsed_id = move |id| rows_collapsed_ids.get().get(&id).cloned();
 
@@ -558,196 +639,13 @@ pub fn ShowSheet() -> impl IntoView {
             .collect::<HashSet<_>>()
     };
 
-    const SAVE_EDITS_TOTAL_TASKS: i32 = 6;
-
-    let save_edits_successes = RwSignal::from(0);
-    let save_edits_dones = RwSignal::from(0);
-
-    let save_edits = move |_| {
-        let Some((sheetid, sheetname)) = get_initial_sheet().map(|x| (x.id, x.sheet_name)) else {
-            return;
-        };
-        {
-            let the_name = sheet_name.get();
-            #[derive(Serialize, Deserialize)]
-            struct Args {
-                name: Name,
-            }
-            spawn_my_local_process(
-                !the_name.is_empty() && the_name != sheetname,
-                "update_sheet_name",
-                Args {
-                    name: Name {
-                        id: sheetid,
-                        the_name,
-                    },
-                },
-                save_edits_successes,
-                save_edits_dones,
-            );
-        }
-        {
-            let rowsids = merge_collapse_and_expanded_deleted_rows();
-            #[derive(Serialize, Deserialize)]
-            struct Args {
-                sheetid: Uuid,
-                rowsids: HashSet<Uuid>,
-            }
-            spawn_my_local_process(
-                !rowsids.is_empty(),
-                "delete_rows_from_sheet",
-                Args { sheetid, rowsids },
-                save_edits_successes,
-                save_edits_dones,
-            );
-        }
-        {
-            let rows = added_rows.get();
-            #[derive(Serialize, Deserialize)]
-            struct Args {
-                sheetid: Uuid,
-                rows: Vec<Row<Uuid, Rc<str>>>,
-            }
-            spawn_my_local_process(
-                !rows.is_empty(),
-                "add_rows_to_sheet",
-                Args { sheetid, rows },
-                save_edits_successes,
-                save_edits_dones,
-            );
-        }
-        let updated_row_primary_columns = modified_primary_columns
-            .get()
-            .into_iter()
-            .filter(|(header, _)| {
-                primary_row_columns
-                    .get()
-                    .iter()
-                    .any(|(old_header, _)| header == old_header)
-            })
-            .map(|(header, column)| (sheetid, header, column.value))
-            .collect::<Vec<_>>();
-        let new_row_primary_columns = modified_primary_columns
-            .get()
-            .into_iter()
-            .filter(|(header, _)| {
-                primary_row_columns
-                    .get()
-                    .iter()
-                    .all(|(old_header, _)| header != old_header)
-            })
-            .map(|(header, column)| (sheetid, header, column.value));
-
-        {
-            let (updated_ids, mut updated_columnsidentifiers): (Vec<_>, Vec<_>) = modified_columns
-                .get()
-                .into_iter()
-                .filter(|ColumnIdentity { row_id, header, .. }| {
-                    rows_accumalator
-                        .get()
-                        .iter()
-                        .filter(|x| x.id == *row_id)
-                        .any(|x| x.columns.keys().any(|x| x == header))
-                })
-                .map(|x| (x.row_id, (x.row_id, x.header, x.value)))
-                .unzip();
-            updated_columnsidentifiers.extend(updated_row_primary_columns);
-            #[derive(Serialize, Deserialize)]
-            struct Args {
-                sheetid: Uuid,
-                columnsidentifiers: Vec<(Uuid, Rc<str>, ColumnValue<Rc<str>>)>,
-            }
-            spawn_my_local_process(
-                !updated_columnsidentifiers.is_empty(),
-                "update_columns",
-                Args {
-                    sheetid,
-                    columnsidentifiers: updated_columnsidentifiers,
-                },
-                save_edits_successes,
-                save_edits_dones,
-            );
-            let new_columnsidentifiers = modified_columns
-                .get()
-                .into_iter()
-                .filter(|ColumnIdentity { row_id, .. }| !updated_ids.contains(row_id))
-                .filter(|ColumnIdentity { row_id, header, .. }| {
-                    rows_accumalator
-                        .get()
-                        .iter()
-                        .filter(|x| x.id == *row_id)
-                        .any(|x| x.columns.keys().any(|x| x != header))
-                })
-                .map(|x| (x.row_id, x.header, x.value))
-                .chain(new_row_primary_columns)
-                .collect::<Vec<_>>();
-            spawn_my_local_process(
-                !new_columnsidentifiers.is_empty(),
-                "save_columns",
-                Args {
-                    sheetid,
-                    columnsidentifiers: new_columnsidentifiers,
-                },
-                save_edits_successes,
-                save_edits_dones,
-            );
-        }
-
-        {
-            let rowsheaders = deleted_primary_columns
-                .get()
-                .into_iter()
-                .map(|x| (sheetid, x))
-                .collect::<Vec<_>>();
-
-            #[derive(Serialize, Deserialize)]
-            struct Args {
-                sheetid: Uuid,
-                rowsheaders: Vec<(Uuid, Rc<str>)>,
-            }
-            spawn_my_local_process(
-                !rowsheaders.is_empty(),
-                "delete_columns",
-                Args {
-                    sheetid,
-                    rowsheaders,
-                },
-                save_edits_successes,
-                save_edits_dones,
-            );
-        }
-    };
-
-    Effect::new(move |_| {
-        if save_edits_successes.get() == SAVE_EDITS_TOTAL_TASKS {
-            spawn_local(async move {
-                message("👍").await;
-                save_edits_successes.set(0);
-            });
-        }
-    });
-
     let patch_changes = move || {
-        let sheet_id = sheet_resource
-            .get()
-            .map(|x| x.map(|x| x.0.id).unwrap_or_default())
-            .unwrap_or_default();
         rows_accumalator.update(|xs| {
             xs.extend(added_rows.get());
             let deleted = merge_collapse_and_expanded_deleted_rows();
             xs.retain(|x| !deleted.contains(&x.id));
             let rows: HashMap<Uuid, Vec<ColumnIdentity>> = {
-                let modified_columns = modified_columns.get().into_iter().chain(
-                    modified_primary_columns
-                        .get()
-                        .into_iter()
-                        .map(|(header, column)| ColumnIdentity {
-                            row_id: sheet_id,
-                            header,
-                            value: column.value,
-                        })
-                        .collect::<Vec<_>>(),
-                );
+                let modified_columns = modified_columns.get();
                 let mut rows: HashMap<Uuid, Vec<ColumnIdentity>> = HashMap::new();
                 for column in modified_columns {
                     let row_id = column.row_id;
@@ -807,16 +705,230 @@ pub fn ShowSheet() -> impl IntoView {
         });
     };
 
-    Effect::new(move |_| {
-        if save_edits_dones.get() == SAVE_EDITS_TOTAL_TASKS {
-            patch_changes();
-            sheet_resource.refetch();
-            rows_updates.update(|xs| *xs = xs.iter().map(|(id, num)| (*id, *num + 1)).collect());
-            revert_all_edits();
-            on_edit.set(false);
-            save_edits_dones.set(0);
+    let finish_edits = move || {
+        patch_changes();
+        sheet_resource.refetch();
+        rows_updates.update(|xs| *xs = xs.iter().map(|(id, num)| (*id, *num + 1)).collect());
+        revert_all_edits();
+        on_edit.set(false);
+    };
+
+    let set_base_version = move |target: &ConflictTarget<Uuid, Rc<str>>, version: i64| match target
+    {
+        ConflictTarget::SheetName | ConflictTarget::Field(_) => sheet_resource.update(|x| {
+            if let Some(Some((sheet, _))) = x {
+                sheet.version = version;
+            }
+        }),
+        ConflictTarget::Row(row_id) | ConflictTarget::Cell { row_id, .. } => rows_accumalator
+            .update(|xs| {
+                xs.iter_mut()
+                    .filter(|x| x.id == *row_id)
+                    .for_each(|x| x.version = version)
+            }),
+    };
+
+    let remove_conflict = move |conflict: &Conflict<Uuid, Rc<str>>| {
+        conflicts.update(|xs| xs.retain(|x| x != conflict));
+        if conflicts.get_untracked().is_empty() {
+            show_conflicts.set(false);
         }
-    });
+    };
+
+    let keep_mine = move |conflict: Conflict<Uuid, Rc<str>>| {
+        set_base_version(&conflict.target, conflict.version);
+        remove_conflict(&conflict);
+    };
+
+    let take_theirs = move |conflict: Conflict<Uuid, Rc<str>>| {
+        set_base_version(&conflict.target, conflict.version);
+        match &conflict.target {
+            ConflictTarget::SheetName => {
+                sheet_name.set(Rc::from(""));
+                if let Some(ColumnValue::String(theirs)) = conflict.theirs.clone() {
+                    sheet_resource.update(|x| {
+                        if let Some(Some((sheet, _))) = x {
+                            sheet.sheet_name = theirs;
+                        }
+                    });
+                }
+            }
+            ConflictTarget::Field(header) => {
+                modified_primary_columns.update(|xs| {
+                    xs.remove(header);
+                });
+                deleted_primary_columns.update(|xs| xs.retain(|x| x != header));
+                sheet_resource.update(|x| {
+                    if let Some(Some((sheet, _))) = x {
+                        match conflict.theirs.clone() {
+                            Some(value) => sheet.fields.insert(header.clone(), value),
+                            None => sheet.fields.remove(header),
+                        };
+                    }
+                });
+            }
+            ConflictTarget::Row(row_id) => {
+                expanded_deleted_rows.update(|xs| xs.retain(|x| x != row_id));
+                collapsed_deleted_rows.update(|xs| xs.retain(|x| x != row_id));
+            }
+            ConflictTarget::Cell { row_id, header } => {
+                modified_columns
+                    .update(|xs| xs.retain(|x| !(x.row_id == *row_id && x.header == *header)));
+                rows_accumalator.update(|xs| {
+                    for row in xs.iter_mut().filter(|x| x.id == *row_id) {
+                        match conflict.theirs.clone() {
+                            Some(value) => {
+                                row.columns.insert(
+                                    header.clone(),
+                                    Column {
+                                        is_basic: true,
+                                        value,
+                                    },
+                                );
+                            }
+                            None => {
+                                row.columns.remove(header);
+                            }
+                        }
+                    }
+                });
+            }
+        }
+        remove_conflict(&conflict);
+    };
+
+    let save_edits = move |_| {
+        let Some((sheetid, sheetname, sheet_version)) =
+            get_initial_sheet().map(|x| (x.id, x.sheet_name, x.version))
+        else {
+            return;
+        };
+        let mut changes = Vec::<SheetChange<Uuid, Rc<str>>>::new();
+
+        let the_name = sheet_name.get();
+        if !the_name.is_empty() && the_name != sheetname {
+            changes.push(SheetChange::Rename(the_name));
+        }
+        changes.extend(
+            merge_collapse_and_expanded_deleted_rows()
+                .into_iter()
+                .map(SheetChange::DeleteRow),
+        );
+        changes.extend(added_rows.get().into_iter().map(SheetChange::AddRow));
+        changes.extend(
+            modified_primary_columns
+                .get()
+                .into_iter()
+                .map(|(header, value)| SheetChange::SetField { header, value }),
+        );
+        changes.extend(
+            deleted_primary_columns
+                .get()
+                .into_iter()
+                .map(SheetChange::DeleteField),
+        );
+
+        let (updated_ids, updated_columns): (Vec<_>, Vec<_>) = modified_columns
+            .get()
+            .into_iter()
+            .filter(|ColumnIdentity { row_id, header, .. }| {
+                rows_accumalator
+                    .get()
+                    .iter()
+                    .filter(|x| x.id == *row_id)
+                    .any(|x| x.columns.keys().any(|x| x == header))
+            })
+            .map(|x| (x.row_id, (x.row_id, x.header, x.value)))
+            .unzip();
+        changes.extend(updated_columns.into_iter().map(|(row_id, header, value)| {
+            SheetChange::UpdateColumn {
+                row_id,
+                header,
+                value,
+            }
+        }));
+        changes.extend(
+            modified_columns
+                .get()
+                .into_iter()
+                .filter(|ColumnIdentity { row_id, .. }| !updated_ids.contains(row_id))
+                .filter(|ColumnIdentity { row_id, header, .. }| {
+                    rows_accumalator
+                        .get()
+                        .iter()
+                        .filter(|x| x.id == *row_id)
+                        .any(|x| x.columns.keys().any(|x| x != header))
+                })
+                .map(|x| SheetChange::AddColumn {
+                    row_id: x.row_id,
+                    header: x.header,
+                    value: x.value,
+                }),
+        );
+
+        let touched_rows = changes
+            .iter()
+            .filter_map(|change| match change {
+                SheetChange::DeleteRow(row_id)
+                | SheetChange::UpdateColumn { row_id, .. }
+                | SheetChange::AddColumn { row_id, .. }
+                | SheetChange::DeleteColumn { row_id, .. } => Some(*row_id),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let rows_versions = rows_accumalator
+            .get()
+            .into_iter()
+            .filter(|x| touched_rows.contains(&x.id))
+            .map(|x| (x.id, x.version))
+            .collect::<Vec<_>>();
+
+        #[derive(Serialize, Deserialize)]
+        struct Args {
+            changeset: SheetChangeset<Uuid, Rc<str>>,
+        }
+        let args = Args {
+            changeset: SheetChangeset {
+                sheet_id: sheetid,
+                sheet_version,
+                rows_versions,
+                changes,
+            },
+        };
+        spawn_local(async move {
+            match invoke::<_, ChangesetResult<Uuid, Rc<str>>>("apply_sheet_changeset", &args).await
+            {
+                Ok(ChangesetResult {
+                    applied: true,
+                    sheet_version,
+                    rows_versions,
+                    ..
+                }) => {
+                    finish_edits();
+                    set_base_version(&ConflictTarget::SheetName, sheet_version);
+                    for (row_id, version) in rows_versions {
+                        set_base_version(&ConflictTarget::Row(row_id), version);
+                    }
+                    message("👍").await;
+                }
+                Ok(ChangesetResult {
+                    conflicts: found, ..
+                }) if !found.is_empty() => {
+                    conflicts.set(found);
+                    show_conflicts.set(true);
+                }
+                Ok(ChangesetResult { errors, .. }) => {
+                    let errors = errors
+                        .into_iter()
+                        .flatten()
+                        .map(|x| x.to_string())
+                        .join("\n");
+                    alert(errors.as_str()).await;
+                }
+                Err(err) => alert(err.to_string().as_str()).await,
+            }
+        });
+    };
 
     let load_file = move || {
         let sheettype = sheet_type_name_resource.get().unwrap_or(Rc::from(""));
@@ -825,32 +937,17 @@ pub fn ShowSheet() -> impl IntoView {
             let Some(filepath) = open_file().await else {
                 return;
             };
-            let sheet_id = get_initial_sheet().map(|x| x.id).unwrap_or_default();
-            let rows = import_sheet_rows(sheet_id, sheettype, filepath).await;
+            let (fields, rows) = import_sheet(sheettype, filepath).await;
 
-            let primary_row = rows.iter().filter(|x| x.id == sheet_id).collect::<Vec<_>>();
-            let primary_row = primary_row
-                .first()
-                .map(|x| x.columns.clone())
-                .unwrap_or_default();
-            let old_primary_row = primary_row_columns.get();
-            for (header, column) in primary_row {
-                if old_primary_row
-                    .get(&header)
-                    .is_some_and(|x| x.value != column.value)
-                {
+            let old_fields = primary_row_columns.get();
+            for (header, value) in fields {
+                if old_fields.get(&header) != Some(&value) {
                     modified_primary_columns.update(|map| {
-                        map.insert(header, column);
+                        map.insert(header, value);
                     })
                 }
             }
-            added_rows.update_untracked(|xs| {
-                xs.extend(
-                    rows.into_iter()
-                        .filter(|x| x.id != sheet_id)
-                        .collect::<Vec<_>>(),
-                )
-            });
+            added_rows.update_untracked(|xs| xs.extend(rows));
             added_rows
                 .update(|xs| xs.sort_rows(sheet_priorities_resource.get().unwrap_or(Rc::from([]))));
         });
@@ -870,6 +967,79 @@ pub fn ShowSheet() -> impl IntoView {
             .collect::<Rc<[_]>>()
     };
 
+    let query = use_query_map();
+    let focused_row =
+        move || query.with(|query| query.get("row").and_then(|id| Uuid::from_str(id).ok()));
+    let focused_row_scrolled = StoredValue::new(false);
+
+    Effect::new(move |_| {
+        let Some(id) = focused_row() else {
+            return;
+        };
+        let rows = sheet_rows_with_calc_values.get();
+        if focused_row_scrolled.get_value() || !rows.iter().any(|x| x.id == id) {
+            return;
+        }
+        if let Some(element) = document().get_element_by_id(&id.to_string()) {
+            element.scroll_into_view();
+            focused_row_scrolled.set_value(true);
+        }
+    });
+
+    let sheet_changed = RwSignal::from(false);
+
+    let on_sheet_event = move |kind: SheetEventKind<Uuid, Rc<str>>| match kind {
+        SheetEventKind::Renamed { name, version } => sheet_resource.update(|x| {
+            if let Some(Some((sheet, _))) = x {
+                sheet.sheet_name = name;
+                sheet.version = version;
+            }
+        }),
+        SheetEventKind::RowsDeleted(ids) => {
+            rows_accumalator.update(|xs| xs.retain(|x| !ids.contains(&x.id)))
+        }
+        SheetEventKind::FieldsUpdated { .. }
+        | SheetEventKind::RowsAdded(_)
+        | SheetEventKind::RowsUpdated(_)
+        | SheetEventKind::Missed => sheet_changed.set(true),
+    };
+
+    let listening = Rc::new(Cell::new(true));
+    on_cleanup({
+        let listening = listening.clone();
+        move || {
+            listening.set(false);
+            spawn_local(async move {
+                invoke::<Non, ()>("unsubscribe_sheet_events", &Non {})
+                    .await
+                    .unwrap_or_default();
+            });
+        }
+    });
+    spawn_local(async move {
+        let Some(id) = sheet_id() else {
+            return;
+        };
+        let Ok(events) = listen::<SheetEvent<Uuid, Rc<str>>>("sheet_event").await else {
+            return;
+        };
+        let mut events = Box::pin(events);
+        if invoke::<Id, ()>("subscribe_sheet_events", &Id { id: Some(id) })
+            .await
+            .is_err()
+        {
+            return;
+        }
+        while let Some(event) = events.next().await {
+            if !listening.get() {
+                break;
+            }
+            if event.payload.sheet_id == id {
+                on_sheet_event(event.payload.kind);
+            }
+        }
+    });
+
     view! {
         <section>
             <EditButtons
@@ -877,6 +1047,13 @@ pub fn ShowSheet() -> impl IntoView {
                 load_file=load_file
                 on_edit=on_edit
             />
+            <SheetHistory sheet_id=sheet_id show=show_history/>
+            <ShowConflicts
+                conflicts=conflicts
+                show=show_conflicts
+                keep_mine=keep_mine
+                take_theirs=take_theirs
+            />
             <Space>
                 <BackArrow n=2/>
                 <ExcelExport
@@ -886,7 +1063,9 @@ pub fn ShowSheet() -> impl IntoView {
                             sheet_name: Rc::from(""),
                             type_name: Rc::from(""),
                             insert_date: NaiveDate::default(),
+                            fields: HashMap::new(),
                             rows: vec![],
+                            version: 0,
                         })
                     }
                     headers=move|| {
@@ -895,12 +1074,26 @@ pub fn ShowSheet() -> impl IntoView {
                             .chain(calc_headers())
                             .collect::<Vec<Rc<str>>>()
                     }
-                    all_rows=move|| sheet_rows_with_primary_row_with_calc_values.get()
+                    all_rows=move|| sheet_rows_with_calc_values.get()
                  />
                 <CollapseIcon render_mode=render_mode is_collapsble=is_collapsable/>
-                <EditIcon on_edit=on_edit has_anything_changed=has_anything_changed revert_all_edits=revert_all_edits/>
-                <SaveIcon has_anything_changed=has_anything_changed save_edits=save_edits/>
+                <HistoryIcon show=show_history/>
+                <Show when=can_delete>
+                    <TrashIcon on_trash=trash_sheet/>
+                </Show>
+                <Show when=can_edit>
+                    <EditIcon on_edit=on_edit has_anything_changed=has_anything_changed revert_all_edits=revert_all_edits/>
+                    <SaveIcon has_anything_changed=has_anything_changed save_edits=save_edits/>
+                </Show>
             </Space>
+            <Show when=move || sheet_changed.get()>
+                <Space>
+                    <p>"تم تعديل الشيت من مستخدم اخر"</p>
+                    <Button on_click=move |_| window().location().reload().unwrap_or_default()>
+                        "اعادة التحميل"
+                    </Button>
+                </Space>
+            </Show>
             <Show
                 when=move || matches!(edit_mode.get(),EditState::Primary)
                 fallback=move || {
@@ -927,24 +1120,27 @@ pub fn ShowSheet() -> impl IntoView {
           edit_mode=edit_mode
         /><br/>
         <Show
-        when=move || rows_offset.get() < rows_number.get()
+        when=move || !rows_loaded.get()
         >
-            <progress max=move || rows_number.get() value=move || rows_offset.get()/>
+            <progress max=move || rows_number.get() value=move || rows_fetched.get()/>
         </Show>
             <Table>
                 <SheetHead basic_headers=basic_headers calc_headers=calc_headers/>
                 <tbody>
                     <ShowRows
                         delete_row=delete_row
+                        can_delete=can_delete
                         basic_headers=basic_headers
                         calc_headers=calc_headers
                         rows_updates=rows_updates
-                        rows=sheet_rows_without_primary_row_with_calc_values
+                        rows=sheet_rows_with_calc_values
+                        calc_errors=calc_errors
                         edit_mode=edit_mode
                         modified_columns=modified_columns
                         get_column_type=get_header_type
                         expand_collapse_id=expand_collapsed_id
                         get_collapse_pattern=get_column_collapse_pattern
+                        focused_row=focused_row
                     />
             <Show
             when=move || !added_rows.get().is_empty()
@@ -956,7 +1152,6 @@ pub fn ShowSheet() -> impl IntoView {
                         basic_headers=basic_headers
                         calc_headers=calc_headers
                         rows=added_rows
-                        sheet_id=move ||get_initial_sheet().map(|x| x.id).unwrap_or_default()
                         priorities=move || sheet_priorities_resource.get().unwrap_or(Rc::from([]))
                         get_column_type=get_header_type
                     />
@@ -968,7 +1163,9 @@ pub fn ShowSheet() -> impl IntoView {
                             calc_headers=calc_headers
                             append=append
                             basic_columns=basic_columns
-                            calc_columns=calc_columns
+                            calc_columns=calc_order
+                            sheet_type_name=move || sheet_type_name_resource.get().unwrap_or(Rc::from(""))
+                            strict_formulas=strict_formulas
                         />
                     </Show>
                 </tbody>
@@ -978,6 +1175,189 @@ pub fn ShowSheet() -> impl IntoView {
     }
 }
 
+#[component]
+fn SheetHistory(
+    sheet_id: impl Fn() -> Option<Uuid> + 'static + Copy,
+    show: RwSignal<bool>,
+) -> impl IntoView {
+    let history_cursor = RwSignal::from(None::<String>);
+    let history_next = RwSignal::from(None::<String>);
+    let history_entries = RwSignal::from(Vec::<AuditEntry<Uuid, Rc<str>>>::new());
+
+    // reopening the history starts again from the newest entries
+    Effect::new(move |_| {
+        if !show.get() {
+            history_cursor.set(None);
+        }
+    });
+
+    let history_resource = Resource::new(
+        move || (show.get(), sheet_id(), history_cursor.get()),
+        move |(show, id, after)| async move {
+            #[derive(Serialize, Deserialize)]
+            struct HistoryAfter {
+                id: Option<Uuid>,
+                after: Option<String>,
+                limit: i64,
+            }
+
+            if !show {
+                return;
+            }
+            let first_page = after.is_none();
+            let HistoryPage { entries, next } = match invoke::<_, HistoryPage<Uuid, Rc<str>>>(
+                "get_sheet_history",
+                &HistoryAfter {
+                    id,
+                    after,
+                    limit: HISTORY_LIMIT,
+                },
+            )
+            .await
+            {
+                Ok(page) => page,
+                Err(err) => {
+                    alert(err.to_string().as_str()).await;
+                    return;
+                }
+            };
+            if first_page {
+                history_entries.set(entries);
+            } else {
+                history_entries.update(|xs| xs.extend(entries));
+            }
+            history_next.set(next);
+        },
+    );
+
+    let action_name = |action: AuditAction| match action {
+        AuditAction::CreateSheet => "انشاء الشيت",
+        AuditAction::RenameSheet => "تغيير اسم الشيت",
+        AuditAction::DeleteSheet => "نقل الى سلة المحذوفات",
+        AuditAction::RestoreSheet => "استعادة من سلة المحذوفات",
+        AuditAction::PurgeSheet => "حذف نهائي",
+        AuditAction::AddRow => "اضافة صف",
+        AuditAction::DeleteRow => "حذف صف",
+        AuditAction::AddColumn => "اضافة قيمة",
+        AuditAction::UpdateColumn => "تعديل قيمة",
+        AuditAction::DeleteColumn => "حذف قيمة",
+        AuditAction::SetField => "تعديل بيانات الشيت",
+        AuditAction::DeleteField => "حذف من بيانات الشيت",
+    };
+
+    let value_string =
+        |value: Option<ColumnValue<Rc<str>>>| value.map(|x| x.to_string()).unwrap_or_default();
+
+    view! {
+        <Modal
+            show=show
+        >
+            <h3>"سجل التعديلات"</h3>
+            <Table>
+                <thead>
+                    <tr>
+                        <th>"الوقت"</th>
+                        <th>"المستخدم"</th>
+                        <th>"العملية"</th>
+                        <th>"العنوان"</th>
+                        <th>"القيمة القديمة"</th>
+                        <th>"القيمة الجديدة"</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <For
+                        each=move || {
+                            history_resource.get();
+                            history_entries.get()
+                        }
+                        key=|entry| entry.id
+                        children=move |entry| view! {
+                            <tr>
+                                <td>{entry.time.format("%Y-%m-%d %H:%M").to_string()}</td>
+                                <td>{entry.username.map(|x| x.to_string()).unwrap_or_default()}</td>
+                                <td>{action_name(entry.action)}</td>
+                                <td>{entry.header.map(|x| x.to_string()).unwrap_or_default()}</td>
+                                <td>{value_string(entry.old_value)}</td>
+                                <td>{value_string(entry.new_value)}</td>
+                            </tr>
+                        }
+                    />
+                </tbody>
+            </Table>
+            <Show when=move || history_next.get().is_some()>
+                <Button on_click=move |_| history_cursor.set(history_next.get())>
+                    "تحميل المزيد"
+                </Button>
+            </Show>
+        </Modal>
+    }
+}
+
+#[component]
+fn ShowConflicts(
+    conflicts: RwSignal<Vec<Conflict<Uuid, Rc<str>>>>,
+    show: RwSignal<bool>,
+    keep_mine: impl Fn(Conflict<Uuid, Rc<str>>) + 'static + Copy,
+    take_theirs: impl Fn(Conflict<Uuid, Rc<str>>) + 'static + Copy,
+) -> impl IntoView {
+    let target_name = |target: &ConflictTarget<Uuid, Rc<str>>| match target {
+        ConflictTarget::SheetName => String::from("اسم الشيت"),
+        ConflictTarget::Field(header) => header.to_string(),
+        ConflictTarget::Row(_) => String::from("حذف صف"),
+        ConflictTarget::Cell { header, .. } => header.to_string(),
+    };
+
+    let value_string = |value: &Option<ColumnValue<Rc<str>>>| {
+        value.as_ref().map(|x| x.to_string()).unwrap_or_default()
+    };
+
+    view! {
+        <Modal
+            show=show
+        >
+            <h3>"تم تعديل الشيت من مستخدم اخر"</h3>
+            <Table>
+                <thead>
+                    <tr>
+                        <th>"العنوان"</th>
+                        <th>"تعديلي"</th>
+                        <th>"القيمة الحالية"</th>
+                        <th></th>
+                        <th></th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <For
+                        each=move || conflicts.get()
+                        key=|conflict| format!("{:?}", conflict.target)
+                        children=move |conflict| {
+                            let mine = conflict.clone();
+                            let theirs = conflict.clone();
+                            view! {
+                                <tr>
+                                    <td>{target_name(&conflict.target)}</td>
+                                    <td>{value_string(&conflict.mine)}</td>
+                                    <td>{value_string(&conflict.theirs)}</td>
+                                    <td>
+                                        <Button on_click=move |_| keep_mine(mine.clone())>
+                                            "احتفظ بتعديلي"
+                                        </Button>
+                                    </td>
+                                    <td>
+                                        <Button on_click=move |_| take_theirs(theirs.clone())>
+                                            "اعتمد قيمتهم"
+                                        </Button>
+                                    </td>
+                                </tr>
+                            }
+                        }
+                    />
+                </tbody>
+            </Table>
+        </Modal>
+    }
+}
+
 #[component]
 fn EditButtons(
     edit_mode: RwSignal<EditState>,
@@ -1011,13 +1391,16 @@ fn ShowRows(
     basic_headers: impl Fn() -> Vec<Rc<str>> + 'static + Copy,
     calc_headers: impl Fn() -> Vec<Rc<str>> + 'static + Copy,
     delete_row: impl Fn(Uuid) + 'static + Copy,
+    can_delete: impl Fn() -> bool + 'static + Copy,
     get_column_type: impl Fn(String) -> Option<ColumnConfig> + 'static + Copy,
     expand_collapse_id: impl Fn(Uuid) -> Option<Vec<Uuid>> + 'static + Copy,
     rows_updates: RwSignal<HashMap<Uuid, i32>>,
     rows: Memo<Vec<Row<Uuid, Rc<str>>>>,
+    calc_errors: Memo<HashMap<Uuid, CalcErrors>>,
     edit_mode: RwSignal<EditState>,
     modified_columns: RwSignal<Vec<ColumnIdentity>>,
     get_collapse_pattern: impl Fn(Rc<str>) -> Option<IdentityDiffsOps> + 'static + Copy,
+    focused_row: impl Fn() -> Option<Uuid> + 'static + Copy,
 ) -> impl IntoView {
     let edit_column = RwSignal::from(None::<ColumnIdentity>);
 
@@ -1051,7 +1434,9 @@ fn ShowRows(
             let on_input = move |ev| {
                 let value = event_target_value(&ev).trim().to_string();
                 let value = match column_value.get() {
-                    ColumnValue::Float(_) => ColumnValue::Float(value.parse().unwrap_or_default()),
+                    ColumnValue::Decimal(_) => {
+                        ColumnValue::Decimal(value.parse().unwrap_or_default())
+                    }
                     ColumnValue::Date(_) => ColumnValue::Date(value.parse().unwrap_or_default()),
                     _ => ColumnValue::String(Rc::from(value)),
                 };
@@ -1072,10 +1457,10 @@ fn ShowRows(
                     })
                 };
                 let push_list = |ids: Vec<Uuid>| {
-                    let len = ids.len() as f64;
+                    let len = BigDecimal::from(ids.len() as u64);
                     let value = match get_collapse_pattern(header.clone()) {
                         Some(IdentityDiffsOps::Sum) => match column_value.get() {
-                            ColumnValue::Float(num) => ColumnValue::Float(num / len),
+                            ColumnValue::Decimal(num) => ColumnValue::Decimal(num / len),
                             _ => column_value.get(),
                         },
                         _ => column_value.get(),
@@ -1098,7 +1483,7 @@ fn ShowRows(
             };
 
             let input_type = move || match column_value.get() {
-                ColumnValue::Float(_) => "number",
+                ColumnValue::Decimal(_) => "number",
                 ColumnValue::Date(_) => "date",
                 _ => "text",
             };
@@ -1156,7 +1541,7 @@ fn ShowRows(
         expand_collapse_id: impl Fn(Uuid) -> Option<Vec<Uuid>> + 'static + Copy,
         get_collapse_pattern: impl Fn(Rc<str>) -> Option<IdentityDiffsOps> + 'static + Copy,
     ) -> impl IntoView {
-        let Row { id, columns } = row;
+        let Row { id, columns, .. } = row;
         let columns = Rc::from(columns);
 
         let original = |header: Rc<str>, columns: Rc<HashMap<Rc<str>, Column<Rc<str>>>>| {
@@ -1174,7 +1559,7 @@ fn ShowRows(
             move |_| {
                 let or: ColumnValue<Rc<str>> = match get_column_type(header.to_string()) {
                     Some(ColumnConfig::String(_)) => ColumnValue::String(Rc::from("empty")),
-                    Some(ColumnConfig::Float(_)) => ColumnValue::Float(0.0),
+                    Some(ColumnConfig::Decimal(_)) => ColumnValue::Decimal(BigDecimal::zero()),
                     Some(ColumnConfig::Date(_)) => ColumnValue::Date(Local::now().date_naive()),
                     _ => ColumnValue::String(Rc::from("EMPTY")),
                 };
@@ -1208,12 +1593,11 @@ fn ShowRows(
                     .get()
                     .into_iter()
                     .filter(|x| ids.contains(&x.row_id) && x.header == header)
-                    .map(|x| match x.value {
-                        ColumnValue::Float(v) => v,
-                        _ => 1.0,
-                    })
-                    .sum::<f64>();
-                if result != 0.0 {
+                    .fold(BigDecimal::zero(), |acc, x| match x.value {
+                        ColumnValue::Decimal(v) => acc + v,
+                        _ => acc + BigDecimal::one(),
+                    });
+                if !result.is_zero() {
                     Some(format!(" > {}", result))
                 } else {
                     None
@@ -1252,17 +1636,28 @@ fn ShowRows(
     fn CalcColumns(
         calc_headers: impl Fn() -> Vec<Rc<str>> + 'static + Copy,
         columns: Rc<HashMap<Rc<str>, Column<Rc<str>>>>,
+        calc_errors: Memo<HashMap<Uuid, CalcErrors>>,
+        id: Uuid,
     ) -> impl IntoView {
         let get_column = {
             let columns = columns.clone();
             move |header: &Rc<str>| columns.get(header).map(|x| x.value.to_string())
         };
+        let get_error = move |header: &Rc<str>| {
+            calc_errors.with(|errors| errors.get(&id).and_then(|x| x.get(header)).cloned())
+        };
         view! {
             <For
                 each=calc_headers
                 key=|key| key.clone()
-                children=move |header| view! {
-                    <td>{get_column(&header)}</td>
+                children=move |header| {
+                    let value = get_column(&header);
+                    view! {
+                        <CalcCell
+                            value=move || value.clone()
+                            error=move || get_error(&header)
+                        />
+                    }
                 }
             />
         }
@@ -1274,9 +1669,11 @@ fn ShowRows(
         edit_mode: RwSignal<EditState>,
         id: Uuid,
         delete_row: impl Fn(Uuid) + 'static + Copy,
+        can_delete: impl Fn() -> bool + 'static + Copy,
     ) -> impl IntoView {
+        let is_modified = move || modified_columns.get().iter().any(|x| x.row_id == id);
         let on_click = move |_| {
-            if modified_columns.get().iter().any(|x| x.row_id == id) {
+            if is_modified() {
                 modified_columns.update(|xs| xs.retain(|x| x.row_id != id))
             } else {
                 delete_row(id)
@@ -1285,7 +1682,7 @@ fn ShowRows(
         view! {
 
             <Show
-                when=move || matches!(edit_mode.get(),EditState::NonePrimary)
+                when=move || matches!(edit_mode.get(),EditState::NonePrimary) && (can_delete() || is_modified())
             >
                 <td>
                     <button on:click=on_click>"XXX"</button>
@@ -1295,11 +1692,18 @@ fn ShowRows(
         }
     }
 
-    let children = move |Row { columns, id }| {
+    let children = move |Row { columns, id, .. }| {
         let columns0 = columns.clone().into_iter().collect();
         let columns = Rc::new(columns);
+        let style = move || {
+            if focused_row() == Some(id) {
+                "background-color: #ffe58f;"
+            } else {
+                ""
+            }
+        };
         view! {
-            <tr>
+            <tr id=id.to_string() style=style>
                 <BasicColumns
                     basic_headers=basic_headers
                     modified_columns=modified_columns
@@ -1314,10 +1718,13 @@ fn ShowRows(
                 <CalcColumns
                     calc_headers=calc_headers
                     columns=columns
+                    calc_errors=calc_errors
+                    id=id
                 />
                 <RowEditor
                     modified_columns=modified_columns
                     delete_row=delete_row
+                    can_delete=can_delete
                     id=id
                     edit_mode=edit_mode
                  />
@@ -1345,8 +1752,8 @@ fn ShowRows(
 fn PrimaryRow(
     primary_headers: impl Fn() -> Rc<[Rc<str>]> + 'static + Copy,
     non_primary_headers: impl Fn() -> Rc<[Rc<str>]> + 'static + Copy,
-    columns: Memo<HashMap<Rc<str>, Column<Rc<str>>>>,
-    new_columns: RwSignal<HashMap<Rc<str>, Column<Rc<str>>>>,
+    columns: Memo<SheetFields>,
+    new_columns: RwSignal<SheetFields>,
     deleted_columns: RwSignal<Vec<Rc<str>>>,
     edit_mode: RwSignal<EditState>,
 ) -> impl IntoView {
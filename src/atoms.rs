@@ -74,6 +74,22 @@ pub fn SaveIcon(
     }
 }
 #[component]
+pub fn TrashIcon(on_trash: impl Fn(MouseEvent) + Copy + 'static) -> impl IntoView {
+    view! {
+        <button on:click=on_trash>
+            <Icon style=ICON_STYLE icon=icondata::AiDeleteFilled/>
+        </button>
+    }
+}
+#[component]
+pub fn HistoryIcon(show: RwSignal<bool>) -> impl IntoView {
+    view! {
+        <button on:click=move |_| show.set(true)>
+            <Icon style=ICON_STYLE icon=icondata::AiHistoryOutlined/>
+        </button>
+    }
+}
+#[component]
 pub fn DownIcon(scroll: impl Fn(MouseEvent) + Copy + 'static) -> impl IntoView {
     view! {
         <Button on_click=scroll>